@@ -0,0 +1,554 @@
+use crate::naive_controller::{Command, NaiveController, NUM_WORD_BYTES};
+
+use std::collections::VecDeque;
+use std::io;
+
+pub enum Policy {
+    // Lower port indices always win
+    FixedPriority,
+    RoundRobin,
+    // Weighted round robin; a port may be granted up to its weight in consecutive commands
+    //  before the grant moves on to the next port with pending requests
+    Weighted(Box<[u32]>),
+    // Requests on ports with a deadline (see `Arbiter::set_deadline`) are served earliest
+    //  deadline first once they're within `slack` cycles of their deadline; everything else
+    //  is served round robin
+    Deadline { slack: u64 },
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PortStats {
+    pub num_reads: u64,
+    pub num_writes: u64,
+    pub total_latency: u64,
+    pub min_latency: Option<u64>,
+    pub max_latency: u64,
+    pub num_deadline_misses: u64,
+}
+
+impl PortStats {
+    pub fn num_commands(&self) -> u64 {
+        self.num_reads + self.num_writes
+    }
+
+    pub fn num_bytes(&self) -> u64 {
+        self.num_commands() * NUM_WORD_BYTES as u64
+    }
+
+    pub fn average_latency(&self) -> Option<f64> {
+        match self.num_commands() {
+            0 => None,
+            num_commands => Some(self.total_latency as f64 / num_commands as f64),
+        }
+    }
+
+    // Bytes per cycle over a window of `num_cycles`
+    pub fn bandwidth(&self, num_cycles: u64) -> f64 {
+        if num_cycles == 0 {
+            return 0.0;
+        }
+
+        self.num_bytes() as f64 / num_cycles as f64
+    }
+
+    fn record(&mut self, command: &Command, latency: u64, missed_deadline: bool) {
+        match command {
+            Command::Write { .. } => self.num_writes += 1,
+            Command::Read { .. } => self.num_reads += 1,
+        }
+        self.total_latency += latency;
+        self.min_latency = Some(self.min_latency.map_or(latency, |x| x.min(latency)));
+        self.max_latency = self.max_latency.max(latency);
+        if missed_deadline {
+            self.num_deadline_misses += 1;
+        }
+    }
+}
+
+struct Request {
+    command: Command,
+    arrival_cycle: u64,
+    deadline_cycle: Option<u64>,
+}
+
+struct Port {
+    requests: VecDeque<Request>,
+    deadline: Option<u64>,
+    stats: PortStats,
+}
+
+impl Port {
+    fn new() -> Port {
+        Port {
+            requests: VecDeque::new(),
+            deadline: None,
+            stats: PortStats::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub port: usize,
+    pub data: Option<u128>,
+    pub latency: u64,
+}
+
+pub struct Arbiter {
+    controller: NaiveController,
+    policy: Policy,
+    ports: Box<[Port]>,
+
+    cycle: u64,
+
+    next_port: usize,
+    num_consecutive_grants: u32,
+}
+
+impl Arbiter {
+    pub fn new(controller: NaiveController, num_ports: usize, policy: Policy) -> Arbiter {
+        assert!(num_ports > 0, "Arbiter must have at least one port.");
+        if let Policy::Weighted(weights) = &policy {
+            assert_eq!(
+                weights.len(),
+                num_ports,
+                "Weighted policy must specify exactly one weight per port."
+            );
+            assert!(
+                weights.iter().all(|&weight| weight > 0),
+                "Weighted policy weights must be nonzero."
+            );
+        }
+
+        Arbiter {
+            controller,
+            policy,
+            ports: (0..num_ports).map(|_| Port::new()).collect(),
+
+            cycle: 0,
+
+            next_port: 0,
+            num_consecutive_grants: 0,
+        }
+    }
+
+    pub fn num_ports(&self) -> usize {
+        self.ports.len()
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    // Every request subsequently submitted on `port` must complete within `deadline` cycles
+    //  of its arrival
+    pub fn set_deadline(&mut self, port: usize, deadline: Option<u64>) {
+        self.ports[port].deadline = deadline;
+    }
+
+    pub fn submit(&mut self, port: usize, command: Command) {
+        let port = &mut self.ports[port];
+        port.requests.push_back(Request {
+            command,
+            arrival_cycle: self.cycle,
+            deadline_cycle: port.deadline.map(|deadline| self.cycle + deadline),
+        });
+    }
+
+    pub fn num_pending(&self, port: usize) -> usize {
+        self.ports[port].requests.len()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.ports.iter().all(|port| port.requests.is_empty())
+    }
+
+    pub fn stats(&self, port: usize) -> &PortStats {
+        &self.ports[port].stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        for port in &mut *self.ports {
+            port.stats = PortStats::default();
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut NaiveController {
+        &mut self.controller
+    }
+
+    pub fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        self.controller.idle(num_cycles)?;
+        self.cycle += num_cycles;
+
+        Ok(())
+    }
+
+    // Grants a single pending request (if any) and executes it to completion
+    pub fn step(&mut self) -> io::Result<Option<Response>> {
+        let port = match self.grant() {
+            Some(port) => port,
+            None => return Ok(None),
+        };
+
+        let request = self.ports[port].requests.pop_front().unwrap();
+        let (data, num_cycles) = self.controller.execute(request.command)?;
+        self.cycle += num_cycles;
+
+        let latency = self.cycle - request.arrival_cycle;
+        let missed_deadline = request
+            .deadline_cycle
+            .is_some_and(|deadline_cycle| self.cycle > deadline_cycle);
+        self.ports[port]
+            .stats
+            .record(&request.command, latency, missed_deadline);
+
+        Ok(Some(Response {
+            port,
+            data,
+            latency,
+        }))
+    }
+
+    // Steps until no requests are pending
+    pub fn drain(&mut self) -> io::Result<Vec<Response>> {
+        let mut responses = Vec::new();
+        while let Some(response) = self.step()? {
+            responses.push(response);
+        }
+
+        Ok(responses)
+    }
+
+    fn grant(&mut self) -> Option<usize> {
+        if self.is_idle() {
+            return None;
+        }
+
+        let port = match &self.policy {
+            Policy::FixedPriority => self.first_pending_from(0),
+            Policy::RoundRobin => self.first_pending_from(self.next_port),
+            Policy::Weighted(weights) => {
+                let current = (self.next_port + self.ports.len() - 1) % self.ports.len();
+                if self.num_consecutive_grants > 0
+                    && self.num_consecutive_grants < weights[current]
+                    && !self.ports[current].requests.is_empty()
+                {
+                    current
+                } else {
+                    self.first_pending_from(self.next_port)
+                }
+            }
+            Policy::Deadline { slack } => self
+                .ports
+                .iter()
+                .enumerate()
+                .filter_map(|(index, port)| {
+                    port.requests
+                        .front()
+                        .and_then(|request| request.deadline_cycle)
+                        .filter(|&deadline_cycle| deadline_cycle <= self.cycle + slack)
+                        .map(|deadline_cycle| (deadline_cycle, index))
+                })
+                .min()
+                .map(|(_, index)| index)
+                .unwrap_or_else(|| self.first_pending_from(self.next_port)),
+        };
+
+        let current = (self.next_port + self.ports.len() - 1) % self.ports.len();
+        if port == current {
+            self.num_consecutive_grants += 1;
+        } else {
+            self.num_consecutive_grants = 1;
+        }
+        self.next_port = (port + 1) % self.ports.len();
+
+        Some(port)
+    }
+
+    fn first_pending_from(&self, start: usize) -> usize {
+        (0..self.ports.len())
+            .map(|offset| (start + offset) % self.ports.len())
+            .find(|&index| !self.ports[index].requests.is_empty())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sdram;
+
+    fn arbiter(trace_file_name_prefix: &str, num_ports: usize, policy: Policy) -> Arbiter {
+        Arbiter::new(
+            NaiveController::new(sdram::Sdram::new(Some(trace_file_name_prefix)).unwrap()),
+            num_ports,
+            policy,
+        )
+    }
+
+    fn granted_ports(responses: &[Response]) -> Vec<usize> {
+        responses.iter().map(|response| response.port).collect()
+    }
+
+    #[test]
+    fn fixed_priority() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__fixed_priority", 3, Policy::FixedPriority);
+
+        for addr in 0..2 {
+            a.controller().execute(Command::Write {
+                addr,
+                data: 0,
+                mask: 0,
+            })?;
+        }
+
+        for port in (0..3).rev() {
+            for addr in 0..2 {
+                a.submit(port, Command::Read { addr });
+            }
+        }
+        a.submit(
+            0,
+            Command::Write {
+                addr: 0,
+                data: 0,
+                mask: 0,
+            },
+        );
+
+        let responses = a.drain()?;
+        assert_eq!(granted_ports(&responses), [0, 0, 0, 1, 1, 2, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_robin() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__round_robin", 3, Policy::RoundRobin);
+
+        for addr in 0..3 {
+            a.submit(
+                0,
+                Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                },
+            );
+        }
+        a.submit(
+            2,
+            Command::Write {
+                addr: 3,
+                data: 0,
+                mask: 0,
+            },
+        );
+        a.submit(
+            1,
+            Command::Write {
+                addr: 4,
+                data: 0,
+                mask: 0,
+            },
+        );
+
+        let responses = a.drain()?;
+        assert_eq!(granted_ports(&responses), [0, 1, 2, 0, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn weighted() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__weighted", 2, Policy::Weighted(vec![3, 1].into()));
+
+        for addr in 0..4 {
+            a.submit(
+                0,
+                Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                },
+            );
+            a.submit(
+                1,
+                Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                },
+            );
+        }
+
+        let responses = a.drain()?;
+        assert_eq!(granted_ports(&responses), [0, 0, 0, 1, 0, 1, 1, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deadline() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__deadline", 2, Policy::Deadline { slack: 40 });
+        a.set_deadline(1, Some(60));
+
+        for addr in 0..4 {
+            a.submit(
+                0,
+                Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                },
+            );
+        }
+        a.submit(
+            1,
+            Command::Write {
+                addr: 4,
+                data: 0,
+                mask: 0,
+            },
+        );
+
+        // The display port isn't urgent yet, so it just takes its turn
+        let responses = a.drain()?;
+        assert_eq!(granted_ports(&responses), [0, 1, 0, 0, 0]);
+        assert_eq!(a.stats(1).num_deadline_misses, 0);
+
+        let mut a = arbiter(
+            "Arbiter__deadline_urgent",
+            2,
+            Policy::Deadline { slack: 40 },
+        );
+        a.set_deadline(1, Some(30));
+
+        for addr in 0..4 {
+            a.submit(
+                0,
+                Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                },
+            );
+        }
+        a.submit(
+            1,
+            Command::Write {
+                addr: 4,
+                data: 0,
+                mask: 0,
+            },
+        );
+
+        // Urgent display requests jump the queue, even ahead of the round robin pointer
+        assert_eq!(a.step()?.unwrap().port, 1);
+        assert_eq!(a.stats(1).num_deadline_misses, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deadline_miss() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__deadline_miss", 2, Policy::RoundRobin);
+        a.set_deadline(1, Some(20));
+
+        a.submit(
+            0,
+            Command::Write {
+                addr: 0,
+                data: 0,
+                mask: 0,
+            },
+        );
+        a.submit(
+            0,
+            Command::Write {
+                addr: 1,
+                data: 0,
+                mask: 0,
+            },
+        );
+        a.submit(
+            1,
+            Command::Write {
+                addr: 2,
+                data: 0,
+                mask: 0,
+            },
+        );
+        a.submit(
+            1,
+            Command::Write {
+                addr: 3,
+                data: 0,
+                mask: 0,
+            },
+        );
+
+        a.drain()?;
+        assert_eq!(a.stats(1).num_writes, 2);
+        assert_eq!(a.stats(1).num_deadline_misses, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stats() -> io::Result<()> {
+        let mut a = arbiter("Arbiter__stats", 2, Policy::RoundRobin);
+
+        let expected_data = 0xfadebabedeadbeefabad1deacafef00d;
+
+        a.submit(
+            0,
+            Command::Write {
+                addr: 0,
+                data: expected_data,
+                mask: 0,
+            },
+        );
+        let write_response = a.step()?.unwrap();
+        assert!(write_response.data.is_none());
+        assert_eq!(write_response.latency, a.cycle());
+
+        a.idle(10)?;
+        a.submit(0, Command::Read { addr: 0 });
+        a.submit(1, Command::Read { addr: 0 });
+        let responses = a.drain()?;
+        for response in &responses {
+            assert_eq!(
+                response.data.expect("No data returned from read command."),
+                expected_data
+            );
+        }
+        // Port 1 is next in line after port 0's write, so port 0's read waits for it
+        assert_eq!(granted_ports(&responses), [1, 0]);
+        assert!(responses[1].latency > responses[0].latency);
+
+        let port_0 = a.stats(0);
+        assert_eq!(port_0.num_writes, 1);
+        assert_eq!(port_0.num_reads, 1);
+        assert_eq!(port_0.num_bytes(), 2 * NUM_WORD_BYTES as u64);
+        assert_eq!(
+            port_0.total_latency,
+            write_response.latency + responses[1].latency
+        );
+        assert_eq!(port_0.min_latency, Some(write_response.latency));
+        assert_eq!(port_0.max_latency, responses[1].latency);
+
+        let port_1 = a.stats(1);
+        assert_eq!(port_1.num_reads, 1);
+        assert_eq!(port_1.average_latency(), Some(responses[0].latency as f64));
+        assert!(port_1.bandwidth(a.cycle()) > 0.0);
+
+        a.reset_stats();
+        assert_eq!(a.stats(0).num_commands(), 0);
+        assert!(a.stats(1).average_latency().is_none());
+
+        Ok(())
+    }
+}
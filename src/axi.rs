@@ -0,0 +1,647 @@
+// AXI4 slave with a 128-bit data bus, so each data beat lines up with one controller word.
+//  Channels are modeled at the transaction level: the master pushes AW/W/AR beats into the
+//  slave's queues and pops B/R beats back out, and `Slave::step` executes one transaction
+//  through the controller, splitting its beats into the 128-bit accesses the controller
+//  performs. Narrow beats which hit the same word are merged into a single access.
+
+use crate::naive_controller::{byte_mask_bits, Command, NaiveController, NUM_WORD_BYTES};
+use crate::sdram;
+
+use std::collections::VecDeque;
+use std::io;
+
+pub const MAX_SIZE: u8 = 4; // log2(NUM_WORD_BYTES)
+pub const NUM_ADDR_BYTES: u32 = (1
+    << (sdram::NUM_BANK_ADDR_BITS + sdram::NUM_ROW_ADDR_BITS + sdram::NUM_COL_ADDR_BITS))
+    * (sdram::NUM_ELEMENT_BITS / 8);
+// Bursts must not cross 4KB address boundaries
+const BOUNDARY_BYTES: u32 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Burst {
+    Fixed,
+    Incr,
+    Wrap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resp {
+    Okay,
+    ExOkay,
+    SlvErr,
+    DecErr,
+}
+
+// AW/AR channel payload
+#[derive(Clone, Copy, Debug)]
+pub struct Address {
+    pub id: u32,
+    // Byte address
+    pub addr: u32,
+    // Number of beats minus one
+    pub len: u8,
+    // log2 of the number of bytes in each beat
+    pub size: u8,
+    pub burst: Burst,
+}
+
+impl Address {
+    pub fn num_beats(&self) -> u32 {
+        self.len as u32 + 1
+    }
+
+    pub fn num_beat_bytes(&self) -> u32 {
+        1 << self.size
+    }
+
+    fn aligned_addr(&self) -> u32 {
+        self.addr & !(self.num_beat_bytes() - 1)
+    }
+
+    fn validate(&self) -> Resp {
+        if self.size > MAX_SIZE {
+            return Resp::SlvErr;
+        }
+
+        match self.burst {
+            Burst::Fixed => {
+                if self.num_beats() > 16 {
+                    return Resp::SlvErr;
+                }
+            }
+            Burst::Incr => {
+                let first = self.addr as u64;
+                let last = self.aligned_addr() as u64
+                    + self.num_beats() as u64 * self.num_beat_bytes() as u64
+                    - 1;
+                if first / BOUNDARY_BYTES as u64 != last / BOUNDARY_BYTES as u64 {
+                    return Resp::SlvErr;
+                }
+            }
+            Burst::Wrap => {
+                if !matches!(self.num_beats(), 2 | 4 | 8 | 16) || self.addr != self.aligned_addr() {
+                    return Resp::SlvErr;
+                }
+            }
+        }
+
+        if (0..self.num_beats()).any(|beat| self.beat_addr(beat) >= NUM_ADDR_BYTES) {
+            return Resp::DecErr;
+        }
+
+        Resp::Okay
+    }
+
+    fn beat_addr(&self, beat: u32) -> u32 {
+        match self.burst {
+            Burst::Fixed => self.addr,
+            Burst::Incr => {
+                if beat == 0 {
+                    self.addr
+                } else {
+                    self.aligned_addr() + beat * self.num_beat_bytes()
+                }
+            }
+            Burst::Wrap => {
+                let container_bytes = self.num_beats() * self.num_beat_bytes();
+                let lower = self.addr & !(container_bytes - 1);
+                lower + (self.addr - lower + beat * self.num_beat_bytes()) % container_bytes
+            }
+        }
+    }
+
+    // Byte lanes which carry valid data for `beat`. For an unaligned first beat this excludes
+    //  the lanes below the start address.
+    fn beat_lanes(&self, beat: u32) -> u16 {
+        let addr = self.beat_addr(beat);
+        let lower = addr % NUM_WORD_BYTES;
+        let upper = (addr & !(self.num_beat_bytes() - 1)) % NUM_WORD_BYTES + self.num_beat_bytes();
+        (((1u32 << upper) - 1) & !((1u32 << lower) - 1)) as u16
+    }
+}
+
+// W channel payload
+#[derive(Clone, Copy, Debug)]
+pub struct WriteData {
+    pub data: u128,
+    pub strb: u16,
+    pub last: bool,
+}
+
+// B channel payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteResponse {
+    pub id: u32,
+    pub resp: Resp,
+}
+
+// R channel payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadData {
+    pub id: u32,
+    pub data: u128,
+    pub resp: Resp,
+    pub last: bool,
+}
+
+enum Transaction {
+    Write(Address),
+    Read(Address),
+}
+
+pub struct Slave {
+    controller: NaiveController,
+    max_outstanding: usize,
+
+    transactions: VecDeque<Transaction>,
+    w: VecDeque<WriteData>,
+    b: VecDeque<WriteResponse>,
+    r: VecDeque<ReadData>,
+
+    num_cycles: u64,
+}
+
+impl Slave {
+    pub fn new(controller: NaiveController, max_outstanding: usize) -> Slave {
+        assert!(
+            max_outstanding > 0,
+            "Slave must accept at least one outstanding transaction."
+        );
+
+        Slave {
+            controller,
+            max_outstanding,
+
+            transactions: VecDeque::new(),
+            w: VecDeque::new(),
+            b: VecDeque::new(),
+            r: VecDeque::new(),
+
+            num_cycles: 0,
+        }
+    }
+
+    pub fn num_cycles(&self) -> u64 {
+        self.num_cycles
+    }
+
+    pub fn controller(&mut self) -> &mut NaiveController {
+        &mut self.controller
+    }
+
+    // AWREADY/ARREADY
+    pub fn address_ready(&self) -> bool {
+        self.transactions.len() < self.max_outstanding
+    }
+
+    pub fn push_aw(&mut self, aw: Address) -> bool {
+        if !self.address_ready() {
+            return false;
+        }
+
+        self.transactions.push_back(Transaction::Write(aw));
+        true
+    }
+
+    pub fn push_w(&mut self, w: WriteData) {
+        self.w.push_back(w);
+    }
+
+    pub fn push_ar(&mut self, ar: Address) -> bool {
+        if !self.address_ready() {
+            return false;
+        }
+
+        self.transactions.push_back(Transaction::Read(ar));
+        true
+    }
+
+    pub fn pop_b(&mut self) -> Option<WriteResponse> {
+        self.b.pop_front()
+    }
+
+    pub fn pop_r(&mut self) -> Option<ReadData> {
+        self.r.pop_front()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    // Executes the oldest transaction that can make progress. Reads may pass writes which are
+    //  still waiting for their data, as the two channels are unordered with respect to each
+    //  other.
+    pub fn step(&mut self) -> io::Result<bool> {
+        let has_complete_write_data = self.w.iter().any(|w| w.last);
+        let index = match self
+            .transactions
+            .iter()
+            .position(|transaction| match transaction {
+                Transaction::Write(_) => has_complete_write_data,
+                Transaction::Read(_) => true,
+            }) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+
+        match self.transactions.remove(index).unwrap() {
+            Transaction::Write(aw) => self.write(aw)?,
+            Transaction::Read(ar) => self.read(ar)?,
+        }
+
+        Ok(true)
+    }
+
+    // Steps until no transaction can make progress
+    pub fn drain(&mut self) -> io::Result<()> {
+        while self.step()? {}
+
+        Ok(())
+    }
+
+    fn write(&mut self, aw: Address) -> io::Result<()> {
+        let mut beats = Vec::new();
+        while let Some(w) = self.w.pop_front() {
+            beats.push(w);
+            if w.last {
+                break;
+            }
+        }
+
+        let mut resp = aw.validate();
+        if beats.len() != aw.num_beats() as usize {
+            resp = Resp::SlvErr;
+        }
+
+        if resp == Resp::Okay {
+            // (word address, data, strobes) of the access being merged
+            let mut pending: Option<(u32, u128, u16)> = None;
+            for (beat, w) in beats.iter().enumerate() {
+                let word_addr = aw.beat_addr(beat as _) / NUM_WORD_BYTES;
+                let strb = w.strb & aw.beat_lanes(beat as _);
+                let bits = byte_mask_bits(strb);
+
+                pending = match pending {
+                    Some((addr, data, pending_strb)) if addr == word_addr => {
+                        Some((addr, (data & !bits) | (w.data & bits), pending_strb | strb))
+                    }
+                    _ => {
+                        if let Some((addr, data, pending_strb)) = pending {
+                            self.write_word(addr, data, pending_strb)?;
+                        }
+                        Some((word_addr, w.data & bits, strb))
+                    }
+                };
+            }
+            if let Some((addr, data, strb)) = pending {
+                self.write_word(addr, data, strb)?;
+            }
+        }
+
+        self.b.push_back(WriteResponse { id: aw.id, resp });
+
+        Ok(())
+    }
+
+    fn write_word(&mut self, addr: u32, data: u128, strb: u16) -> io::Result<()> {
+        // Beats with no strobes set don't need to touch memory at all
+        if strb == 0 {
+            return Ok(());
+        }
+
+        let (_, num_cycles) = self.controller.execute(Command::Write {
+            addr,
+            data,
+            mask: !strb,
+        })?;
+        self.num_cycles += num_cycles;
+
+        Ok(())
+    }
+
+    fn read(&mut self, ar: Address) -> io::Result<()> {
+        let resp = ar.validate();
+
+        let mut word: Option<(u32, u128)> = None;
+        for beat in 0..ar.num_beats() {
+            let data = if resp == Resp::Okay {
+                let word_addr = ar.beat_addr(beat) / NUM_WORD_BYTES;
+                match word {
+                    Some((addr, data)) if addr == word_addr => data,
+                    _ => {
+                        let (data, num_cycles) =
+                            self.controller.execute(Command::Read { addr: word_addr })?;
+                        self.num_cycles += num_cycles;
+                        let data = data.unwrap();
+                        word = Some((word_addr, data));
+                        data
+                    }
+                }
+            } else {
+                0
+            };
+
+            self.r.push_back(ReadData {
+                id: ar.id,
+                data,
+                resp,
+                last: beat == ar.len as u32,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slave(trace_file_name_prefix: &str, max_outstanding: usize) -> Slave {
+        Slave::new(
+            NaiveController::new(sdram::Sdram::new(Some(trace_file_name_prefix)).unwrap()),
+            max_outstanding,
+        )
+    }
+
+    fn write(s: &mut Slave, aw: Address, beats: &[(u128, u16)]) -> io::Result<WriteResponse> {
+        assert!(s.push_aw(aw));
+        for (i, &(data, strb)) in beats.iter().enumerate() {
+            s.push_w(WriteData {
+                data,
+                strb,
+                last: i == beats.len() - 1,
+            });
+        }
+        s.drain()?;
+
+        Ok(s.pop_b().unwrap())
+    }
+
+    fn read(s: &mut Slave, ar: Address) -> io::Result<Vec<ReadData>> {
+        assert!(s.push_ar(ar));
+        s.drain()?;
+
+        let mut beats = Vec::new();
+        while let Some(r) = s.pop_r() {
+            beats.push(r);
+        }
+
+        Ok(beats)
+    }
+
+    fn incr(id: u32, addr: u32, len: u8, size: u8) -> Address {
+        Address {
+            id,
+            addr,
+            len,
+            size,
+            burst: Burst::Incr,
+        }
+    }
+
+    #[test]
+    fn incr_full_width() -> io::Result<()> {
+        let mut s = slave("Axi__incr_full_width", 4);
+
+        let beats = (0..4)
+            .map(|i| (0xfadebabedeadbeefabad1deacafe0000 | i, !0))
+            .collect::<Vec<_>>();
+        let b = write(&mut s, incr(3, 0x40, 3, 4), &beats)?;
+        assert_eq!(
+            b,
+            WriteResponse {
+                id: 3,
+                resp: Resp::Okay
+            }
+        );
+
+        let r = read(&mut s, incr(5, 0x40, 3, 4))?;
+        assert_eq!(r.len(), 4);
+        for (i, r) in r.iter().enumerate() {
+            assert_eq!(r.id, 5);
+            assert_eq!(r.resp, Resp::Okay);
+            assert_eq!(r.data, beats[i].0);
+            assert_eq!(r.last, i == 3);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn narrow_unaligned_incr() -> io::Result<()> {
+        let mut s = slave("Axi__narrow_unaligned_incr", 4);
+
+        write(&mut s, incr(0, 0x00, 1, 4), &[(0, !0), (0, !0)])?;
+        let num_init_cycles = s.num_cycles();
+
+        // 32-bit beats starting halfway into the first 32-bit lane; the strobes for the
+        //  bytes below the start address are deliberately left on, and must be ignored
+        let beats = (0..8u32)
+            .map(|i| {
+                let lane = (0x02 + i * 4) % NUM_WORD_BYTES / 4;
+                (
+                    (0x11111111u128 * (i + 1) as u128) << (lane * 32),
+                    0x000fu16 << (lane * 4),
+                )
+            })
+            .collect::<Vec<_>>();
+        let b = write(&mut s, incr(0, 0x02, 7, 2), &beats)?;
+        assert_eq!(b.resp, Resp::Okay);
+        // 8 narrow beats spanning 2 words are merged into 2 accesses
+        assert_eq!(s.num_cycles() - num_init_cycles, num_init_cycles);
+
+        let r = read(&mut s, incr(0, 0x00, 1, 4))?;
+        assert_eq!(r[0].data, 0x44444444333333332222222211110000);
+        assert_eq!(r[1].data, 0x88888888777777776666666655555555);
+
+        // Narrow reads return the whole bus word; each beat reads from its own lanes
+        let r = read(&mut s, incr(0, 0x0c, 1, 2))?;
+        assert_eq!(r[0].data, 0x44444444333333332222222211110000);
+        assert_eq!(r[1].data, 0x88888888777777776666666655555555);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrap() -> io::Result<()> {
+        let mut s = slave("Axi__wrap", 4);
+
+        let aw = Address {
+            id: 0,
+            addr: 0x20,
+            len: 3,
+            size: 4,
+            burst: Burst::Wrap,
+        };
+        assert_eq!(
+            (0..4).map(|beat| aw.beat_addr(beat)).collect::<Vec<_>>(),
+            [0x20, 0x30, 0x00, 0x10]
+        );
+        let b = write(&mut s, aw, &(0..4).map(|i| (i, !0)).collect::<Vec<_>>())?;
+        assert_eq!(b.resp, Resp::Okay);
+
+        let r = read(&mut s, incr(0, 0x00, 3, 4))?;
+        assert_eq!(r.iter().map(|r| r.data).collect::<Vec<_>>(), [2, 3, 0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fixed() -> io::Result<()> {
+        let mut s = slave("Axi__fixed", 4);
+
+        write(&mut s, incr(0, 0x00, 0, 4), &[(0, !0)])?;
+
+        // Every beat hits the same lanes, so the last one wins
+        let aw = Address {
+            id: 0,
+            addr: 0x04,
+            len: 2,
+            size: 2,
+            burst: Burst::Fixed,
+        };
+        let b = write(
+            &mut s,
+            aw,
+            &[
+                (0xaaaa << 32, 0x00f0),
+                (0xbbbb << 32, 0x00f0),
+                (0xcccc << 32, 0x0030),
+            ],
+        )?;
+        assert_eq!(b.resp, Resp::Okay);
+
+        let r = read(&mut s, incr(0, 0x00, 0, 4))?;
+        assert_eq!(r[0].data, 0xcccc << 32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wstrb() -> io::Result<()> {
+        let mut s = slave("Axi__wstrb", 4);
+
+        write(
+            &mut s,
+            incr(0, 0x00, 0, 4),
+            &[(0xfadebabedeadbeefabad1deacafef00d, !0)],
+        )?;
+        write(
+            &mut s,
+            incr(0, 0x00, 0, 4),
+            &[(0x0123456789abcdef0123456789abcdef, 0x8001)],
+        )?;
+        // No strobes at all means no access
+        let num_cycles = s.num_cycles();
+        write(&mut s, incr(0, 0x00, 0, 4), &[(0, 0)])?;
+        assert_eq!(s.num_cycles(), num_cycles);
+
+        let r = read(&mut s, incr(0, 0x00, 0, 4))?;
+        assert_eq!(r[0].data, 0x01debabedeadbeefabad1deacafef0ef);
+
+        Ok(())
+    }
+
+    #[test]
+    fn outstanding_ids() -> io::Result<()> {
+        let mut s = slave("Axi__outstanding_ids", 3);
+
+        write(
+            &mut s,
+            incr(0, 0x00, 3, 4),
+            &[(0, !0), (1, !0), (2, !0), (3, !0)],
+        )?;
+
+        // A write waiting on its data doesn't block reads behind it
+        assert!(s.push_aw(incr(7, 0x00, 0, 4)));
+        assert!(s.push_ar(incr(1, 0x10, 0, 4)));
+        assert!(s.push_ar(incr(2, 0x20, 1, 4)));
+        assert!(!s.address_ready());
+        assert!(!s.push_ar(incr(3, 0x00, 0, 4)));
+        s.drain()?;
+        assert!(!s.is_idle());
+        assert!(s.pop_b().is_none());
+        assert_eq!(
+            s.pop_r(),
+            Some(ReadData {
+                id: 1,
+                data: 1,
+                resp: Resp::Okay,
+                last: true
+            })
+        );
+        assert_eq!(
+            s.pop_r(),
+            Some(ReadData {
+                id: 2,
+                data: 2,
+                resp: Resp::Okay,
+                last: false
+            })
+        );
+        assert_eq!(
+            s.pop_r(),
+            Some(ReadData {
+                id: 2,
+                data: 3,
+                resp: Resp::Okay,
+                last: true
+            })
+        );
+
+        s.push_w(WriteData {
+            data: 0xff,
+            strb: !0,
+            last: true,
+        });
+        s.drain()?;
+        assert!(s.is_idle());
+        assert_eq!(
+            s.pop_b(),
+            Some(WriteResponse {
+                id: 7,
+                resp: Resp::Okay
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> io::Result<()> {
+        let mut s = slave("Axi__errors", 4);
+
+        // Out of range
+        let b = write(&mut s, incr(0, NUM_ADDR_BYTES, 0, 4), &[(0, !0)])?;
+        assert_eq!(b.resp, Resp::DecErr);
+        let r = read(&mut s, incr(1, NUM_ADDR_BYTES, 1, 4))?;
+        assert!(r.iter().all(|r| r.resp == Resp::DecErr && r.id == 1));
+        assert!(r[1].last);
+
+        // Illegal wrap length
+        let ar = Address {
+            id: 0,
+            addr: 0x00,
+            len: 2,
+            size: 4,
+            burst: Burst::Wrap,
+        };
+        let r = read(&mut s, ar)?;
+        assert_eq!(r.len(), 3);
+        assert!(r.iter().all(|r| r.resp == Resp::SlvErr));
+
+        // Crossing a 4KB boundary
+        let r = read(&mut s, incr(0, 0x0ff0, 1, 4))?;
+        assert!(r.iter().all(|r| r.resp == Resp::SlvErr));
+
+        // WLAST too early
+        let b = write(&mut s, incr(0, 0x00, 1, 4), &[(0, !0)])?;
+        assert_eq!(b.resp, Resp::SlvErr);
+
+        // Errors never reach memory
+        assert_eq!(s.num_cycles(), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use dramatic::controller::Controller;
+use dramatic::naive_controller::NaiveController;
+use dramatic::sdram;
+use dramatic::workload;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+const USAGE: &str = "Usage: dramatic-run <trace> [--format native|ramulator|dramsim] \
+    [--controller naive] [--trace <prefix>] [--coverage]";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn controller(name: &str, sdram: sdram::Sdram) -> Box<dyn Controller> {
+    match name {
+        "naive" => Box::new(NaiveController::new(sdram)),
+        _ => usage(),
+    }
+}
+
+fn run(args: &[String]) -> io::Result<bool> {
+    let mut path = None;
+    let mut format = None;
+    let mut controller_name = "naive";
+    let mut trace_file_name_prefix = None;
+    let mut collect_coverage = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = Some(
+                    workload::Format::from_name(args.next().unwrap_or_else(|| usage()))
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--controller" => controller_name = args.next().unwrap_or_else(|| usage()),
+            "--trace" => trace_file_name_prefix = Some(args.next().unwrap_or_else(|| usage())),
+            "--coverage" => collect_coverage = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let file = fs::File::open(path)?;
+    let accesses = workload::parse(io::BufReader::new(file), format)?;
+
+    let mut sdram = sdram::Sdram::new(trace_file_name_prefix.map(|prefix| prefix.as_str()))?;
+    sdram.set_panic_on_violation(false);
+    sdram.set_collect_coverage(collect_coverage);
+    let mut controller = controller(controller_name, sdram);
+
+    let report = workload::replay(controller.as_mut(), accesses)?;
+    for violation in &report.violations {
+        println!("{}", violation);
+    }
+    for mismatch in &report.mismatches {
+        println!("{}", mismatch);
+    }
+    println!("{}", report);
+    if let Some(coverage) = controller.sdram().coverage() {
+        println!("{}", coverage);
+    }
+
+    Ok(report.violations.is_empty() && report.mismatches.is_empty())
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::naive_controller::Command;
+#[cfg(test)]
+use crate::naive_controller::NaiveController;
+use crate::sdram;
+
+use std::io;
+
+// Anything which executes word-level commands against an `Sdram`, one at a time, so that
+//  workloads can be replayed through whichever controller is selected
+pub trait Controller {
+    fn sdram(&mut self) -> &mut sdram::Sdram;
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()>;
+
+    // Returns the read data (for reads) and the number of cycles the command took
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)>;
+}
+
+// The data a read of word `addr` returned, for callers which can't go on without it
+pub fn read_data(addr: u32, data: Option<u128>) -> io::Result<u128> {
+    data.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Read of word 0x{:06x} returned no data.", addr),
+        )
+    })
+}
+
+impl<C: Controller + ?Sized> Controller for Box<C> {
+    fn sdram(&mut self) -> &mut sdram::Sdram {
+        (**self).sdram()
+    }
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        (**self).idle(num_cycles)
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
+        (**self).execute(command)
+    }
+}
+
+#[cfg(test)]
+type ExecuteHook = Box<dyn FnMut(&mut NaiveController, Command) -> io::Result<(Option<u128>, u64)>>;
+
+// Runs every command through the hook, which calls `execute` on the `NaiveController` itself, so
+//  it can change the command, the result, or the SDRAM's cells before and after. For tests which
+//  need a misbehaving controller.
+#[cfg(test)]
+pub struct Hooked {
+    controller: NaiveController,
+    hook: ExecuteHook,
+}
+
+#[cfg(test)]
+impl Hooked {
+    pub fn new(
+        sdram: sdram::Sdram,
+        hook: impl FnMut(&mut NaiveController, Command) -> io::Result<(Option<u128>, u64)> + 'static,
+    ) -> Hooked {
+        Hooked {
+            controller: NaiveController::new(sdram),
+            hook: Box::new(hook),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Controller for Hooked {
+    fn sdram(&mut self) -> &mut sdram::Sdram {
+        self.controller.sdram()
+    }
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        self.controller.idle(num_cycles)
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
+        (self.hook)(&mut self.controller, command)
+    }
+}
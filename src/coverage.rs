@@ -0,0 +1,612 @@
+// Functional coverage of the commands an `Sdram` has seen, as collected when enabled with
+//  `Sdram::set_collect_coverage`: which command followed which in each bank, how often each
+//  minimum delay was met exactly (the corner cases controllers tend to get wrong), which rows were
+//  activated, and which CAS latencies, burst lengths and burst types were loaded into the mode
+//  register. The `Display` impl reports what's still unexercised.
+
+use crate::sdram::{self, IoBank, ViolationKind, A_10_MASK, NUM_BANKS, NUM_ROWS};
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Commands as seen by a single bank. PRECHARGE with A10 set, AUTO REFRESH, self refresh entry and
+//  LOAD MODE REGISTER are seen by every bank.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Active,
+    Read,
+    ReadAutoPrecharge,
+    Write,
+    WriteAutoPrecharge,
+    Precharge,
+    PrechargeAll,
+    AutoRefresh,
+    SelfRefresh,
+    LoadModeRegister,
+}
+
+impl Command {
+    pub const ALL: [Command; 10] = [
+        Command::Active,
+        Command::Read,
+        Command::ReadAutoPrecharge,
+        Command::Write,
+        Command::WriteAutoPrecharge,
+        Command::Precharge,
+        Command::PrechargeAll,
+        Command::AutoRefresh,
+        Command::SelfRefresh,
+        Command::LoadModeRegister,
+    ];
+
+    // Tells the commands which share an `sdram::Command` apart by A10 and CKE. Returns `None`
+    //  for NOP.
+    pub fn decode(command: sdram::Command, a: u16, cke: bool) -> Option<Command> {
+        let is_auto_precharge = (a & A_10_MASK as u16) != 0;
+        Some(match command {
+            sdram::Command::Nop => return None,
+            sdram::Command::Active => Command::Active,
+            sdram::Command::AutoRefresh if cke => Command::AutoRefresh,
+            sdram::Command::AutoRefresh => Command::SelfRefresh,
+            sdram::Command::LoadModeRegister => Command::LoadModeRegister,
+            sdram::Command::Precharge if is_auto_precharge => Command::PrechargeAll,
+            sdram::Command::Precharge => Command::Precharge,
+            sdram::Command::Read if is_auto_precharge => Command::ReadAutoPrecharge,
+            sdram::Command::Read => Command::Read,
+            sdram::Command::Write if is_auto_precharge => Command::WriteAutoPrecharge,
+            sdram::Command::Write => Command::Write,
+        })
+    }
+
+    // As used by DRAMPower, except for MRS, which it doesn't have
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Active => "ACT",
+            Command::Read => "RD",
+            Command::ReadAutoPrecharge => "RDA",
+            Command::Write => "WR",
+            Command::WriteAutoPrecharge => "WRA",
+            Command::Precharge => "PRE",
+            Command::PrechargeAll => "PREA",
+            Command::AutoRefresh => "REF",
+            Command::SelfRefresh => "SREN",
+            Command::LoadModeRegister => "MRS",
+        }
+    }
+
+    // Whether every bank sees this command, rather than the one its BA pins select
+    pub fn is_all_banks(&self) -> bool {
+        matches!(
+            self,
+            Command::PrechargeAll
+                | Command::AutoRefresh
+                | Command::SelfRefresh
+                | Command::LoadModeRegister
+        )
+    }
+
+    fn index(&self) -> usize {
+        Command::ALL
+            .iter()
+            .position(|command| command == self)
+            .unwrap()
+    }
+
+    fn needs_active_row(&self) -> bool {
+        matches!(
+            self,
+            Command::Read
+                | Command::ReadAutoPrecharge
+                | Command::Write
+                | Command::WriteAutoPrecharge
+        )
+    }
+
+    fn leaves_active_row(&self) -> bool {
+        matches!(self, Command::Active | Command::Read | Command::Write)
+    }
+
+    // Whether `next` can legally follow this command in the same bank. Precharging a bank
+    //  without an active row is a NOP, so it's always legal.
+    pub fn can_precede(&self, next: Command) -> bool {
+        match next {
+            Command::Precharge | Command::PrechargeAll => true,
+            _ => self.leaves_active_row() == next.needs_active_row(),
+        }
+    }
+}
+
+const NUM_COMMANDS: usize = Command::ALL.len();
+
+// The CAS latencies the mode register can select. The others are reserved.
+pub const CAS_LATENCIES: [u32; 2] = [2, 3];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurstLength {
+    One,
+    Two,
+    Four,
+    Eight,
+    FullPage,
+}
+
+impl BurstLength {
+    pub const ALL: [BurstLength; 5] = [
+        BurstLength::One,
+        BurstLength::Two,
+        BurstLength::Four,
+        BurstLength::Eight,
+        BurstLength::FullPage,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BurstLength::One => "1",
+            BurstLength::Two => "2",
+            BurstLength::Four => "4",
+            BurstLength::Eight => "8",
+            BurstLength::FullPage => "full page",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurstType {
+    Sequential,
+    Interleaved,
+}
+
+impl BurstType {
+    pub const ALL: [BurstType; 2] = [BurstType::Sequential, BurstType::Interleaved];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BurstType::Sequential => "sequential",
+            BurstType::Interleaved => "interleaved",
+        }
+    }
+}
+
+// The fields of a mode register value which coverage is collected for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mode {
+    pub cas_latency: u32,
+    pub burst_length: BurstLength,
+    pub burst_type: BurstType,
+}
+
+impl Mode {
+    // A0-A2 are the burst length, A3 the burst type and A4-A6 the CAS latency. Returns `None` for
+    //  reserved values, including full page interleaved bursts.
+    pub fn decode(value: u16) -> Option<Mode> {
+        let burst_length = match value & 0x7 {
+            0 => BurstLength::One,
+            1 => BurstLength::Two,
+            2 => BurstLength::Four,
+            3 => BurstLength::Eight,
+            7 => BurstLength::FullPage,
+            _ => return None,
+        };
+        let burst_type = if (value >> 3) & 1 == 0 {
+            BurstType::Sequential
+        } else {
+            BurstType::Interleaved
+        };
+        let cas_latency = ((value >> 4) & 0x7) as u32;
+        if !CAS_LATENCIES.contains(&cas_latency)
+            || (burst_length, burst_type) == (BurstLength::FullPage, BurstType::Interleaved)
+        {
+            return None;
+        }
+
+        Some(Mode {
+            cas_latency,
+            burst_length,
+            burst_type,
+        })
+    }
+}
+
+pub struct Coverage {
+    // Indexed by bank, then the earlier and later command
+    transitions: [[[u64; NUM_COMMANDS]; NUM_COMMANDS]; NUM_BANKS as usize],
+    last_commands: [Option<Command>; NUM_BANKS as usize],
+
+    // How many times each minimum delay was met exactly, in the order the rules are checked
+    min_delay_hits: Vec<(ViolationKind, u64)>,
+
+    activated_rows: Box<[Box<[bool]>]>,
+    num_activated_rows: [u32; NUM_BANKS as usize],
+
+    mode_registers: BTreeSet<u16>,
+}
+
+impl Coverage {
+    pub fn new(min_delay_kinds: impl IntoIterator<Item = ViolationKind>) -> Coverage {
+        Coverage {
+            transitions: [[[0; NUM_COMMANDS]; NUM_COMMANDS]; NUM_BANKS as usize],
+            last_commands: [None; NUM_BANKS as usize],
+
+            min_delay_hits: min_delay_kinds.into_iter().map(|kind| (kind, 0)).collect(),
+
+            activated_rows: (0..NUM_BANKS)
+                .map(|_| vec![false; NUM_ROWS as usize].into())
+                .collect(),
+            num_activated_rows: [0; NUM_BANKS as usize],
+
+            mode_registers: BTreeSet::new(),
+        }
+    }
+
+    pub fn record_command(&mut self, bank: IoBank, command: Command) {
+        let index = bank.index();
+        if let Some(last_command) = self.last_commands[index] {
+            self.transitions[index][last_command.index()][command.index()] += 1;
+        }
+        self.last_commands[index] = Some(command);
+    }
+
+    pub fn record_active(&mut self, bank: IoBank, row_addr: u32) {
+        let is_activated = &mut self.activated_rows[bank.index()][row_addr as usize];
+        if !*is_activated {
+            *is_activated = true;
+            self.num_activated_rows[bank.index()] += 1;
+        }
+    }
+
+    // `index` is the rule's position in `min_delay_hits`
+    pub fn record_min_delay_hit(&mut self, index: usize) {
+        self.min_delay_hits[index].1 += 1;
+    }
+
+    pub fn record_mode_register(&mut self, value: u16) {
+        self.mode_registers.insert(value);
+    }
+
+    pub fn num_transitions(&self, bank: IoBank, from: Command, to: Command) -> u64 {
+        self.transitions[bank.index()][from.index()][to.index()]
+    }
+
+    // Legal transitions which haven't happened yet in `bank`
+    pub fn missing_transitions(&self, bank: IoBank) -> Vec<(Command, Command)> {
+        Command::ALL
+            .iter()
+            .flat_map(|&from| Command::ALL.iter().map(move |&to| (from, to)))
+            .filter(|&(from, to)| from.can_precede(to) && self.num_transitions(bank, from, to) == 0)
+            .collect()
+    }
+
+    pub fn num_min_delay_hits(&self, kind: ViolationKind) -> Option<u64> {
+        self.min_delay_hits
+            .iter()
+            .find(|(hit_kind, _)| *hit_kind == kind)
+            .map(|(_, num_hits)| *num_hits)
+    }
+
+    pub fn missed_min_delays(&self) -> Vec<ViolationKind> {
+        self.min_delay_hits
+            .iter()
+            .filter(|(_, num_hits)| *num_hits == 0)
+            .map(|(kind, _)| *kind)
+            .collect()
+    }
+
+    pub fn is_row_activated(&self, bank: IoBank, row_addr: u32) -> bool {
+        self.activated_rows[bank.index()][row_addr as usize]
+    }
+
+    pub fn num_activated_rows(&self, bank: IoBank) -> u32 {
+        self.num_activated_rows[bank.index()]
+    }
+
+    pub fn mode_registers(&self) -> impl Iterator<Item = u16> + '_ {
+        self.mode_registers.iter().copied()
+    }
+
+    // The loaded mode register values which aren't reserved, decoded
+    pub fn modes(&self) -> impl Iterator<Item = Mode> + '_ {
+        self.mode_registers().filter_map(Mode::decode)
+    }
+
+    pub fn missing_cas_latencies(&self) -> Vec<u32> {
+        CAS_LATENCIES
+            .into_iter()
+            .filter(|&cas_latency| !self.modes().any(|mode| mode.cas_latency == cas_latency))
+            .collect()
+    }
+
+    pub fn missing_burst_lengths(&self) -> Vec<BurstLength> {
+        BurstLength::ALL
+            .into_iter()
+            .filter(|&burst_length| !self.modes().any(|mode| mode.burst_length == burst_length))
+            .collect()
+    }
+
+    pub fn missing_burst_types(&self) -> Vec<BurstType> {
+        BurstType::ALL
+            .into_iter()
+            .filter(|&burst_type| !self.modes().any(|mode| mode.burst_type == burst_type))
+            .collect()
+    }
+}
+
+// Writes a line like "Burst types: 1/2 covered, missing interleaved"
+fn write_bins(
+    f: &mut fmt::Formatter,
+    label: &str,
+    num_bins: usize,
+    missing_bins: &[String],
+) -> fmt::Result {
+    write!(
+        f,
+        "{}: {}/{} covered",
+        label,
+        num_bins - missing_bins.len(),
+        num_bins
+    )?;
+    if !missing_bins.is_empty() {
+        write!(f, ", missing {}", missing_bins.join("/"))?;
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let num_legal_transitions = Command::ALL
+            .iter()
+            .flat_map(|&from| Command::ALL.iter().filter(move |&&to| from.can_precede(to)))
+            .count();
+        for index in 0..NUM_BANKS as usize {
+            let bank = IoBank::from_index(index).unwrap();
+            let missing_transitions = self.missing_transitions(bank);
+            write!(
+                f,
+                "Bank {}: {}/{} transitions, {}/{} rows activated",
+                index,
+                num_legal_transitions - missing_transitions.len(),
+                num_legal_transitions,
+                self.num_activated_rows(bank),
+                NUM_ROWS
+            )?;
+            if !missing_transitions.is_empty() {
+                write!(f, ", missing")?;
+                // Grouped by the earlier command, eg. "ACT->RDA/WRA"
+                let mut last_from = None;
+                for (from, to) in missing_transitions {
+                    if last_from == Some(from) {
+                        write!(f, "/{}", to.name())?;
+                    } else {
+                        write!(f, " {}->{}", from.name(), to.name())?;
+                        last_from = Some(from);
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+
+        write!(f, "Minimum delays met exactly:")?;
+        for (kind, num_hits) in &self.min_delay_hits {
+            write!(f, " {} {}", kind.name(), num_hits)?;
+        }
+        writeln!(f)?;
+
+        write_bins(
+            f,
+            "CAS latencies",
+            CAS_LATENCIES.len(),
+            &self
+                .missing_cas_latencies()
+                .iter()
+                .map(|cas_latency| cas_latency.to_string())
+                .collect::<Vec<_>>(),
+        )?;
+        write_bins(
+            f,
+            "Burst lengths",
+            BurstLength::ALL.len(),
+            &self
+                .missing_burst_lengths()
+                .iter()
+                .map(|burst_length| burst_length.name().to_string())
+                .collect::<Vec<_>>(),
+        )?;
+        write_bins(
+            f,
+            "Burst types",
+            BurstType::ALL.len(),
+            &self
+                .missing_burst_types()
+                .iter()
+                .map(|burst_type| burst_type.name().to_string())
+                .collect::<Vec<_>>(),
+        )?;
+
+        write!(f, "Mode registers loaded:")?;
+        if self.mode_registers.is_empty() {
+            write!(f, " none")?;
+        }
+        for value in &self.mode_registers {
+            write!(f, " 0x{:04x}", value)?;
+            if Mode::decode(*value).is_none() {
+                write!(f, " (reserved)")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::{self, NaiveController};
+    use crate::sdram::{
+        self, Io, Sdram, A_10_MASK, BURST_LEN, T_RCD_CYCLES, T_RP_CYCLES, T_WR_CYCLES,
+    };
+
+    use std::io;
+
+    #[test]
+    fn commands() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+        assert!(sdram.coverage().is_none());
+        sdram.set_collect_coverage(true);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        for (num_nops, command, bank, a) in [
+            (0, sdram::Command::LoadModeRegister, IoBank::Bank0, 0x0033),
+            (1, sdram::Command::Active, IoBank::Bank1, 5),
+            (T_RCD_CYCLES - 1, sdram::Command::Write, IoBank::Bank1, 0),
+            (
+                BURST_LEN - 1 + T_WR_CYCLES - 1,
+                sdram::Command::Precharge,
+                IoBank::Bank0,
+                A_10_MASK,
+            ),
+            (T_RP_CYCLES - 1, sdram::Command::Active, IoBank::Bank1, 6),
+        ] {
+            io.command = sdram::Command::Nop;
+            for _ in 0..num_nops {
+                sdram.clk(&mut io)?;
+            }
+            io.command = command;
+            io.bank = bank;
+            io.a = a as _;
+            io.dq_in = sdram::OptionalBytePair::some(0xf00d);
+            sdram.clk(&mut io)?;
+        }
+
+        let coverage = sdram.coverage().unwrap();
+        for (bank, from, to, num_transitions) in [
+            (
+                IoBank::Bank0,
+                Command::LoadModeRegister,
+                Command::PrechargeAll,
+                1,
+            ),
+            (IoBank::Bank1, Command::LoadModeRegister, Command::Active, 1),
+            (IoBank::Bank1, Command::Active, Command::Write, 1),
+            (IoBank::Bank1, Command::Write, Command::PrechargeAll, 1),
+            (IoBank::Bank1, Command::PrechargeAll, Command::Active, 1),
+            (IoBank::Bank0, Command::PrechargeAll, Command::Active, 0),
+        ] {
+            assert_eq!(
+                coverage.num_transitions(bank, from, to),
+                num_transitions,
+                "bank {} {}->{}",
+                bank.index(),
+                from.name(),
+                to.name()
+            );
+        }
+        assert!(!coverage
+            .missing_transitions(IoBank::Bank1)
+            .contains(&(Command::Active, Command::Write)));
+        assert!(coverage
+            .missing_transitions(IoBank::Bank1)
+            .contains(&(Command::Active, Command::Read)));
+        // Only legal transitions count as missing
+        assert!(!coverage
+            .missing_transitions(IoBank::Bank1)
+            .contains(&(Command::Active, Command::Active)));
+
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TMrd), Some(1));
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TRcd), Some(1));
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TWr), Some(1));
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TRp), Some(1));
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TRc), Some(0));
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TRef), None);
+        assert!(coverage.missed_min_delays().contains(&ViolationKind::TXsr));
+
+        assert!(coverage.is_row_activated(IoBank::Bank1, 5));
+        assert!(!coverage.is_row_activated(IoBank::Bank0, 5));
+        assert_eq!(coverage.num_activated_rows(IoBank::Bank1), 2);
+        assert_eq!(coverage.mode_registers().collect::<Vec<_>>(), [0x0033]);
+        assert_eq!(coverage.missing_cas_latencies(), [2]);
+        assert_eq!(
+            coverage.missing_burst_lengths(),
+            [
+                BurstLength::One,
+                BurstLength::Two,
+                BurstLength::Four,
+                BurstLength::FullPage
+            ]
+        );
+        assert_eq!(coverage.missing_burst_types(), [BurstType::Interleaved]);
+        assert!(coverage.to_string().ends_with(
+            "CAS latencies: 1/2 covered, missing 2\n\
+             Burst lengths: 1/5 covered, missing 1/2/4/full page\n\
+             Burst types: 1/2 covered, missing interleaved\n\
+             Mode registers loaded: 0x0033"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(
+            Mode::decode(0x0033),
+            Some(Mode {
+                cas_latency: 3,
+                burst_length: BurstLength::Eight,
+                burst_type: BurstType::Sequential,
+            })
+        );
+        assert_eq!(
+            Mode::decode(0x0027),
+            Some(Mode {
+                cas_latency: 2,
+                burst_length: BurstLength::FullPage,
+                burst_type: BurstType::Sequential,
+            })
+        );
+        assert_eq!(
+            Mode::decode(0x002a),
+            Some(Mode {
+                cas_latency: 2,
+                burst_length: BurstLength::Four,
+                burst_type: BurstType::Interleaved,
+            })
+        );
+
+        // Reserved CAS latencies, burst lengths, and full page interleaved bursts
+        for value in [0x0013, 0x0043, 0x0034, 0x003f] {
+            assert_eq!(Mode::decode(value), None);
+        }
+    }
+
+    #[test]
+    fn naive_controller() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+        sdram.set_collect_coverage(true);
+        let mut c = NaiveController::new(sdram);
+
+        for addr in 0..4 {
+            c.execute(naive_controller::Command::Write {
+                addr: addr << 20,
+                data: addr as _,
+                mask: 0,
+            })?;
+            c.execute(naive_controller::Command::Read { addr: addr << 20 })?;
+        }
+
+        let coverage = c.sdram().coverage().unwrap();
+
+        // Every access opens and closes its own row, with the minimum tRCD, but it never refreshes
+        //  or uses auto precharge
+        assert_eq!(coverage.num_min_delay_hits(ViolationKind::TRcd), Some(8));
+        for bank in [IoBank::Bank0, IoBank::Bank3] {
+            let missing_transitions = coverage.missing_transitions(bank);
+            assert!(!missing_transitions.contains(&(Command::Precharge, Command::Active)));
+            assert!(missing_transitions.contains(&(Command::Active, Command::ReadAutoPrecharge)));
+            assert!(missing_transitions.contains(&(Command::Precharge, Command::AutoRefresh)));
+            assert_eq!(coverage.num_activated_rows(bank), 1);
+        }
+
+        Ok(())
+    }
+}
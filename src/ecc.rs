@@ -0,0 +1,592 @@
+// SECDED ECC on top of `NaiveController`: each 128-bit word is protected by an extended Hamming
+//  code (8 Hamming check bits and an overall parity bit), which corrects any single-bit error and
+//  detects any double-bit error on read. Corrected data isn't written back (there's no scrubbing),
+//  so the error is reported again on every read until the word is rewritten.
+//
+// The check bits are stored either:
+//
+//  - In a sideband device, modeled as a second `Sdram` which runs every command in lockstep with
+//    the data device, so it costs no extra cycles. An x16 device carries the check bits in the
+//    first beat of the burst; an x8 one only has the low byte of each beat, so they take the
+//    first two.
+//  - In-band, in a region reserved at the top of the data device, which takes up a ninth of it.
+//    Each word there holds the check bits of 8 data words, one per beat, so writes update their
+//    beat with an extra (masked) burst, and reads take an extra burst to fetch them.
+//
+// Masked writes can't update the check bits of a partial word, so they read the whole word
+//  first (correcting it as usual), merge in the new bytes, and write it back in full.
+
+use crate::controller::{self, Controller};
+use crate::naive_controller::{
+    byte_mask_bits, Command, NaiveController, NUM_WORD_ADDR_BITS, NUM_WORD_BITS,
+};
+use crate::sdram;
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+pub const NUM_HAMMING_BITS: u32 = 8;
+// Including the overall parity bit
+pub const NUM_CHECK_BITS: u32 = NUM_HAMMING_BITS + 1;
+// Data words whose check bits share an in-band check word
+pub const NUM_CHECK_WORD_SLOTS: u32 = sdram::BURST_LEN;
+
+// Position of each data bit in the Hamming code word, where positions which are powers of two
+//  are the check bits
+const DATA_POSITIONS: [u8; NUM_WORD_BITS as usize] = {
+    let mut positions = [0; NUM_WORD_BITS as usize];
+    let mut index = 0;
+    let mut position = 3u32;
+    while index < NUM_WORD_BITS as usize {
+        if !position.is_power_of_two() {
+            positions[index] = position as u8;
+            index += 1;
+        }
+        position += 1;
+    }
+    positions
+};
+
+fn hamming(data: u128) -> u16 {
+    (0..NUM_WORD_BITS)
+        .filter(|bit| (data >> bit) & 1 != 0)
+        .fold(0, |hamming, bit| {
+            hamming ^ DATA_POSITIONS[bit as usize] as u16
+        })
+}
+
+// Hamming check bits in bits 0-7, overall parity in bit 8
+pub fn encode(data: u128) -> u16 {
+    let hamming = hamming(data);
+    let parity = (data.count_ones() + hamming.count_ones()) & 1;
+    hamming | (parity as u16) << NUM_HAMMING_BITS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syndrome {
+    NoError,
+    // A single flipped data bit
+    Data(u32),
+    // A single flipped check bit, where bit 8 is the overall parity
+    Check(u32),
+    // Two (or more) flipped bits, which can't be corrected
+    Uncorrectable,
+}
+
+pub fn decode(data: u128, check: u16) -> Syndrome {
+    let check = check & ((1 << NUM_CHECK_BITS) - 1);
+    let syndrome = (hamming(data) ^ check) & ((1 << NUM_HAMMING_BITS) - 1);
+    let is_parity_error = (data.count_ones() + check.count_ones()) & 1 != 0;
+    match (syndrome, is_parity_error) {
+        (0, false) => Syndrome::NoError,
+        (_, false) => Syndrome::Uncorrectable,
+        (0, true) => Syndrome::Check(NUM_HAMMING_BITS),
+        (syndrome, true) if syndrome.is_power_of_two() => {
+            Syndrome::Check(syndrome.trailing_zeros())
+        }
+        (syndrome, true) => DATA_POSITIONS
+            .iter()
+            .position(|&position| position as u16 == syndrome)
+            .map_or(Syndrome::Uncorrectable, |bit| Syndrome::Data(bit as _)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SidebandWidth {
+    X8,
+    X16,
+}
+
+pub enum CheckBits {
+    Sideband(Box<sdram::Sdram>, SidebandWidth),
+    InBand,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EccError {
+    Corrected { addr: u32, syndrome: Syndrome },
+    Uncorrectable { addr: u32 },
+}
+
+impl fmt::Display for EccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EccError::Corrected {
+                addr,
+                syndrome: Syndrome::Data(bit),
+            } => write!(f, "Corrected data bit {} of word 0x{:06x}.", bit, addr),
+            EccError::Corrected {
+                addr,
+                syndrome: Syndrome::Check(bit),
+            } => write!(f, "Corrected check bit {} of word 0x{:06x}.", bit, addr),
+            EccError::Corrected { addr, .. } => write!(f, "Corrected word 0x{:06x}.", addr),
+            EccError::Uncorrectable { addr } => {
+                write!(f, "Uncorrectable error in word 0x{:06x}.", addr)
+            }
+        }
+    }
+}
+
+pub struct EccController {
+    controller: NaiveController,
+    sideband: Option<(NaiveController, SidebandWidth)>,
+
+    num_corrected: u64,
+    num_uncorrectable: u64,
+    errors: Vec<EccError>,
+}
+
+impl EccController {
+    pub fn new(sdram: sdram::Sdram, check_bits: CheckBits) -> EccController {
+        EccController {
+            controller: NaiveController::new(sdram),
+            sideband: match check_bits {
+                CheckBits::Sideband(sdram, width) => Some((NaiveController::new(*sdram), width)),
+                CheckBits::InBand => None,
+            },
+
+            num_corrected: 0,
+            num_uncorrectable: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn sideband_sdram(&mut self) -> Option<&mut sdram::Sdram> {
+        self.sideband
+            .as_mut()
+            .map(|(controller, _)| controller.sdram())
+    }
+
+    // Words available for data, starting from 0. In-band check words are above them.
+    pub fn num_data_words(&self) -> u32 {
+        let num_words = 1 << NUM_WORD_ADDR_BITS;
+        match self.sideband {
+            Some(_) => num_words,
+            None => num_words / (NUM_CHECK_WORD_SLOTS + 1) * NUM_CHECK_WORD_SLOTS,
+        }
+    }
+
+    // In-band only: the check word holding `addr`'s check bits, and the beat they're in
+    pub fn check_word(&self, addr: u32) -> Option<(u32, u32)> {
+        match self.sideband {
+            Some(_) => None,
+            None => Some((
+                self.num_data_words() + addr / NUM_CHECK_WORD_SLOTS,
+                addr % NUM_CHECK_WORD_SLOTS,
+            )),
+        }
+    }
+
+    pub fn num_corrected(&self) -> u64 {
+        self.num_corrected
+    }
+
+    pub fn num_uncorrectable(&self) -> u64 {
+        self.num_uncorrectable
+    }
+
+    // Errors found by reads since the last call, including those of read-modify-writes
+    pub fn take_errors(&mut self) -> Vec<EccError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    // Zeroes the data words in `range`, widened to whole check words, along with their check
+    //  bits (which are also zero), as ECC memory needs to be at boot. In-band check words are
+    //  read in full, so any of them which hasn't been written in full would be an uninitialized
+    //  read; once one has been initialized, the data words sharing it can be written one at a
+    //  time. Returns the number of cycles taken.
+    pub fn initialize(&mut self, range: Range<u32>) -> io::Result<u64> {
+        let start = range.start / NUM_CHECK_WORD_SLOTS * NUM_CHECK_WORD_SLOTS;
+        let end = (range.end.div_ceil(NUM_CHECK_WORD_SLOTS) * NUM_CHECK_WORD_SLOTS)
+            .min(self.num_data_words());
+        let mut num_cycles = 0;
+        for addr in start..end {
+            num_cycles += match self.sideband {
+                Some(_) => self.data_write(addr, 0)?,
+                None => self.execute_data(Command::Write {
+                    addr,
+                    data: 0,
+                    mask: 0,
+                })?,
+            };
+        }
+        if self.sideband.is_none() {
+            for addr in (start..end).step_by(NUM_CHECK_WORD_SLOTS as _) {
+                let (check_addr, _) = self.check_word(addr).unwrap();
+                num_cycles += self.execute_data(Command::Write {
+                    addr: check_addr,
+                    data: 0,
+                    mask: 0,
+                })?;
+            }
+        }
+
+        Ok(num_cycles)
+    }
+
+    fn check_range(&self, addr: u32) -> io::Result<()> {
+        if addr >= self.num_data_words() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Word 0x{:06x} is reserved for in-band check bits.", addr),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn execute_data(&mut self, command: Command) -> io::Result<u64> {
+        let (_, num_cycles) = self.controller.execute(command)?;
+        Ok(num_cycles)
+    }
+
+    // Sideband beats carrying check bits, as (number of beats, bits per beat)
+    fn sideband_layout(width: SidebandWidth) -> (u32, u32) {
+        match width {
+            SidebandWidth::X8 => (NUM_CHECK_BITS.div_ceil(8), 8),
+            SidebandWidth::X16 => (1, sdram::NUM_ELEMENT_BITS),
+        }
+    }
+
+    // Writes a full word and its check bits
+    fn data_write(&mut self, addr: u32, data: u128) -> io::Result<u64> {
+        let check = encode(data);
+        let mut num_cycles = self.execute_data(Command::Write {
+            addr,
+            data,
+            mask: 0,
+        })?;
+        match &mut self.sideband {
+            Some((sideband, width)) => {
+                let (num_beats, num_beat_bits) = Self::sideband_layout(*width);
+                let data = (0..num_beats).fold(0, |data, beat| {
+                    let bits =
+                        (check as u32 >> (beat * num_beat_bits)) & ((1 << num_beat_bits) - 1);
+                    data | (bits as u128) << (beat * sdram::NUM_ELEMENT_BITS)
+                });
+                sideband.execute(Command::Write {
+                    addr,
+                    data,
+                    mask: 0,
+                })?;
+            }
+            None => {
+                let (check_addr, slot) = self.check_word(addr).unwrap();
+                num_cycles += self.execute_data(Command::Write {
+                    addr: check_addr,
+                    data: (check as u128) << (slot * sdram::NUM_ELEMENT_BITS),
+                    mask: !(0b11 << (slot * 2)),
+                })?;
+            }
+        }
+
+        Ok(num_cycles)
+    }
+
+    // Reads a word and corrects it if possible. Returns the data and the number of cycles
+    //  taken.
+    fn data_read(&mut self, addr: u32) -> io::Result<(u128, u64)> {
+        let (data, mut num_cycles) = self.controller.execute(Command::Read { addr })?;
+        let mut data = controller::read_data(addr, data)?;
+        let check = match &mut self.sideband {
+            Some((sideband, width)) => {
+                let (num_beats, num_beat_bits) = Self::sideband_layout(*width);
+                let (check_data, _) = sideband.execute(Command::Read { addr })?;
+                let check_data = controller::read_data(addr, check_data)?;
+                (0..num_beats).fold(0, |check, beat| {
+                    let bits = (check_data >> (beat * sdram::NUM_ELEMENT_BITS)) as u32
+                        & ((1 << num_beat_bits) - 1);
+                    check | (bits << (beat * num_beat_bits)) as u16
+                })
+            }
+            None => {
+                let (check_addr, slot) = self.check_word(addr).unwrap();
+                let (check_data, check_num_cycles) = self
+                    .controller
+                    .execute(Command::Read { addr: check_addr })?;
+                num_cycles += check_num_cycles;
+                (controller::read_data(check_addr, check_data)? >> (slot * sdram::NUM_ELEMENT_BITS))
+                    as u16
+            }
+        };
+
+        match decode(data, check) {
+            Syndrome::NoError => (),
+            Syndrome::Uncorrectable => {
+                self.num_uncorrectable += 1;
+                self.errors.push(EccError::Uncorrectable { addr });
+            }
+            syndrome => {
+                if let Syndrome::Data(bit) = syndrome {
+                    data ^= 1 << bit;
+                }
+                self.num_corrected += 1;
+                self.errors.push(EccError::Corrected { addr, syndrome });
+            }
+        }
+
+        Ok((data, num_cycles))
+    }
+}
+
+impl Controller for EccController {
+    fn sdram(&mut self) -> &mut sdram::Sdram {
+        self.controller.sdram()
+    }
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        self.controller.idle(num_cycles)?;
+        if let Some((sideband, _)) = &mut self.sideband {
+            sideband.idle(num_cycles)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
+        match command {
+            Command::Write { addr, data, mask } => {
+                self.check_range(addr)?;
+                if mask == 0 {
+                    return Ok((None, self.data_write(addr, data)?));
+                }
+
+                // Merging into a word which can't be corrected would store fresh check bits
+                //  for bad data, and hide the error from later reads
+                let num_uncorrectable = self.num_uncorrectable;
+                let (old_data, mut num_cycles) = self.data_read(addr)?;
+                if self.num_uncorrectable > num_uncorrectable {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Masked write to word 0x{:06x}, which has an uncorrectable error.",
+                            addr
+                        ),
+                    ));
+                }
+                let byte_mask = byte_mask_bits(mask);
+                let data = (data & !byte_mask) | (old_data & byte_mask);
+                num_cycles += self.data_write(addr, data)?;
+                Ok((None, num_cycles))
+            }
+            Command::Read { addr } => {
+                self.check_range(addr)?;
+                let (data, num_cycles) = self.data_read(addr)?;
+                Ok((Some(data), num_cycles))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Rng;
+
+    // Flips bit `bit` of word `addr` directly in the SDRAM's cells
+    fn flip(sdram: &mut sdram::Sdram, addr: u32, bit: u32) {
+        let (bank, row_addr, col_addr) = NaiveController::map_addr(addr);
+        let col_addr = col_addr + bit / sdram::NUM_ELEMENT_BITS;
+        let value = sdram.peek(bank, row_addr, col_addr).expect("initialized");
+        sdram.poke(
+            bank,
+            row_addr,
+            col_addr,
+            sdram::OptionalBytePair::some(value ^ 1 << (bit % sdram::NUM_ELEMENT_BITS)),
+        );
+    }
+
+    fn ecc_controllers() -> io::Result<Vec<EccController>> {
+        let mut controllers = Vec::new();
+        for width in [SidebandWidth::X8, SidebandWidth::X16] {
+            controllers.push(EccController::new(
+                sdram::Sdram::new(None)?,
+                CheckBits::Sideband(Box::new(sdram::Sdram::new(None)?), width),
+            ));
+        }
+        let mut controller = EccController::new(sdram::Sdram::new(None)?, CheckBits::InBand);
+        controller.initialize(0..16)?;
+        controllers.push(controller);
+        Ok(controllers)
+    }
+
+    // Flips bit `bit` of the check bits of word `addr`, wherever they're stored
+    fn flip_check_bit(c: &mut EccController, addr: u32, bit: u32) {
+        match c.sideband {
+            Some((_, SidebandWidth::X8)) => {
+                flip(c.sideband_sdram().unwrap(), addr, bit / 8 * 16 + bit % 8)
+            }
+            Some((_, SidebandWidth::X16)) => flip(c.sideband_sdram().unwrap(), addr, bit),
+            None => {
+                let (check_addr, slot) = c.check_word(addr).unwrap();
+                flip(c.sdram(), check_addr, slot * sdram::NUM_ELEMENT_BITS + bit);
+            }
+        }
+    }
+
+    #[test]
+    fn secded() {
+        let mut rng = Rng::new(50);
+        for _ in 0..100 {
+            let data = rng.next_u128();
+            let check = encode(data);
+            assert_eq!(decode(data, check), Syndrome::NoError);
+            for bit in 0..NUM_WORD_BITS {
+                assert_eq!(decode(data ^ 1 << bit, check), Syndrome::Data(bit));
+            }
+            for bit in 0..NUM_CHECK_BITS {
+                assert_eq!(decode(data, check ^ 1 << bit), Syndrome::Check(bit));
+            }
+
+            // Any two flips, whether in the data or the check bits
+            let num_bits = (NUM_WORD_BITS + NUM_CHECK_BITS) as u64;
+            let a = rng.below(num_bits) as u32;
+            let b = (a + 1 + rng.below(num_bits - 1) as u32) % num_bits as u32;
+            let (mut data, mut check) = (data, check);
+            for bit in [a, b] {
+                match bit.checked_sub(NUM_WORD_BITS) {
+                    Some(check_bit) => check ^= 1 << check_bit,
+                    None => data ^= 1 << bit,
+                }
+            }
+            assert_eq!(decode(data, check), Syndrome::Uncorrectable);
+        }
+    }
+
+    #[test]
+    fn corrects_and_detects() -> io::Result<()> {
+        for mut c in ecc_controllers()? {
+            let data = 0x0123456789abcdef_fedcba9876543210;
+            for addr in 0..4 {
+                c.execute(Command::Write {
+                    addr,
+                    data: data ^ addr as u128,
+                    mask: 0,
+                })?;
+            }
+
+            // Single-bit errors in the data are corrected
+            flip(c.sdram(), 1, 77);
+            assert_eq!(c.execute(Command::Read { addr: 1 })?.0, Some(data ^ 1));
+            assert_eq!(
+                c.take_errors(),
+                [EccError::Corrected {
+                    addr: 1,
+                    syndrome: Syndrome::Data(77),
+                }]
+            );
+
+            // ... as are those in the check bits
+            flip_check_bit(&mut c, 2, 8);
+            assert_eq!(c.execute(Command::Read { addr: 2 })?.0, Some(data ^ 2));
+            assert_eq!(
+                c.take_errors(),
+                [EccError::Corrected {
+                    addr: 2,
+                    syndrome: Syndrome::Check(8),
+                }]
+            );
+
+            // Double-bit errors are only reported, and the data comes back as it was read
+            flip(c.sdram(), 3, 0);
+            flip_check_bit(&mut c, 3, 2);
+            assert_eq!(c.execute(Command::Read { addr: 3 })?.0, Some(data ^ 2));
+            assert_eq!(c.take_errors(), [EccError::Uncorrectable { addr: 3 }]);
+
+            // Other words are unaffected
+            assert_eq!(c.execute(Command::Read { addr: 0 })?.0, Some(data));
+            assert_eq!(c.take_errors(), []);
+            assert_eq!((c.num_corrected(), c.num_uncorrectable()), (2, 1));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn masked_writes() -> io::Result<()> {
+        for mut c in ecc_controllers()? {
+            c.execute(Command::Write {
+                addr: 5,
+                data: !0,
+                mask: 0,
+            })?;
+            let (_, unmasked_num_cycles) = c.execute(Command::Write {
+                addr: 6,
+                data: !0,
+                mask: 0,
+            })?;
+
+            // The read-modify-write corrects the word on the way
+            flip(c.sdram(), 5, 100);
+            let (_, num_cycles) = c.execute(Command::Write {
+                addr: 5,
+                data: 0,
+                mask: 0xff00,
+            })?;
+            assert!(num_cycles > unmasked_num_cycles);
+            assert_eq!(c.take_errors().len(), 1);
+            assert_eq!(c.execute(Command::Read { addr: 5 })?.0, Some(!0 << 64));
+            assert_eq!(c.take_errors(), []);
+
+            // ... but fails if the word can't be corrected, and leaves it as it was
+            flip(c.sdram(), 6, 3);
+            flip(c.sdram(), 6, 90);
+            assert_eq!(
+                c.execute(Command::Write {
+                    addr: 6,
+                    data: 0,
+                    mask: 0xff00,
+                })
+                .unwrap_err()
+                .kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(
+                c.execute(Command::Read { addr: 6 })?.0,
+                Some(!0 ^ 1 << 3 ^ 1 << 90)
+            );
+            assert_eq!(c.take_errors(), [EccError::Uncorrectable { addr: 6 }; 2]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn in_band_costs_extra_bursts() -> io::Result<()> {
+        let mut sideband = EccController::new(
+            sdram::Sdram::new(None)?,
+            CheckBits::Sideband(Box::new(sdram::Sdram::new(None)?), SidebandWidth::X8),
+        );
+        let mut in_band = EccController::new(sdram::Sdram::new(None)?, CheckBits::InBand);
+        in_band.initialize(0..8)?;
+        let mut num_cycles = [0; 2];
+        for (c, num_cycles) in [&mut sideband, &mut in_band]
+            .into_iter()
+            .zip(&mut num_cycles)
+        {
+            c.execute(Command::Write {
+                addr: 7,
+                data: 7,
+                mask: 0,
+            })?;
+            *num_cycles = c.execute(Command::Read { addr: 7 })?.1;
+        }
+        assert_eq!(num_cycles[1], 2 * num_cycles[0]);
+
+        // The check words are off limits
+        let addr = in_band.num_data_words();
+        assert!(addr + addr / NUM_CHECK_WORD_SLOTS <= 1 << NUM_WORD_ADDR_BITS);
+        assert_eq!(
+            in_band.check_word(addr - 1),
+            Some((addr + addr / NUM_CHECK_WORD_SLOTS - 1, 7))
+        );
+        assert_eq!(
+            in_band.execute(Command::Read { addr }).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        Ok(())
+    }
+}
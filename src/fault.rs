@@ -0,0 +1,428 @@
+// Faults injected into an `Sdram` with `Sdram::add_fault`, either before the simulation starts or
+//  at any point during it. Cell faults act on the stored data, while pin faults change what the
+//  part sees on its inputs (and drives onto DQ), so they affect every command. Reads which any
+//  fault went into are reported by `Sdram::take_faulty_reads`:
+//
+//  - Stuck-at cells, transient flips (until the cell is written again) and dead banks, for reads
+//    of the cells they affect
+//  - Address pin faults, when they changed the row or column address (or bank) that was read
+//  - DQ pin faults, for every read which drove the pin
+
+use crate::sdram::{
+    Io, IoBank, OptionalBytePair, NUM_BANKS, NUM_BANK_ADDR_BITS, NUM_COLS, NUM_COL_ADDR_BITS,
+    NUM_ELEMENT_BITS, NUM_ROWS, NUM_ROW_ADDR_BITS,
+};
+
+use std::{fmt, io};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub bank: IoBank,
+    pub row_addr: u32,
+    pub col_addr: u32,
+    // 0-15, as on DQ
+    pub bit: u32,
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bank {} row 0x{:04x} col 0x{:03x} bit {}",
+            self.bank.index(),
+            self.row_addr,
+            self.col_addr,
+            self.bit
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pin {
+    A(u32),
+    Ba(u32),
+    Dq(u32),
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pin::A(index) => write!(f, "A{}", index),
+            Pin::Ba(index) => write!(f, "BA{}", index),
+            Pin::Dq(index) => write!(f, "DQ{}", index),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // Always reads back as `value`
+    StuckAt { cell: Cell, value: bool },
+    // Flips at the start of `cycle`, if the cell has been written by then
+    TransientFlip { cell: Cell, cycle: u64 },
+    StuckPin { pin: Pin, value: bool },
+    // Both pins see the AND of their levels. Both must be DQ pins, or both address (A/BA) pins
+    ShortedPins(Pin, Pin),
+    // Drops writes, and leaves DQ undriven for reads
+    DeadBank(IoBank),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StuckAt { cell, value } => write!(f, "{} stuck at {}", cell, *value as u8),
+            Fault::TransientFlip { cell, cycle } => write!(f, "{} flips on cycle {}", cell, cycle),
+            Fault::StuckPin { pin, value } => write!(f, "{} stuck at {}", pin, *value as u8),
+            Fault::ShortedPins(a, b) => write!(f, "{} shorted to {}", a, b),
+            Fault::DeadBank(bank) => write!(f, "bank {} dead", bank.index()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaultId(u32);
+
+impl fmt::Display for FaultId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault #{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaultyRead {
+    pub cycle: u64,
+    // Where the read actually went, after any address pin faults
+    pub bank: IoBank,
+    pub row_addr: u32,
+    pub col_addr: u32,
+    pub fault_ids: Vec<FaultId>,
+}
+
+impl fmt::Display for FaultyRead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycle {}: bank {}: read of row 0x{:04x} col 0x{:03x} affected by",
+            self.cycle,
+            self.bank.index(),
+            self.row_addr,
+            self.col_addr
+        )?;
+        for (index, fault_id) in self.fault_ids.iter().enumerate() {
+            write!(f, "{} {}", if index == 0 { "" } else { "," }, fault_id)?;
+        }
+        write!(f, ".")
+    }
+}
+
+// Levels on the pins a fault can affect. DQ bytes which aren't driven have no level.
+struct Pins {
+    a: u16,
+    ba: u32,
+    dq: OptionalBytePair,
+}
+
+impl Pins {
+    fn get(&self, pin: Pin) -> Option<bool> {
+        match pin {
+            Pin::A(index) => Some((self.a >> index) & 1 != 0),
+            Pin::Ba(index) => Some((self.ba >> index) & 1 != 0),
+            Pin::Dq(index) => {
+                let byte = if index < 8 { self.dq.low } else { self.dq.high };
+                byte.map(|byte| (byte >> (index % 8)) & 1 != 0)
+            }
+        }
+    }
+
+    fn set(&mut self, pin: Pin, level: bool) {
+        match pin {
+            Pin::A(index) => self.a = (self.a & !(1 << index)) | (level as u16) << index,
+            Pin::Ba(index) => self.ba = (self.ba & !(1 << index)) | (level as u32) << index,
+            Pin::Dq(index) => {
+                let byte = if index < 8 {
+                    &mut self.dq.low
+                } else {
+                    &mut self.dq.high
+                };
+                if let Some(byte) = byte {
+                    let bit = index % 8;
+                    *byte = (*byte & !(1 << bit)) | (level as u8) << bit;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Faults {
+    faults: Vec<(FaultId, Fault)>,
+    next_id: u32,
+
+    // Transient flips which have happened, and haven't been overwritten since
+    flipped_ids: Vec<FaultId>,
+    // Address pin faults which changed the current cycle's inputs
+    input_fault_ids: Vec<FaultId>,
+    // Address pin faults which changed the last ACT in each bank
+    row_fault_ids: [Vec<FaultId>; NUM_BANKS as usize],
+    // Address pin faults which changed the last READ
+    burst_fault_ids: Vec<FaultId>,
+}
+
+impl Faults {
+    // Fails for pins or cells which don't exist, and for shorts between a DQ pin and an address
+    //  pin, since those would tie read data to the command inputs
+    pub fn add(&mut self, fault: Fault) -> io::Result<FaultId> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let pins = match fault {
+            Fault::StuckAt { cell, .. } | Fault::TransientFlip { cell, .. } => {
+                if cell.row_addr >= NUM_ROWS || cell.col_addr >= NUM_COLS {
+                    return Err(invalid(format!("No such cell: {}.", cell)));
+                }
+                vec![Pin::Dq(cell.bit)]
+            }
+            Fault::StuckPin { pin, .. } => vec![pin],
+            Fault::ShortedPins(a, b) => {
+                if matches!(a, Pin::Dq(_)) != matches!(b, Pin::Dq(_)) {
+                    return Err(invalid(format!(
+                        "Can't short a DQ pin to an address pin: {}.",
+                        fault
+                    )));
+                }
+                vec![a, b]
+            }
+            Fault::DeadBank(_) => Vec::new(),
+        };
+        for pin in pins {
+            let num_pins = match pin {
+                Pin::A(_) => NUM_ROW_ADDR_BITS,
+                Pin::Ba(_) => NUM_BANK_ADDR_BITS,
+                Pin::Dq(_) => NUM_ELEMENT_BITS,
+            };
+            let (Pin::A(index) | Pin::Ba(index) | Pin::Dq(index)) = pin;
+            if index >= num_pins {
+                return Err(invalid(format!("No such pin: {}.", pin)));
+            }
+        }
+
+        let id = FaultId(self.next_id);
+        self.next_id += 1;
+        self.faults.push((id, fault));
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: FaultId) {
+        self.faults.retain(|(fault_id, _)| *fault_id != id);
+        self.flipped_ids.retain(|fault_id| *fault_id != id);
+        for fault_ids in self
+            .row_fault_ids
+            .iter_mut()
+            .chain([&mut self.input_fault_ids, &mut self.burst_fault_ids])
+        {
+            fault_ids.retain(|fault_id| *fault_id != id);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faults.is_empty()
+    }
+
+    pub fn get(&self, id: FaultId) -> Option<Fault> {
+        self.faults
+            .iter()
+            .find(|(fault_id, _)| *fault_id == id)
+            .map(|(_, fault)| *fault)
+    }
+
+    pub fn is_dead(&self, bank: IoBank) -> bool {
+        self.faults
+            .iter()
+            .any(|(_, fault)| *fault == Fault::DeadBank(bank))
+    }
+
+    // Applies the pin faults `selects` picks, in the order they were added, and returns the
+    //  ones which changed any level
+    fn apply_to_pins(&self, pins: &mut Pins, selects: fn(Pin) -> bool) -> Vec<FaultId> {
+        let mut fault_ids = Vec::new();
+        for &(id, fault) in &self.faults {
+            let is_changed = match fault {
+                Fault::StuckPin { pin, value } if selects(pin) => {
+                    let is_changed = pins.get(pin).is_some_and(|level| level != value);
+                    pins.set(pin, value);
+                    is_changed
+                }
+                Fault::ShortedPins(a, b) if selects(a) && selects(b) => {
+                    match (pins.get(a), pins.get(b)) {
+                        (Some(a_level), Some(b_level)) => {
+                            let level = a_level && b_level;
+                            pins.set(a, level);
+                            pins.set(b, level);
+                            a_level != b_level
+                        }
+                        _ => false,
+                    }
+                }
+                _ => false,
+            };
+            if is_changed {
+                fault_ids.push(id);
+            }
+        }
+        fault_ids
+    }
+
+    // Changes `io` to what the part sees on its inputs. A pins above the address width (eg. A10
+    //  for auto precharge) still take effect, but don't count towards the faults reads report.
+    pub fn apply_to_inputs(&mut self, io: &mut Io) {
+        let mut pins = Pins {
+            a: io.a,
+            ba: io.bank.index() as _,
+            dq: io.dq_in,
+        };
+        let fault_ids = self.apply_to_pins(&mut pins, |_| true);
+        io.a = pins.a;
+        io.bank = IoBank::from_index(pins.ba as _).unwrap();
+        io.dq_in = pins.dq;
+
+        self.input_fault_ids = fault_ids
+            .into_iter()
+            .filter(|&id| self.is_address_fault(id, NUM_ROW_ADDR_BITS))
+            .collect();
+    }
+
+    // Whether `id` involves BA or the low `num_addr_bits` A pins
+    fn is_address_fault(&self, id: FaultId, num_addr_bits: u32) -> bool {
+        let is_address_pin = |pin| match pin {
+            Pin::A(index) => index < num_addr_bits,
+            Pin::Ba(_) => true,
+            Pin::Dq(_) => false,
+        };
+        match self.get(id) {
+            Some(Fault::StuckPin { pin, .. }) => is_address_pin(pin),
+            Some(Fault::ShortedPins(a, b)) => is_address_pin(a) || is_address_pin(b),
+            _ => false,
+        }
+    }
+
+    fn column_fault_ids(&self) -> impl Iterator<Item = FaultId> + '_ {
+        self.input_fault_ids
+            .iter()
+            .copied()
+            .filter(|&id| self.is_address_fault(id, NUM_COL_ADDR_BITS))
+    }
+
+    // Called when a row is activated, after `apply_to_inputs`
+    pub fn active(&mut self, bank: IoBank) {
+        self.row_fault_ids[bank.index()].clone_from(&self.input_fault_ids);
+    }
+
+    // Called when a read burst starts, after `apply_to_inputs`
+    pub fn read(&mut self) {
+        self.burst_fault_ids = self.column_fault_ids().collect();
+    }
+
+    // Transient flips due at the start of `cycle`
+    pub fn transient_flips(&self, cycle: u64) -> Vec<(FaultId, Cell)> {
+        self.faults
+            .iter()
+            .filter_map(|&(id, fault)| match fault {
+                Fault::TransientFlip {
+                    cell,
+                    cycle: flip_cycle,
+                } if flip_cycle == cycle => Some((id, cell)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn mark_flipped(&mut self, id: FaultId) {
+        self.flipped_ids.push(id);
+    }
+
+    // Called for every column written, with the bytes which were actually written
+    pub fn written(&mut self, bank: IoBank, row_addr: u32, col_addr: u32, data: OptionalBytePair) {
+        let faults = &self.faults;
+        self.flipped_ids.retain(|&id| {
+            let Some(&(_, Fault::TransientFlip { cell, .. })) =
+                faults.iter().find(|(fault_id, _)| *fault_id == id)
+            else {
+                return false;
+            };
+            let byte = if cell.bit < 8 { data.low } else { data.high };
+            !((cell.bank, cell.row_addr, cell.col_addr) == (bank, row_addr, col_addr)
+                && byte.is_some())
+        });
+    }
+
+    // Applies cell and DQ pin faults to data read from a column, once DQM has masked it, and
+    //  returns every fault which went into the read. The column address comes from the current
+    //  cycle's inputs.
+    pub fn apply_to_read(
+        &self,
+        bank: IoBank,
+        row_addr: u32,
+        col_addr: u32,
+        data: &mut OptionalBytePair,
+    ) -> Vec<FaultId> {
+        let mut fault_ids = self.row_fault_ids[bank.index()].clone();
+        fault_ids.extend(&self.burst_fault_ids);
+        fault_ids.extend(self.column_fault_ids());
+        if let Some((id, _)) = self
+            .faults
+            .iter()
+            .find(|(_, fault)| *fault == Fault::DeadBank(bank))
+        {
+            *data = OptionalBytePair::none();
+            fault_ids.push(*id);
+        }
+
+        let is_cell =
+            |cell: &Cell| (cell.bank, cell.row_addr, cell.col_addr) == (bank, row_addr, col_addr);
+        let mut pins = Pins {
+            a: 0,
+            ba: 0,
+            dq: *data,
+        };
+        for &(id, fault) in &self.faults {
+            match fault {
+                Fault::StuckAt { cell, value }
+                    if is_cell(&cell) && pins.get(Pin::Dq(cell.bit)).is_some() =>
+                {
+                    pins.set(Pin::Dq(cell.bit), value);
+                    fault_ids.push(id);
+                }
+                Fault::TransientFlip { cell, .. }
+                    if is_cell(&cell) && self.flipped_ids.contains(&id) =>
+                {
+                    fault_ids.push(id);
+                }
+                _ => (),
+            }
+        }
+
+        // Every read which drives a faulty DQ pin goes through it, even if it happens to
+        //  leave the level alone (eg. a write through the same pin may have stored it wrong)
+        let dq_ids = self
+            .faults
+            .iter()
+            .filter(|(_, fault)| match *fault {
+                Fault::StuckPin {
+                    pin: pin @ Pin::Dq(_),
+                    ..
+                } => pins.get(pin).is_some(),
+                Fault::ShortedPins(a @ Pin::Dq(_), b @ Pin::Dq(_)) => {
+                    pins.get(a).is_some() || pins.get(b).is_some()
+                }
+                _ => false,
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        self.apply_to_pins(&mut pins, |pin| matches!(pin, Pin::Dq(_)));
+        fault_ids.extend(dq_ids);
+
+        *data = pins.dq;
+        fault_ids.sort();
+        fault_ids.dedup();
+        fault_ids
+    }
+}
@@ -0,0 +1,580 @@
+// Minimal FST writer, covering what the SDRAM traces need: nested scopes, bit vector wires and
+//  variable length strings. The format is described in GTKWave's `fstapi.c`; this follows the
+//  same block layout but leaves out aliases, attributes and the LZ4/FastLZ packers.
+//
+// The header, geometry and hierarchy are written as soon as the definitions are done, and value
+//  changes are buffered and written out in blocks, after which the header is patched. So the file
+//  is a complete, readable FST whenever it's been flushed, even if the writer is never dropped.
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const BLOCK_TYPE_HEADER: u8 = 0;
+const BLOCK_TYPE_VC_DATA: u8 = 1;
+const BLOCK_TYPE_GEOMETRY: u8 = 3;
+const BLOCK_TYPE_HIERARCHY: u8 = 4;
+
+const HEADER_LEN: u64 = 329;
+const HEADER_VERSION_LEN: usize = 128;
+const HEADER_DATE_LEN: usize = 119;
+// Readers use this to find out which endianness doubles were written with
+const HEADER_ENDIAN_TEST: f64 = std::f64::consts::E;
+const FILE_TYPE_VERILOG: u8 = 0;
+
+const HIERARCHY_SCOPE: u8 = 254;
+const HIERARCHY_UPSCOPE: u8 = 255;
+const SCOPE_TYPE_MODULE: u8 = 0;
+const VAR_TYPE_WIRE: u8 = 16;
+const VAR_TYPE_STRING: u8 = 21;
+const VAR_DIRECTION_IMPLICIT: u8 = 0;
+
+const PACK_TYPE_ZLIB: u8 = b'Z';
+
+// Value change data is written out as a block once it grows past this
+const MAX_BLOCK_DATA_LEN: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handle(usize);
+
+struct Signal {
+    // 0 for strings, which have no fixed width
+    width: u32,
+    // Current value and value as of the start of the block, as '0'/'1'/'x'/'z' characters
+    value: Vec<u8>,
+    frame: Vec<u8>,
+
+    // Encoded changes in the current block, and the index of the last change's time in the
+    //  block's time table
+    changes: Vec<u8>,
+    last_time_index: usize,
+}
+
+pub struct Writer<W: Write + Seek> {
+    w: W,
+    header_pos: u64,
+    timescale_exponent: i8,
+
+    hierarchy: Vec<u8>,
+    num_scopes: u64,
+    signals: Vec<Signal>,
+    definitions_done: bool,
+
+    start_time: Option<u64>,
+    time: u64,
+    // Times in the current block with at least one change
+    time_table: Vec<u64>,
+    block_data_len: usize,
+    num_blocks: u64,
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_c_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+// Compressed data is only used if it's actually smaller; readers tell the two apart by comparing
+//  the compressed and uncompressed lengths
+fn zlib_compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    Ok(if compressed.len() < data.len() {
+        compressed
+    } else {
+        data.to_vec()
+    })
+}
+
+impl<W: Write + Seek> Writer<W> {
+    // Times are in units of 10^timescale_exponent seconds, eg. -9 for ns
+    pub fn new(mut w: W, timescale_exponent: i8) -> io::Result<Writer<W>> {
+        let header_pos = w.stream_position()?;
+        Ok(Writer {
+            w,
+            header_pos,
+            timescale_exponent,
+
+            hierarchy: Vec::new(),
+            num_scopes: 0,
+            signals: Vec::new(),
+            definitions_done: false,
+
+            start_time: None,
+            time: 0,
+            time_table: Vec::new(),
+            block_data_len: 0,
+            num_blocks: 0,
+        })
+    }
+
+    pub fn add_module(&mut self, name: &str) {
+        assert!(!self.definitions_done);
+        self.hierarchy.push(HIERARCHY_SCOPE);
+        self.hierarchy.push(SCOPE_TYPE_MODULE);
+        write_c_str(&mut self.hierarchy, name);
+        write_c_str(&mut self.hierarchy, "");
+        self.num_scopes += 1;
+    }
+
+    pub fn upscope(&mut self) {
+        assert!(!self.definitions_done);
+        self.hierarchy.push(HIERARCHY_UPSCOPE);
+    }
+
+    fn add_var(&mut self, var_type: u8, width: u32, reference: &str) -> Handle {
+        assert!(!self.definitions_done);
+        self.hierarchy.push(var_type);
+        self.hierarchy.push(VAR_DIRECTION_IMPLICIT);
+        write_c_str(&mut self.hierarchy, reference);
+        write_varint(&mut self.hierarchy, width as _);
+        // Not an alias of another signal
+        write_varint(&mut self.hierarchy, 0);
+
+        let value = vec![b'x'; width as usize];
+        self.signals.push(Signal {
+            width,
+            frame: value.clone(),
+            value,
+
+            changes: Vec::new(),
+            last_time_index: 0,
+        });
+        Handle(self.signals.len() - 1)
+    }
+
+    pub fn add_wire(&mut self, width: u32, reference: &str) -> Handle {
+        assert!(width > 0);
+        self.add_var(VAR_TYPE_WIRE, width, reference)
+    }
+
+    pub fn add_string(&mut self, reference: &str) -> Handle {
+        self.add_var(VAR_TYPE_STRING, 0, reference)
+    }
+
+    pub fn enddefinitions(&mut self) -> io::Result<()> {
+        assert!(!self.definitions_done);
+        self.definitions_done = true;
+
+        self.write_header()?;
+
+        // Geometry: each signal's width, with strings marked as variable length
+        let mut geometry = Vec::new();
+        for signal in &self.signals {
+            write_varint(
+                &mut geometry,
+                match signal.width {
+                    0 => u32::MAX as _,
+                    width => width as _,
+                },
+            );
+        }
+        let compressed = zlib_compress(&geometry)?;
+        self.w.write_all(&[BLOCK_TYPE_GEOMETRY])?;
+        self.w
+            .write_all(&(compressed.len() as u64 + 3 * 8).to_be_bytes())?;
+        self.w.write_all(&(geometry.len() as u64).to_be_bytes())?;
+        self.w
+            .write_all(&(self.signals.len() as u64).to_be_bytes())?;
+        self.w.write_all(&compressed)?;
+
+        // The hierarchy is always gzipped, unlike the other blocks
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.hierarchy)?;
+        let compressed = encoder.finish()?;
+        self.w.write_all(&[BLOCK_TYPE_HIERARCHY])?;
+        self.w
+            .write_all(&(compressed.len() as u64 + 2 * 8).to_be_bytes())?;
+        self.w
+            .write_all(&(self.hierarchy.len() as u64).to_be_bytes())?;
+        self.w.write_all(&compressed)?;
+
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = Vec::new();
+        header.push(BLOCK_TYPE_HEADER);
+        header.extend_from_slice(&HEADER_LEN.to_be_bytes());
+        header.extend_from_slice(&self.start_time.unwrap_or(0).to_be_bytes());
+        header.extend_from_slice(&self.time.to_be_bytes());
+        header.extend_from_slice(&HEADER_ENDIAN_TEST.to_le_bytes());
+        // Memory used by writer
+        header.extend_from_slice(&0u64.to_be_bytes());
+        header.extend_from_slice(&self.num_scopes.to_be_bytes());
+        // Var count and max handle are the same, as there are no aliases
+        header.extend_from_slice(&(self.signals.len() as u64).to_be_bytes());
+        header.extend_from_slice(&(self.signals.len() as u64).to_be_bytes());
+        header.extend_from_slice(&self.num_blocks.to_be_bytes());
+        header.push(self.timescale_exponent as u8);
+        let mut version = env!("CARGO_PKG_NAME").as_bytes().to_vec();
+        version.resize(HEADER_VERSION_LEN, 0);
+        header.extend_from_slice(&version);
+        header.extend_from_slice(&[0; HEADER_DATE_LEN]);
+        header.push(FILE_TYPE_VERILOG);
+        // Time zero
+        header.extend_from_slice(&0u64.to_be_bytes());
+        assert_eq!(header.len() as u64, 1 + HEADER_LEN);
+
+        let end = self.w.stream_position()?;
+        self.w.seek(SeekFrom::Start(self.header_pos))?;
+        self.w.write_all(&header)?;
+        if end > self.header_pos {
+            self.w.seek(SeekFrom::Start(end))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn timestamp(&mut self, time: u64) -> io::Result<()> {
+        assert!(time >= self.time || self.start_time.is_none());
+        if self.block_data_len >= MAX_BLOCK_DATA_LEN {
+            self.write_block()?;
+        }
+        self.start_time.get_or_insert(time);
+        self.time = time;
+
+        Ok(())
+    }
+
+    // Number of time table entries since the signal's last change in this block (or since the
+    //  start of the block), which is how changes are timed
+    fn time_delta(&mut self, handle: Handle) -> u64 {
+        assert!(self.definitions_done);
+        if self.time_table.last() != Some(&self.time) {
+            self.time_table.push(self.time);
+        }
+        let time_index = self.time_table.len() - 1;
+        let signal = &mut self.signals[handle.0];
+        let delta = time_index - signal.last_time_index;
+        signal.last_time_index = time_index;
+        delta as _
+    }
+
+    pub fn change_vector(&mut self, handle: Handle, value: &[vcd::Value]) -> io::Result<()> {
+        let delta = self.time_delta(handle);
+        let signal = &mut self.signals[handle.0];
+        assert_eq!(value.len(), signal.width as usize);
+        let len = signal.changes.len();
+
+        let is_two_state = value
+            .iter()
+            .all(|bit| matches!(bit, vcd::Value::V0 | vcd::Value::V1));
+        if signal.width == 1 {
+            // Single bits pack the value in with the time delta
+            let vli = match value[0] {
+                vcd::Value::V0 => delta << 2,
+                vcd::Value::V1 => (delta << 2) | (1 << 1),
+                vcd::Value::X => (delta << 4) | 1,
+                vcd::Value::Z => (delta << 4) | (1 << 1) | 1,
+            };
+            write_varint(&mut signal.changes, vli);
+        } else if is_two_state {
+            write_varint(&mut signal.changes, delta << 1);
+            for byte in value.chunks(8) {
+                signal
+                    .changes
+                    .push(byte.iter().enumerate().fold(0, |acc, (i, bit)| {
+                        acc | (((*bit == vcd::Value::V1) as u8) << (7 - i))
+                    }));
+            }
+        } else {
+            write_varint(&mut signal.changes, (delta << 1) | 1);
+            signal
+                .changes
+                .extend(value.iter().map(|bit| bit.to_string().as_bytes()[0]));
+        }
+
+        signal.value = value
+            .iter()
+            .map(|bit| bit.to_string().as_bytes()[0])
+            .collect();
+        self.block_data_len += signal.changes.len() - len;
+
+        Ok(())
+    }
+
+    pub fn change_string(&mut self, handle: Handle, value: &str) -> io::Result<()> {
+        let delta = self.time_delta(handle);
+        let signal = &mut self.signals[handle.0];
+        assert_eq!(signal.width, 0);
+        let len = signal.changes.len();
+
+        write_varint(&mut signal.changes, delta << 1);
+        write_varint(&mut signal.changes, value.len() as _);
+        signal.changes.extend_from_slice(value.as_bytes());
+
+        self.block_data_len += signal.changes.len() - len;
+
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.time_table.is_empty() {
+            return Ok(());
+        }
+
+        let mut block = Vec::new();
+
+        // Values as of the start of the block. Strings have no entry.
+        let frame = self
+            .signals
+            .iter()
+            .flat_map(|signal| signal.frame.iter().copied())
+            .collect::<Vec<_>>();
+        let compressed = zlib_compress(&frame)?;
+        write_varint(&mut block, frame.len() as _);
+        write_varint(&mut block, compressed.len() as _);
+        write_varint(&mut block, self.signals.len() as _);
+        block.extend_from_slice(&compressed);
+
+        // Each signal's changes, located by their offsets relative to the pack type
+        write_varint(&mut block, self.signals.len() as _);
+        let vc_start = block.len();
+        block.push(PACK_TYPE_ZLIB);
+        let mut offsets = Vec::new();
+        let mut mem_required = frame.len();
+        for signal in &self.signals {
+            if signal.changes.is_empty() {
+                offsets.push(None);
+                continue;
+            }
+
+            offsets.push(Some(block.len() - vc_start));
+            mem_required += signal.changes.len();
+            let compressed = zlib_compress(&signal.changes)?;
+            if compressed.len() < signal.changes.len() {
+                write_varint(&mut block, signal.changes.len() as _);
+                block.extend_from_slice(&compressed);
+            } else {
+                // Stored uncompressed
+                write_varint(&mut block, 0);
+                block.extend_from_slice(&signal.changes);
+            }
+        }
+
+        // Offset chain: deltas between consecutive offsets, with runs of signals that have no
+        //  changes collapsed into a count
+        let mut chain = Vec::new();
+        let mut prev_offset = 0;
+        let mut num_unchanged = 0u64;
+        for offset in offsets {
+            match offset {
+                Some(offset) => {
+                    if num_unchanged > 0 {
+                        write_varint(&mut chain, num_unchanged << 1);
+                        num_unchanged = 0;
+                    }
+                    write_varint(&mut chain, (((offset - prev_offset) as u64) << 1) | 1);
+                    prev_offset = offset;
+                }
+                None => num_unchanged += 1,
+            }
+        }
+        if num_unchanged > 0 {
+            write_varint(&mut chain, num_unchanged << 1);
+        }
+        block.extend_from_slice(&chain);
+        block.extend_from_slice(&(chain.len() as u64).to_be_bytes());
+
+        // Time table, as deltas
+        let mut time_table = Vec::new();
+        let mut prev_time = 0;
+        for &time in &self.time_table {
+            write_varint(&mut time_table, time - prev_time);
+            prev_time = time;
+        }
+        let compressed = zlib_compress(&time_table)?;
+        block.extend_from_slice(&compressed);
+        block.extend_from_slice(&(time_table.len() as u64).to_be_bytes());
+        block.extend_from_slice(&(compressed.len() as u64).to_be_bytes());
+        block.extend_from_slice(&(self.time_table.len() as u64).to_be_bytes());
+
+        self.w.write_all(&[BLOCK_TYPE_VC_DATA])?;
+        self.w
+            .write_all(&(block.len() as u64 + 4 * 8).to_be_bytes())?;
+        self.w.write_all(&self.time_table[0].to_be_bytes())?;
+        self.w
+            .write_all(&self.time_table.last().unwrap().to_be_bytes())?;
+        self.w.write_all(&(mem_required as u64).to_be_bytes())?;
+        self.w.write_all(&block)?;
+
+        for signal in &mut self.signals {
+            signal.frame.clone_from(&signal.value);
+            signal.changes.clear();
+            signal.last_time_index = 0;
+        }
+        self.time_table.clear();
+        self.block_data_len = 0;
+        self.num_blocks += 1;
+
+        self.write_header()
+    }
+
+    // Writes out any buffered changes, so the file is complete up to the current time
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.definitions_done {
+            self.write_block()?;
+        }
+        self.w.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for Writer<W> {
+    fn drop(&mut self) {
+        // As with `io::BufWriter`, errors can't be reported here; call `flush` to see them
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fst_reader::{FstFilter, FstHierarchyEntry, FstReader, FstSignalValue};
+
+    use std::collections::BTreeMap;
+
+    // All changes in a file, by signal name
+    fn read_changes(bytes: Vec<u8>) -> BTreeMap<String, Vec<(u64, String)>> {
+        let mut reader = FstReader::open(io::Cursor::new(bytes)).unwrap();
+        let mut names = Vec::new();
+        let mut scopes = Vec::new();
+        reader
+            .read_hierarchy(|entry| match entry {
+                FstHierarchyEntry::Scope { name, .. } => scopes.push(name),
+                FstHierarchyEntry::UpScope => {
+                    scopes.pop();
+                }
+                FstHierarchyEntry::Var { name, handle, .. } => {
+                    assert_eq!(handle.get_index(), names.len());
+                    scopes.push(name);
+                    names.push(scopes.join("."));
+                    scopes.pop();
+                }
+                _ => (),
+            })
+            .unwrap();
+
+        let mut ret = BTreeMap::<String, Vec<(u64, String)>>::new();
+        reader
+            .read_signals(&FstFilter::all(), |time, handle, value| {
+                let value = match value {
+                    FstSignalValue::String(value) => String::from_utf8(value.to_vec()).unwrap(),
+                    FstSignalValue::Real(value) => value.to_string(),
+                };
+                ret.entry(names[handle.get_index()].clone())
+                    .or_default()
+                    .push((time, value));
+            })
+            .unwrap();
+        ret
+    }
+
+    #[test]
+    fn round_trip() -> io::Result<()> {
+        let mut bytes = Vec::new();
+        {
+            let mut w = Writer::new(io::Cursor::new(&mut bytes), -9)?;
+            w.add_module("top");
+            let clk = w.add_wire(1, "clk");
+            let data = w.add_wire(12, "data");
+            w.add_module("sub");
+            let name = w.add_string("name");
+            let _unused = w.add_wire(3, "unused");
+            w.upscope();
+            w.upscope();
+            w.enddefinitions()?;
+
+            let (v0, v1, x, z) = (vcd::Value::V0, vcd::Value::V1, vcd::Value::X, vcd::Value::Z);
+            w.timestamp(0)?;
+            w.change_vector(clk, &[v0])?;
+            w.change_vector(data, &[z; 12])?;
+            w.change_string(name, "idle")?;
+            w.timestamp(5)?;
+            w.change_vector(clk, &[v1])?;
+            w.change_vector(data, &[v1, v0, v1, v0, v0, v0, v0, v0, v0, v0, v1, v1])?;
+            // Blocks can be split anywhere
+            w.flush()?;
+            w.timestamp(10)?;
+            w.change_vector(clk, &[x])?;
+            w.change_string(name, "busy")?;
+            w.timestamp(15)?;
+            w.change_vector(clk, &[v0])?;
+            w.change_vector(data, &[x, v1, v0, v1, v1, v1, v1, v1, v1, v1, v1, z])?;
+        }
+
+        let changes = read_changes(bytes);
+        assert_eq!(
+            changes["top.clk"],
+            [
+                (0, "0".into()),
+                (5, "1".into()),
+                (10, "x".into()),
+                (15, "0".into())
+            ]
+        );
+        assert_eq!(
+            changes["top.data"],
+            [
+                (0, "zzzzzzzzzzzz".into()),
+                (5, "101000000011".into()),
+                (15, "x1011111111z".into())
+            ]
+        );
+        assert_eq!(
+            changes["top.sub.name"],
+            [(0, "idle".into()), (10, "busy".into())]
+        );
+        assert!(!changes.contains_key("top.sub.unused"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn many_blocks() -> io::Result<()> {
+        let mut bytes = Vec::new();
+        let num_changes = 300_000;
+        {
+            let mut w = Writer::new(io::Cursor::new(&mut bytes), -9)?;
+            w.add_module("top");
+            let count = w.add_wire(32, "count");
+            w.upscope();
+            w.enddefinitions()?;
+
+            for i in 0..num_changes {
+                w.timestamp(i as u64 * 2)?;
+                let bits = (0..32)
+                    .rev()
+                    .map(|bit| match (i >> bit) & 1 {
+                        0 => vcd::Value::V0,
+                        _ => vcd::Value::V1,
+                    })
+                    .collect::<Vec<_>>();
+                w.change_vector(count, &bits)?;
+            }
+        }
+
+        let changes = &read_changes(bytes)["top.count"];
+        assert_eq!(changes.len(), num_changes as usize);
+        for (i, (time, value)) in changes.iter().enumerate() {
+            assert_eq!(*time, i as u64 * 2);
+            assert_eq!(u32::from_str_radix(value, 2).unwrap(), i as u32);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,481 @@
+// Randomized property testing for controllers. `Harness` generates random sequences of
+//  commands and idle periods, runs each through a fresh controller (wrapped in a
+//  `ShadowChecker`) and checks that:
+//
+//  - the SDRAM reports no timing or protocol violations,
+//  - every read returns what was last written (see `shadow`), and
+//  - no row misses its refresh deadline (tREF).
+//
+// The first failing sequence is shrunk by repeatedly removing steps and simplifying the ones
+//  left, as long as it keeps failing the same way, and the minimal sequence is then run once
+//  more with tracing enabled so it can be inspected as a VCD.
+
+use crate::controller::Controller;
+use crate::naive_controller::{Command, NUM_WORD_ADDR_BITS};
+use crate::rng::Rng;
+use crate::sdram;
+use crate::shadow::{self, ShadowChecker};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Execute(Command),
+    Idle(u64),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Execute(Command::Write { addr, data, mask }) => write!(
+                f,
+                "write 0x{:06x} 0x{:032x} mask 0x{:04x}",
+                addr, data, mask
+            ),
+            Step::Execute(Command::Read { addr }) => write!(f, "read 0x{:06x}", addr),
+            Step::Idle(num_cycles) => write!(f, "idle {}", num_cycles),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    // Any violation except tREF
+    Violation(sdram::Violation),
+    Mismatch(shadow::Mismatch),
+    RefreshDeadline(sdram::Violation),
+}
+
+impl Failure {
+    // Whether `other` is the same kind of failure, which is what shrinking preserves
+    fn is_like(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Failure::Violation(a), Failure::Violation(b)) => a.kind == b.kind,
+            (Failure::Mismatch(_), Failure::Mismatch(_)) => true,
+            (Failure::RefreshDeadline(_), Failure::RefreshDeadline(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Violation(violation) => write!(f, "violation: {}", violation),
+            Failure::Mismatch(mismatch) => write!(f, "data mismatch: {}", mismatch),
+            Failure::RefreshDeadline(violation) => {
+                write!(f, "missed refresh deadline: {}", violation)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    // Seed of the failing case, as passed to `Harness::generate`
+    pub case_seed: u64,
+    pub original_num_steps: usize,
+    // Shrunk
+    pub steps: Vec<Step>,
+    pub failure: Failure,
+    pub trace_path: Option<String>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "case 0x{:016x} failed after shrinking from {} to {} steps: {}",
+            self.case_seed,
+            self.original_num_steps,
+            self.steps.len(),
+            self.failure
+        )?;
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(f, "  {:>4}: {}", index, step)?;
+        }
+        if let Some(trace_path) = &self.trace_path {
+            write!(f, "trace: {}", trace_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+type NewController = Box<dyn FnMut(sdram::Sdram) -> Box<dyn Controller>>;
+
+pub struct Harness {
+    trace_file_name_prefix: Option<String>,
+    new_controller: NewController,
+
+    seed: u64,
+    num_cases: u32,
+    max_num_steps: usize,
+    num_addrs: usize,
+    max_idle_cycles: u64,
+    t_ref_cycles: u32,
+}
+
+impl Harness {
+    // Counterexamples are traced to `vcd/{trace_file_name_prefix}.vcd`
+    pub fn new(
+        trace_file_name_prefix: Option<&str>,
+        new_controller: impl FnMut(sdram::Sdram) -> Box<dyn Controller> + 'static,
+    ) -> Harness {
+        Harness {
+            trace_file_name_prefix: trace_file_name_prefix.map(|prefix| prefix.to_owned()),
+            new_controller: Box::new(new_controller),
+
+            seed: 0,
+            num_cases: 100,
+            max_num_steps: 100,
+            num_addrs: 8,
+            max_idle_cycles: 100,
+            t_ref_cycles: sdram::T_REF_CYCLES,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn set_num_cases(&mut self, num_cases: u32) {
+        self.num_cases = num_cases;
+    }
+
+    pub fn set_max_num_steps(&mut self, max_num_steps: usize) {
+        self.max_num_steps = max_num_steps;
+    }
+
+    // Each case picks this many random words and only accesses those, so reads and masked
+    //  writes hit previously written data
+    pub fn set_num_addrs(&mut self, num_addrs: usize) {
+        assert!(num_addrs > 0, "At least one address is required.");
+        self.num_addrs = num_addrs;
+    }
+
+    pub fn set_max_idle_cycles(&mut self, max_idle_cycles: u64) {
+        self.max_idle_cycles = max_idle_cycles;
+    }
+
+    // `sdram::T_REF_CYCLES` by default, which is millions of cycles and far longer than any
+    //  generated sequence, so refresh deadlines are only tested if this is shortened to within
+    //  a run's length
+    pub fn set_t_ref_cycles(&mut self, t_ref_cycles: u32) {
+        self.t_ref_cycles = t_ref_cycles;
+    }
+
+    // Words are always written in full before they're read or partially written, since reading
+    //  uninitialized columns is a violation in itself
+    pub fn generate(&self, case_seed: u64) -> Vec<Step> {
+        let mut rng = Rng::new(case_seed);
+        let addrs = (0..self.num_addrs)
+            .map(|_| rng.below(1 << NUM_WORD_ADDR_BITS) as u32)
+            .collect::<Vec<_>>();
+        let mut is_written = vec![false; addrs.len()];
+
+        let num_steps = 1 + rng.below(self.max_num_steps as _) as usize;
+        (0..num_steps)
+            .map(|_| {
+                if rng.chance(0.1) {
+                    return Step::Idle(rng.below(self.max_idle_cycles + 1));
+                }
+
+                let index = rng.below(addrs.len() as _) as usize;
+                let addr = addrs[index];
+                if is_written[index] && rng.chance(0.5) {
+                    return Step::Execute(Command::Read { addr });
+                }
+
+                let mask = if is_written[index] && rng.chance(0.5) {
+                    rng.next_u64() as u16
+                } else {
+                    0
+                };
+                is_written[index] = true;
+                Step::Execute(Command::Write {
+                    addr,
+                    data: rng.next_u128(),
+                    mask,
+                })
+            })
+            .collect()
+    }
+
+    // Runs `steps` through a fresh controller, stopping at the first failure
+    pub fn check(&mut self, steps: &[Step]) -> io::Result<Option<Failure>> {
+        self.check_traced(steps, None)
+    }
+
+    fn check_traced(
+        &mut self,
+        steps: &[Step],
+        trace_file_name_prefix: Option<&str>,
+    ) -> io::Result<Option<Failure>> {
+        let mut sdram = sdram::Sdram::new(trace_file_name_prefix)?;
+        sdram.set_panic_on_violation(false);
+        sdram.set_t_ref_cycles(self.t_ref_cycles);
+        let mut c = ShadowChecker::new((self.new_controller)(sdram));
+        c.set_panic_on_mismatch(false);
+
+        for step in steps {
+            match *step {
+                Step::Execute(command) => {
+                    c.execute(command)?;
+                }
+                Step::Idle(num_cycles) => c.idle(num_cycles)?,
+            }
+
+            if let Some(violation) = c.sdram().take_violations().first() {
+                return Ok(Some(match violation.kind {
+                    sdram::ViolationKind::TRef => Failure::RefreshDeadline(*violation),
+                    _ => Failure::Violation(*violation),
+                }));
+            }
+            if let Some(mismatch) = c.take_mismatches().first() {
+                return Ok(Some(Failure::Mismatch(*mismatch)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Generates and checks `num_cases` sequences, and returns the first failure, shrunk
+    pub fn run(&mut self) -> io::Result<Option<Counterexample>> {
+        let mut rng = Rng::new(self.seed);
+        for _ in 0..self.num_cases {
+            let case_seed = rng.next_u64();
+            let steps = self.generate(case_seed);
+            if let Some(failure) = self.check(&steps)? {
+                let original_num_steps = steps.len();
+                let (steps, failure) = self.shrink(steps, failure)?;
+
+                let trace_path = match self.trace_file_name_prefix.clone() {
+                    Some(prefix) => {
+                        self.check_traced(&steps, Some(&prefix))?;
+                        Some(format!("vcd/{}.vcd", prefix))
+                    }
+                    None => None,
+                };
+
+                return Ok(Some(Counterexample {
+                    case_seed,
+                    original_num_steps,
+                    steps,
+                    failure,
+                    trace_path,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Greedily applies simplifications which keep the sequence failing the same way, until
+    //  none do
+    pub fn shrink(
+        &mut self,
+        mut steps: Vec<Step>,
+        mut failure: Failure,
+    ) -> io::Result<(Vec<Step>, Failure)> {
+        loop {
+            let mut is_shrunk = false;
+            for candidate in candidates(&steps) {
+                if let Some(candidate_failure) = self.check(&candidate)? {
+                    if candidate_failure.is_like(&failure) {
+                        steps = candidate;
+                        failure = candidate_failure;
+                        is_shrunk = true;
+                        break;
+                    }
+                }
+            }
+            if !is_shrunk {
+                return Ok((steps, failure));
+            }
+        }
+    }
+}
+
+// Simpler variants of `steps`, most aggressive first: with chunks removed (halves, quarters,
+//  ... single steps), with addresses renumbered from zero, and with single steps simplified
+fn candidates(steps: &[Step]) -> Vec<Vec<Step>> {
+    let mut candidates = Vec::new();
+
+    let mut chunk_len = steps.len() / 2;
+    while chunk_len > 0 {
+        for start in (0..steps.len()).step_by(chunk_len) {
+            let end = (start + chunk_len).min(steps.len());
+            candidates.push([&steps[..start], &steps[end..]].concat());
+        }
+        chunk_len /= 2;
+    }
+
+    let mut renumbered_addrs = HashMap::new();
+    let mut renumber = |addr: u32| {
+        let num_addrs = renumbered_addrs.len() as u32;
+        *renumbered_addrs.entry(addr).or_insert(num_addrs)
+    };
+    let renumbered = steps
+        .iter()
+        .map(|step| match *step {
+            Step::Execute(Command::Write { addr, data, mask }) => Step::Execute(Command::Write {
+                addr: renumber(addr),
+                data,
+                mask,
+            }),
+            Step::Execute(Command::Read { addr }) => Step::Execute(Command::Read {
+                addr: renumber(addr),
+            }),
+            step => step,
+        })
+        .collect::<Vec<_>>();
+    if renumbered != steps {
+        candidates.push(renumbered);
+    }
+
+    for (index, step) in steps.iter().enumerate() {
+        let simplified = match *step {
+            Step::Execute(Command::Write { addr, data, mask }) if data != 0 => {
+                Step::Execute(Command::Write {
+                    addr,
+                    data: 0,
+                    mask,
+                })
+            }
+            Step::Idle(num_cycles) if num_cycles > 0 => Step::Idle(num_cycles / 2),
+            _ => continue,
+        };
+        let mut candidate = steps.to_vec();
+        candidate[index] = simplified;
+        candidates.push(candidate);
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::controller::Hooked;
+    use crate::naive_controller::NaiveController;
+
+    use std::path::Path;
+
+    #[test]
+    fn naive_controller_passes() -> io::Result<()> {
+        // With the real tREF, which no sequence gets near
+        let mut harness = Harness::new(None, |sdram| Box::new(NaiveController::new(sdram)));
+        harness.set_num_cases(20);
+
+        let counterexample = harness.run()?;
+        assert!(counterexample.is_none(), "{}", counterexample.unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn naive_controller_misses_refresh() -> io::Result<()> {
+        // `NaiveController` doesn't refresh yet, which a tREF within a run's length shows. It's
+        //  the only property it breaks, so the cases are checked without shrinking.
+        let mut harness = Harness::new(None, |sdram| Box::new(NaiveController::new(sdram)));
+        harness.set_t_ref_cycles(500);
+
+        let mut num_failures = 0;
+        for case_seed in 0..20 {
+            let steps = harness.generate(case_seed);
+            if let Some(failure) = harness.check(&steps)? {
+                assert!(
+                    matches!(failure, Failure::RefreshDeadline(_)),
+                    "{}",
+                    failure
+                );
+                num_failures += 1;
+            }
+        }
+        assert!(num_failures > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrinks_counterexample() -> io::Result<()> {
+        let mut harness = Harness::new(Some("Harness__shrinks_counterexample"), |sdram| {
+            // Ignores write masks
+            Box::new(Hooked::new(sdram, |c, command| {
+                c.execute(match command {
+                    Command::Write { addr, data, .. } => Command::Write {
+                        addr,
+                        data,
+                        mask: 0,
+                    },
+                    command => command,
+                })
+            }))
+        });
+
+        let counterexample = harness.run()?.expect("Unmasked writes should be caught.");
+        println!("{}", counterexample);
+
+        // A full write, a masked write and a read are all it takes
+        assert!(matches!(counterexample.failure, Failure::Mismatch(_)));
+        assert_eq!(counterexample.steps.len(), 3);
+        assert!(matches!(
+            counterexample.steps[..],
+            [
+                Step::Execute(Command::Write {
+                    addr: 0,
+                    mask: 0,
+                    ..
+                }),
+                Step::Execute(Command::Write { addr: 0, .. }),
+                Step::Execute(Command::Read { addr: 0 }),
+            ]
+        ));
+        assert!(Path::new(counterexample.trace_path.as_ref().unwrap()).exists());
+
+        // The same case is generated again from its seed
+        let steps = harness.generate(counterexample.case_seed);
+        assert_eq!(steps.len(), counterexample.original_num_steps);
+        assert!(harness.check(&steps)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_deadline() -> io::Result<()> {
+        let mut harness = Harness::new(None, |sdram| Box::new(NaiveController::new(sdram)));
+        harness.set_t_ref_cycles(1_000);
+
+        // `NaiveController` doesn't refresh yet
+        let write = Step::Execute(Command::Write {
+            addr: 0,
+            data: 0,
+            mask: 0,
+        });
+        assert_eq!(harness.check(&[write, Step::Idle(900)])?, None);
+        let failure = harness.check(&[write, Step::Idle(1_000)])?;
+        assert!(matches!(failure, Some(Failure::RefreshDeadline(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn violation() -> io::Result<()> {
+        let mut harness = Harness::new(None, |sdram| Box::new(NaiveController::new(sdram)));
+
+        let failure = harness.check(&[Step::Execute(Command::Read { addr: 0 })])?;
+        assert!(matches!(
+            failure,
+            Some(Failure::Violation(sdram::Violation {
+                kind: sdram::ViolationKind::UninitializedRead,
+                ..
+            }))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,2 +1,20 @@
+pub mod arbiter;
+pub mod axi;
+pub mod controller;
+pub mod coverage;
+pub mod ecc;
+pub mod fault;
+pub mod fst;
+pub mod harness;
+pub mod memtest;
 pub mod naive_controller;
+pub mod power;
+pub mod rng;
 pub mod sdram;
+pub mod shadow;
+pub mod timing;
+pub mod trace;
+pub mod traffic;
+pub mod vcd_replay;
+pub mod wishbone;
+pub mod workload;
@@ -0,0 +1,67 @@
+use dramatic::vcd_replay;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+const USAGE: &str = "Usage: dramatic check <file.vcd> [--map <signals.toml>] [--trace <prefix>]";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn check(args: &[String]) -> io::Result<bool> {
+    let mut path = None;
+    let mut map = None;
+    let mut trace_file_name_prefix = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => {
+                map = Some(vcd_replay::SignalMap::load(
+                    args.next().unwrap_or_else(|| usage()),
+                )?)
+            }
+            "--trace" => trace_file_name_prefix = Some(args.next().unwrap_or_else(|| usage())),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let file = fs::File::open(path)?;
+    let report = vcd_replay::replay(
+        io::BufReader::new(file),
+        map.as_ref(),
+        trace_file_name_prefix.map(|prefix| prefix.as_str()),
+    )?;
+    for finding in &report.findings {
+        println!("{}", finding);
+    }
+    println!(
+        "{} cycles replayed, {} findings",
+        report.num_cycles,
+        report.findings.len()
+    );
+
+    Ok(report.findings.is_empty())
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.split_first() {
+        Some((command, args)) if command == "check" => check(args),
+        _ => usage(),
+    };
+
+    match result {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,717 @@
+// Memory test algorithms, run through any `Controller` over a range of word addresses, as a
+//  boot-time RAM test would. Every read is checked against what the algorithm last wrote, and
+//  the words which failed are diagnosed from how their bits behaved over the whole run:
+//
+//  - Address decoder faults: a read brought back another word in the range (address
+//    uniqueness), or every bit of the word was wrong at once (eg. a write to another address
+//    landed on it, or nothing was accessed at all)
+//  - Stuck-at faults: bits which failed and always read back the same value
+//  - Coupling faults: bits which failed, but read back both values at some point, so an access
+//    to some other cell must have changed them
+//
+// Each algorithm only claims to detect some of these (see `Algorithm::detects`). Faults of other
+//  classes may still show up, but nothing guarantees they will.
+
+use crate::controller::{self, Controller};
+use crate::naive_controller::Command;
+use crate::sdram;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    // {⇕(w0); ⇑(r0,w1); ⇓(r1,w0)}
+    MatsPlus,
+    // {⇕(w0); ⇑(r0,w1); ⇑(r1,w0); ⇓(r0,w1); ⇓(r1,w0); ⇕(r0)}
+    MarchCMinus,
+    // For each word, writes and reads back each of the 16 single-bit patterns on DQ, repeated
+    //  across the burst
+    WalkingOnes,
+    // Writes each word's own address (repeated across the word) to every word in ascending
+    //  order and reads them all back, then does the same with the complement in descending order,
+    //  so writes which also land on a higher or lower word are both caught
+    AddressUniqueness,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FaultClass {
+    StuckAt,
+    Coupling,
+    AddressDecoder,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::MatsPlus,
+        Algorithm::MarchCMinus,
+        Algorithm::WalkingOnes,
+        Algorithm::AddressUniqueness,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::MatsPlus => "MATS+",
+            Algorithm::MarchCMinus => "March C-",
+            Algorithm::WalkingOnes => "walking ones",
+            Algorithm::AddressUniqueness => "address uniqueness",
+        }
+    }
+
+    // Fault classes the algorithm is guaranteed to detect
+    pub fn detects(&self) -> &'static [FaultClass] {
+        match self {
+            Algorithm::MatsPlus => &[FaultClass::StuckAt, FaultClass::AddressDecoder],
+            Algorithm::MarchCMinus => &[
+                FaultClass::StuckAt,
+                FaultClass::Coupling,
+                FaultClass::AddressDecoder,
+            ],
+            Algorithm::WalkingOnes => &[FaultClass::StuckAt],
+            Algorithm::AddressUniqueness => &[FaultClass::AddressDecoder],
+        }
+    }
+
+    // March elements: ascending or not, and the operations on each word, as (is_write, value)
+    fn march_elements(&self) -> &'static [(bool, &'static [(bool, bool)])] {
+        const W0: (bool, bool) = (true, false);
+        const W1: (bool, bool) = (true, true);
+        const R0: (bool, bool) = (false, false);
+        const R1: (bool, bool) = (false, true);
+        match self {
+            Algorithm::MatsPlus => &[(true, &[W0]), (true, &[R0, W1]), (false, &[R1, W0])],
+            Algorithm::MarchCMinus => &[
+                (true, &[W0]),
+                (true, &[R0, W1]),
+                (true, &[R1, W0]),
+                (false, &[R0, W1]),
+                (false, &[R1, W0]),
+                (true, &[R0]),
+            ],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub addr: u32,
+    pub expected: u128,
+    pub actual: u128,
+}
+
+impl Failure {
+    pub fn bits(&self) -> u128 {
+        self.expected ^ self.actual
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Read of word 0x{:06x} returned 0x{:032x}, expected 0x{:032x}.",
+            self.addr, self.actual, self.expected
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finding {
+    StuckAt { addr: u32, bits: u128, value: bool },
+    Coupling { addr: u32, bits: u128 },
+    // `other_addr` is the word which was read back instead, if that's known
+    AddressDecoder { addr: u32, other_addr: Option<u32> },
+}
+
+impl Finding {
+    pub fn class(&self) -> FaultClass {
+        match self {
+            Finding::StuckAt { .. } => FaultClass::StuckAt,
+            Finding::Coupling { .. } => FaultClass::Coupling,
+            Finding::AddressDecoder { .. } => FaultClass::AddressDecoder,
+        }
+    }
+
+    pub fn addr(&self) -> u32 {
+        match *self {
+            Finding::StuckAt { addr, .. }
+            | Finding::Coupling { addr, .. }
+            | Finding::AddressDecoder { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Finding::StuckAt { addr, bits, value } => write!(
+                f,
+                "word 0x{:06x}: bits 0x{:032x} stuck at {}",
+                addr, bits, value as u8
+            ),
+            Finding::Coupling { addr, bits } => write!(
+                f,
+                "word 0x{:06x}: bits 0x{:032x} coupled to other cells",
+                addr, bits
+            ),
+            Finding::AddressDecoder { addr, other_addr } => {
+                write!(f, "word 0x{:06x}: address decoder fault", addr)?;
+                if let Some(other_addr) = other_addr {
+                    write!(f, " (read back word 0x{:06x})", other_addr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub algorithm: Algorithm,
+    pub range: Range<u32>,
+    pub num_cycles: u64,
+    pub num_reads: u64,
+    pub num_writes: u64,
+    // Every read which didn't return what was last written, in the order they happened
+    pub failures: Vec<Failure>,
+    // One or more for each failing word, in address order
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn is_pass(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn failing_addrs(&self) -> Vec<u32> {
+        self.failures
+            .iter()
+            .map(|failure| failure.addr)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    // Bits of `addr` which failed at least once
+    pub fn failing_bits(&self, addr: u32) -> u128 {
+        self.failures
+            .iter()
+            .filter(|failure| failure.addr == addr)
+            .fold(0, |bits, failure| bits | failure.bits())
+    }
+
+    pub fn classes(&self) -> BTreeSet<FaultClass> {
+        self.findings.iter().map(Finding::class).collect()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: words 0x{:06x}-0x{:06x}, {} cycles ({} reads, {} writes), ",
+            self.algorithm.name(),
+            self.range.start,
+            self.range.end.saturating_sub(1),
+            self.num_cycles,
+            self.num_reads,
+            self.num_writes
+        )?;
+        if self.is_pass() {
+            return write!(f, "passed.");
+        }
+        write!(
+            f,
+            "{} failing words in {} failing reads:",
+            self.failing_addrs().len(),
+            self.failures.len()
+        )?;
+        for finding in &self.findings {
+            write!(f, "\n  {}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+// Per word: the bits which have read back as 0 and as 1
+#[derive(Clone, Copy, Default)]
+struct WordReads {
+    as_0: u128,
+    as_1: u128,
+}
+
+struct Run<'a> {
+    controller: &'a mut dyn Controller,
+    report: Report,
+    word_reads: Vec<WordReads>,
+    // (failure index, other address) for reads which brought back another word
+    aliases: Vec<(usize, u32)>,
+}
+
+impl Run<'_> {
+    fn write(&mut self, addr: u32, data: u128) -> io::Result<()> {
+        self.controller.execute(Command::Write {
+            addr,
+            data,
+            mask: 0,
+        })?;
+        self.report.num_writes += 1;
+
+        Ok(())
+    }
+
+    fn read(&mut self, addr: u32, expected: u128) -> io::Result<u128> {
+        let (actual, _) = self.controller.execute(Command::Read { addr })?;
+        let actual = controller::read_data(addr, actual)?;
+        self.report.num_reads += 1;
+
+        let word_reads = &mut self.word_reads[(addr - self.report.range.start) as usize];
+        word_reads.as_0 |= !actual;
+        word_reads.as_1 |= actual;
+        if actual != expected {
+            self.report.failures.push(Failure {
+                addr,
+                expected,
+                actual,
+            });
+        }
+
+        Ok(actual)
+    }
+
+    fn march(&mut self) -> io::Result<()> {
+        let range = self.report.range.clone();
+        for &(is_ascending, ops) in self.report.algorithm.march_elements() {
+            let addrs: Box<dyn Iterator<Item = u32>> = if is_ascending {
+                Box::new(range.clone())
+            } else {
+                Box::new(range.clone().rev())
+            };
+            for addr in addrs {
+                for &(is_write, value) in ops {
+                    let data = if value { !0 } else { 0 };
+                    if is_write {
+                        self.write(addr, data)?;
+                    } else {
+                        self.read(addr, data)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn walking_ones(&mut self) -> io::Result<()> {
+        for addr in self.report.range.clone() {
+            for bit in 0..sdram::NUM_ELEMENT_BITS {
+                let data = (0..sdram::BURST_LEN).fold(0, |data, element| {
+                    data | 1 << (element * sdram::NUM_ELEMENT_BITS + bit)
+                });
+                self.write(addr, data)?;
+                self.read(addr, data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn address_uniqueness(&mut self) -> io::Result<()> {
+        let range = self.report.range.clone();
+        for is_complement in [false, true] {
+            let pattern = |addr: u32| {
+                let data = addr as u128 * 0x00000001_00000001_00000001_00000001;
+                if is_complement {
+                    !data
+                } else {
+                    data
+                }
+            };
+            let addrs: Box<dyn Iterator<Item = u32>> = if is_complement {
+                Box::new(range.clone().rev())
+            } else {
+                Box::new(range.clone())
+            };
+            for addr in addrs {
+                self.write(addr, pattern(addr))?;
+            }
+            for addr in range.clone() {
+                let actual = self.read(addr, pattern(addr))?;
+                let other_addr = if is_complement { !actual } else { actual } as u32;
+                if other_addr != addr
+                    && range.contains(&other_addr)
+                    && actual == pattern(other_addr)
+                {
+                    self.aliases
+                        .push((self.report.failures.len() - 1, other_addr));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn diagnose(&mut self) {
+        for addr in self.report.failing_addrs() {
+            let failures = || {
+                self.report
+                    .failures
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, failure)| failure.addr == addr)
+            };
+
+            let other_addr = failures().find_map(|(index, _)| {
+                self.aliases
+                    .iter()
+                    .find(|(alias_index, _)| *alias_index == index)
+                    .map(|(_, other_addr)| *other_addr)
+            });
+            if other_addr.is_some() || failures().any(|(_, failure)| failure.bits() == !0) {
+                self.report
+                    .findings
+                    .push(Finding::AddressDecoder { addr, other_addr });
+                continue;
+            }
+
+            let bits = self.report.failing_bits(addr);
+            let word_reads = self.word_reads[(addr - self.report.range.start) as usize];
+            for (value, stuck_bits) in [
+                (false, bits & word_reads.as_0 & !word_reads.as_1),
+                (true, bits & word_reads.as_1 & !word_reads.as_0),
+            ] {
+                if stuck_bits != 0 {
+                    self.report.findings.push(Finding::StuckAt {
+                        addr,
+                        bits: stuck_bits,
+                        value,
+                    });
+                }
+            }
+            let coupled_bits = bits & word_reads.as_0 & word_reads.as_1;
+            if coupled_bits != 0 {
+                self.report.findings.push(Finding::Coupling {
+                    addr,
+                    bits: coupled_bits,
+                });
+            }
+        }
+    }
+}
+
+// Runs `algorithm` over the words in `range`, overwriting them. Reads of words which have never
+//  been written by anything else are fine, since every algorithm writes each word before reading
+//  it.
+pub fn run(
+    controller: &mut dyn Controller,
+    algorithm: Algorithm,
+    range: Range<u32>,
+) -> io::Result<Report> {
+    let start_cycle = controller.sdram().cycle();
+    let mut run = Run {
+        controller,
+        report: Report {
+            algorithm,
+            range: range.clone(),
+            num_cycles: 0,
+            num_reads: 0,
+            num_writes: 0,
+            failures: Vec::new(),
+            findings: Vec::new(),
+        },
+        word_reads: vec![WordReads::default(); range.len()],
+        aliases: Vec::new(),
+    };
+
+    match algorithm {
+        Algorithm::MatsPlus | Algorithm::MarchCMinus => run.march()?,
+        Algorithm::WalkingOnes => run.walking_ones()?,
+        Algorithm::AddressUniqueness => run.address_uniqueness()?,
+    }
+    run.diagnose();
+
+    let mut report = run.report;
+    report.num_cycles = run.controller.sdram().cycle() - start_cycle;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::controller::Hooked;
+    use crate::naive_controller::{NaiveController, NUM_WORD_BITS};
+
+    const RANGE: Range<u32> = 0..16;
+
+    // Where bit `bit` of word `addr` is stored, as (bank, row address, column address, bit)
+    fn cell(addr: u32, bit: u32) -> (sdram::IoBank, u32, u32, u32) {
+        let (bank, row_addr, col_addr) = NaiveController::map_addr(addr);
+        (
+            bank,
+            row_addr,
+            col_addr + bit / sdram::NUM_ELEMENT_BITS,
+            bit % sdram::NUM_ELEMENT_BITS,
+        )
+    }
+
+    fn get_bit(sdram: &sdram::Sdram, addr: u32, bit: u32) -> Option<bool> {
+        let (bank, row_addr, col_addr, bit) = cell(addr, bit);
+        let data = sdram.peek(bank, row_addr, col_addr);
+        let byte = if bit < 8 { data.low } else { data.high };
+        byte.map(|byte| (byte >> (bit % 8)) & 1 != 0)
+    }
+
+    // Only changes initialized bytes
+    fn set_bit(sdram: &mut sdram::Sdram, addr: u32, bit: u32, value: bool) {
+        let (bank, row_addr, col_addr, bit) = cell(addr, bit);
+        let mut data = sdram.peek(bank, row_addr, col_addr);
+        let byte = if bit < 8 {
+            &mut data.low
+        } else {
+            &mut data.high
+        };
+        if let Some(byte) = byte {
+            *byte = (*byte & !(1 << (bit % 8))) | (value as u8) << (bit % 8);
+        }
+        sdram.poke(bank, row_addr, col_addr, data);
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum InjectedFault {
+        StuckAt {
+            addr: u32,
+            bit: u32,
+            value: bool,
+        },
+        // Idempotent coupling: the victim bit is set whenever the aggressor bit rises
+        Coupling {
+            aggressor: (u32, u32),
+            victim: (u32, u32),
+        },
+        // Writes to `addr` also land on `other_addr`
+        AddressDecoder {
+            addr: u32,
+            other_addr: u32,
+        },
+    }
+
+    // Corrupts the SDRAM's cells directly around each command, to model faults in the array
+    fn corrupted(fault: InjectedFault) -> io::Result<Hooked> {
+        Ok(Hooked::new(sdram::Sdram::new(None)?, move |c, command| {
+            if let InjectedFault::StuckAt { addr, bit, value } = fault {
+                set_bit(c.sdram(), addr, bit, value);
+            }
+            let aggressor_level = match fault {
+                InjectedFault::Coupling {
+                    aggressor: (addr, bit),
+                    ..
+                } => get_bit(c.sdram(), addr, bit),
+                _ => None,
+            };
+
+            let result = c.execute(command)?;
+
+            match (fault, command) {
+                (InjectedFault::StuckAt { addr, bit, value }, _) => {
+                    set_bit(c.sdram(), addr, bit, value)
+                }
+                (
+                    InjectedFault::Coupling {
+                        aggressor: (addr, bit),
+                        victim: (victim_addr, victim_bit),
+                    },
+                    _,
+                ) if aggressor_level == Some(false)
+                    && get_bit(c.sdram(), addr, bit) == Some(true) =>
+                {
+                    set_bit(c.sdram(), victim_addr, victim_bit, true);
+                }
+                (
+                    InjectedFault::AddressDecoder { addr, other_addr },
+                    Command::Write {
+                        addr: write_addr, ..
+                    },
+                ) if write_addr == addr => {
+                    for bit in (0..NUM_WORD_BITS).step_by(sdram::NUM_ELEMENT_BITS as _) {
+                        let (bank, row_addr, col_addr, _) = cell(addr, bit);
+                        let data = c.sdram().peek(bank, row_addr, col_addr);
+                        let (bank, row_addr, col_addr, _) = cell(other_addr, bit);
+                        c.sdram().poke(bank, row_addr, col_addr, data);
+                    }
+                }
+                _ => (),
+            }
+
+            Ok(result)
+        }))
+    }
+
+    #[test]
+    fn fault_free() -> io::Result<()> {
+        let mut c = NaiveController::new(sdram::Sdram::new(Some("MemTest__fault_free"))?);
+        for algorithm in Algorithm::ALL {
+            let report = run(&mut c, algorithm, RANGE)?;
+            assert!(report.is_pass(), "{}", report);
+            assert!(report.findings.is_empty());
+            assert!(report.num_cycles > 0);
+        }
+
+        // 10N operations for March C-
+        let report = run(&mut c, Algorithm::MarchCMinus, RANGE)?;
+        assert_eq!(
+            report.num_reads + report.num_writes,
+            10 * RANGE.len() as u64
+        );
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "March C-: words 0x000000-0x00000f, {} cycles (80 reads, 80 writes), passed.",
+                report.num_cycles
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_without_data() -> io::Result<()> {
+        let mut c = Hooked::new(sdram::Sdram::new(None)?, |c, command| {
+            let (data, num_cycles) = c.execute(command)?;
+            Ok((data.filter(|_| false), num_cycles))
+        });
+        let error = run(&mut c, Algorithm::MarchCMinus, RANGE).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Read of word 0x000000 returned no data.");
+
+        Ok(())
+    }
+
+    #[test]
+    fn detects_claimed_fault_classes() -> io::Result<()> {
+        let faults = [
+            InjectedFault::StuckAt {
+                addr: 5,
+                bit: 77,
+                value: true,
+            },
+            InjectedFault::StuckAt {
+                addr: 11,
+                bit: 3,
+                value: false,
+            },
+            InjectedFault::Coupling {
+                aggressor: (2, 5),
+                victim: (7, 40),
+            },
+            InjectedFault::Coupling {
+                aggressor: (12, 100),
+                victim: (4, 17),
+            },
+            InjectedFault::AddressDecoder {
+                addr: 3,
+                other_addr: 9,
+            },
+            InjectedFault::AddressDecoder {
+                addr: 14,
+                other_addr: 6,
+            },
+        ];
+
+        for algorithm in Algorithm::ALL {
+            for fault in faults {
+                let (class, addr, bits) = match fault {
+                    InjectedFault::StuckAt { addr, bit, .. } => {
+                        (FaultClass::StuckAt, addr, Some(1 << bit))
+                    }
+                    InjectedFault::Coupling {
+                        victim: (addr, bit),
+                        ..
+                    } => (FaultClass::Coupling, addr, Some(1 << bit)),
+                    InjectedFault::AddressDecoder { other_addr, .. } => {
+                        (FaultClass::AddressDecoder, other_addr, None)
+                    }
+                };
+                if !algorithm.detects().contains(&class) {
+                    continue;
+                }
+
+                let report = run(&mut corrupted(fault)?, algorithm, RANGE)?;
+                assert_eq!(report.failing_addrs(), [addr], "{:?}: {}", fault, report);
+                if let Some(bits) = bits {
+                    assert_eq!(report.failing_bits(addr), bits);
+                }
+                assert_eq!(
+                    report.classes(),
+                    BTreeSet::from([class]),
+                    "{:?}: {}",
+                    fault,
+                    report
+                );
+                assert!(report.findings.iter().all(|finding| finding.addr() == addr));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn diagnosis() -> io::Result<()> {
+        let report = run(
+            &mut corrupted(InjectedFault::StuckAt {
+                addr: 5,
+                bit: 77,
+                value: true,
+            })?,
+            Algorithm::MarchCMinus,
+            RANGE,
+        )?;
+        assert_eq!(
+            report.findings,
+            [Finding::StuckAt {
+                addr: 5,
+                bits: 1 << 77,
+                value: true,
+            }]
+        );
+        // Every r0 of the word fails
+        assert_eq!(report.failures.len(), 3);
+
+        // Address uniqueness knows which word was read back instead
+        let report = run(
+            &mut corrupted(InjectedFault::AddressDecoder {
+                addr: 3,
+                other_addr: 9,
+            })?,
+            Algorithm::AddressUniqueness,
+            RANGE,
+        )?;
+        assert_eq!(
+            report.findings,
+            [Finding::AddressDecoder {
+                addr: 9,
+                other_addr: Some(3),
+            }]
+        );
+        assert!(report
+            .to_string()
+            .ends_with("1 failing words in 1 failing reads:\n  word 0x000009: address decoder fault (read back word 0x000003)"));
+
+        // A victim below the aggressor which sets it is only caught by a descending (r0,w1),
+        //  which MATS+ doesn't have
+        let report = run(
+            &mut corrupted(InjectedFault::Coupling {
+                aggressor: (12, 100),
+                victim: (4, 17),
+            })?,
+            Algorithm::MatsPlus,
+            RANGE,
+        )?;
+        assert!(report.is_pass());
+
+        Ok(())
+    }
+}
@@ -1,16 +1,78 @@
+use crate::controller::Controller;
+use crate::rng::Rng;
 use crate::sdram;
 
 use std::io;
 
+// Each command transfers one word: a full burst of elements
+pub const NUM_WORD_BITS: u32 = sdram::BURST_LEN * sdram::NUM_ELEMENT_BITS;
+pub const NUM_WORD_BYTES: u32 = NUM_WORD_BITS / 8;
+// Word addresses cover the whole SDRAM, laid out as `NaiveController::map_addr` describes
+pub const NUM_WORD_ADDR_BITS: u32 =
+    sdram::NUM_BANK_ADDR_BITS + sdram::NUM_ROW_ADDR_BITS + sdram::NUM_COL_ADDR_BITS
+        - sdram::NUM_BURST_ADDR_BITS;
+pub const WORD_ADDR_MASK: u32 = (1 << NUM_WORD_ADDR_BITS) - 1;
+// Words per SDRAM row
+pub const NUM_ROW_WORDS: u32 = 1 << (sdram::NUM_COL_ADDR_BITS - sdram::NUM_BURST_ADDR_BITS);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Command {
-    // TODO: Mask bits
-    Write { addr: u32, data: u128 },
+    // Each set bit in `mask` masks off the corresponding byte of `data` (via DQM), leaving
+    //  the stored byte unchanged
+    Write { addr: u32, data: u128, mask: u16 },
     Read { addr: u32 },
 }
 
+// Expands a mask with one bit per byte of a word, like `Command::Write`'s `mask`, to one with all
+//  eight bits of each selected byte set
+pub fn byte_mask_bits(byte_mask: u16) -> u128 {
+    (0..NUM_WORD_BYTES)
+        .filter(|i| (byte_mask >> i) & 1 != 0)
+        .fold(0, |bits, i| bits | 0xff << (i * 8))
+}
+
+// Row hammer mitigations. Both refresh the rows next to an activated row by activating and
+//  precharging them after the command is done.
+#[derive(Clone, Copy, Debug)]
+pub enum Mitigation {
+    // Probabilistic adjacent row activation: after each ACT, each neighbor is refreshed with
+    //  `probability`
+    Para { probability: f64, seed: u64 },
+    // Targeted row refresh: ACTs are counted per row in a table of `num_counters` entries, and a
+    //  row's neighbors are refreshed once its count reaches `threshold`. When the table is full,
+    //  a new row takes over the entry with the lowest count and keeps counting from there. As on
+    //  real parts, hammering more rows than there are counters can still get through.
+    Trr { num_counters: usize, threshold: u32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MitigationCost {
+    // Rows refreshed on each side of the activated row. Only the nearest ones are disturbed in
+    //  `Sdram`'s model, so anything more only costs time.
+    pub blast_radius: u32,
+    // Cycles spent after each ACT deciding whether to refresh its neighbors (eg. looking up the
+    //  TRR table)
+    pub decision_cycles: u32,
+}
+
+impl Default for MitigationCost {
+    fn default() -> MitigationCost {
+        MitigationCost {
+            blast_radius: 1,
+            decision_cycles: 0,
+        }
+    }
+}
+
 pub struct NaiveController {
     sdram: sdram::Sdram,
     io: sdram::Io,
+
+    mitigation: Option<(Mitigation, MitigationCost)>,
+    rng: Rng,
+    // (bank, row address, count) for TRR
+    trr_counters: Vec<(sdram::IoBank, u32, u32)>,
+    num_mitigation_refreshes: u64,
 }
 
 impl NaiveController {
@@ -18,27 +80,67 @@ impl NaiveController {
         NaiveController {
             sdram,
             io: sdram::Io::new(),
+
+            mitigation: None,
+            rng: Rng::new(0),
+            trr_counters: Vec::new(),
+            num_mitigation_refreshes: 0,
         }
 
         // TODO: Initialization
     }
 
+    // Off by default. Setting it starts over with empty TRR counters.
+    pub fn set_mitigation(&mut self, mitigation: Option<Mitigation>, cost: MitigationCost) {
+        if let Some(Mitigation::Para { seed, .. }) = mitigation {
+            self.rng = Rng::new(seed);
+        }
+        self.mitigation = mitigation.map(|mitigation| (mitigation, cost));
+        self.trr_counters.clear();
+    }
+
+    // Rows refreshed by the mitigation so far
+    pub fn num_mitigation_refreshes(&self) -> u64 {
+        self.num_mitigation_refreshes
+    }
+
+    // Where word `addr` is stored: its bank, row address and the column address its burst starts
+    //  at. Bank bits are above row bits, which are above column bits.
+    pub fn map_addr(addr: u32) -> (sdram::IoBank, u32, u32) {
+        let element_addr = addr << sdram::NUM_BURST_ADDR_BITS;
+        let bank_addr = element_addr >> (sdram::NUM_ROW_ADDR_BITS + sdram::NUM_COL_ADDR_BITS)
+            & sdram::BANK_ADDR_MASK;
+        (
+            sdram::IoBank::from_index(bank_addr as _).unwrap(),
+            (element_addr >> sdram::NUM_COL_ADDR_BITS) & sdram::ROW_ADDR_MASK,
+            element_addr & sdram::COL_ADDR_MASK,
+        )
+    }
+
+    pub fn sdram(&mut self) -> &mut sdram::Sdram {
+        &mut self.sdram
+    }
+
+    pub fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        self.io.command = sdram::Command::Nop;
+        for _ in 0..num_cycles {
+            self.sdram.clk(&mut self.io)?;
+        }
+
+        Ok(())
+    }
+
     pub fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
         let mut ret_data = None;
         let mut num_cycles = 0;
 
         match command {
-            Command::Write { addr, data } => {
-                let element_addr = addr << sdram::NUM_BURST_ADDR_BITS;
-                let bank_addr = element_addr
-                    >> (sdram::NUM_ROW_ADDR_BITS + sdram::NUM_COL_ADDR_BITS)
-                    & sdram::NUM_BANK_ADDR_BITS;
-                self.io.bank = sdram::IoBank::from_index(bank_addr as _).unwrap();
+            Command::Write { addr, data, mask } => {
+                let (bank, row_addr, col_addr) = NaiveController::map_addr(addr);
+                self.io.bank = bank;
 
                 self.io.command = sdram::Command::Active;
-                let row_addr =
-                    ((element_addr >> sdram::NUM_COL_ADDR_BITS) & sdram::ROW_ADDR_MASK) as _;
-                self.io.a = row_addr;
+                self.io.a = row_addr as _;
                 for _ in 0..sdram::T_RCD_CYCLES {
                     self.sdram.clk(&mut self.io)?;
                     num_cycles += 1;
@@ -46,8 +148,10 @@ impl NaiveController {
                 }
 
                 self.io.command = sdram::Command::Write;
-                self.io.a = (element_addr & sdram::COL_ADDR_MASK) as _;
+                self.io.a = col_addr as _;
                 for i in 0..sdram::BURST_LEN {
+                    self.io.ldqm = (mask >> (i * 2)) & 1 != 0;
+                    self.io.udqm = (mask >> (i * 2 + 1)) & 1 != 0;
                     self.io.dq_in =
                         sdram::OptionalBytePair::some((data >> (i * sdram::NUM_ELEMENT_BITS)) as _);
                     self.sdram.clk(&mut self.io)?;
@@ -55,6 +159,8 @@ impl NaiveController {
                     self.io.command = sdram::Command::Nop;
                 }
 
+                self.io.ldqm = false;
+                self.io.udqm = false;
                 self.io.dq_in = sdram::OptionalBytePair::none();
                 for _ in 0..sdram::T_WR_CYCLES - 1 {
                     self.sdram.clk(&mut self.io)?;
@@ -70,16 +176,11 @@ impl NaiveController {
                 }
             }
             Command::Read { addr } => {
-                let element_addr = addr << sdram::NUM_BURST_ADDR_BITS;
-                let bank_addr = element_addr
-                    >> (sdram::NUM_ROW_ADDR_BITS + sdram::NUM_COL_ADDR_BITS)
-                    & sdram::NUM_BANK_ADDR_BITS;
-                self.io.bank = sdram::IoBank::from_index(bank_addr as _).unwrap();
+                let (bank, row_addr, col_addr) = NaiveController::map_addr(addr);
+                self.io.bank = bank;
 
                 self.io.command = sdram::Command::Active;
-                let row_addr =
-                    ((element_addr >> sdram::NUM_COL_ADDR_BITS) & sdram::ROW_ADDR_MASK) as _;
-                self.io.a = row_addr;
+                self.io.a = row_addr as _;
                 for _ in 0..sdram::T_RCD_CYCLES {
                     self.sdram.clk(&mut self.io)?;
                     num_cycles += 1;
@@ -87,7 +188,7 @@ impl NaiveController {
                 }
 
                 self.io.command = sdram::Command::Read;
-                self.io.a = (element_addr & sdram::COL_ADDR_MASK) as _;
+                self.io.a = col_addr as _;
                 for _ in 0..sdram::CAS_LATENCY {
                     self.sdram.clk(&mut self.io)?;
                     num_cycles += 1;
@@ -95,8 +196,11 @@ impl NaiveController {
                 }
                 let mut data = 0;
                 for i in 0..sdram::BURST_LEN {
-                    data |= (self.io.dq().expect("No data returned for read cycle.") as u128)
-                        << (i * sdram::NUM_ELEMENT_BITS);
+                    // Undriven read data is already reported as a violation by the SDRAM, so if
+                    //  that doesn't panic, those bytes read as zero
+                    let dq = self.io.dq();
+                    let element = dq.low.unwrap_or(0) as u128 | (dq.high.unwrap_or(0) as u128) << 8;
+                    data |= element << (i * sdram::NUM_ELEMENT_BITS);
                     self.sdram.clk(&mut self.io)?;
                     num_cycles += 1;
                 }
@@ -112,14 +216,137 @@ impl NaiveController {
             }
         }
 
+        let (Command::Write { addr, .. } | Command::Read { addr }) = command;
+        let (bank, row_addr, _) = NaiveController::map_addr(addr);
+        num_cycles += self.mitigate(bank, row_addr)?;
+
         Ok((ret_data, num_cycles))
     }
+
+    // Called once `row_addr` has been activated and precharged again. Returns the number of
+    //  cycles spent.
+    fn mitigate(&mut self, bank: sdram::IoBank, row_addr: u32) -> io::Result<u64> {
+        let Some((mitigation, cost)) = self.mitigation else {
+            return Ok(0);
+        };
+
+        self.idle(cost.decision_cycles as _)?;
+        let mut num_cycles = cost.decision_cycles as u64;
+
+        let neighbor_row_addrs = (1..=cost.blast_radius)
+            .flat_map(|distance| {
+                [
+                    row_addr.checked_sub(distance),
+                    row_addr.checked_add(distance),
+                ]
+            })
+            .flatten()
+            .filter(|&neighbor_row_addr| neighbor_row_addr < sdram::NUM_ROWS)
+            .collect::<Vec<_>>();
+        let refresh_row_addrs = match mitigation {
+            Mitigation::Para { probability, .. } => neighbor_row_addrs
+                .into_iter()
+                .filter(|_| self.rng.chance(probability))
+                .collect(),
+            Mitigation::Trr {
+                num_counters,
+                threshold,
+            } => {
+                let index = match self.trr_counters.iter().position(
+                    |&(counter_bank, counter_row_addr, _)| {
+                        counter_bank == bank && counter_row_addr == row_addr
+                    },
+                ) {
+                    Some(index) => index,
+                    None if self.trr_counters.len() < num_counters => {
+                        self.trr_counters.push((bank, row_addr, 0));
+                        self.trr_counters.len() - 1
+                    }
+                    None => {
+                        let index = (0..self.trr_counters.len())
+                            .min_by_key(|&index| self.trr_counters[index].2)
+                            .unwrap();
+                        self.trr_counters[index].0 = bank;
+                        self.trr_counters[index].1 = row_addr;
+                        index
+                    }
+                };
+                let count = &mut self.trr_counters[index].2;
+                *count += 1;
+                if *count >= threshold {
+                    *count = 0;
+                    neighbor_row_addrs
+                } else {
+                    Vec::new()
+                }
+            }
+        };
+
+        for refresh_row_addr in refresh_row_addrs {
+            num_cycles += self.refresh_row(bank, refresh_row_addr)?;
+        }
+
+        Ok(num_cycles)
+    }
+
+    // Activates and precharges a row, which restores its cells
+    fn refresh_row(&mut self, bank: sdram::IoBank, row_addr: u32) -> io::Result<u64> {
+        self.io.bank = bank;
+        self.io.command = sdram::Command::Active;
+        self.io.a = row_addr as _;
+        for _ in 0..sdram::T_RAS_MIN_CYCLES {
+            self.sdram.clk(&mut self.io)?;
+            self.io.command = sdram::Command::Nop;
+        }
+        self.io.command = sdram::Command::Precharge;
+        self.io.a = 0;
+        for _ in 0..sdram::T_RP_CYCLES {
+            self.sdram.clk(&mut self.io)?;
+            self.io.command = sdram::Command::Nop;
+        }
+        self.num_mitigation_refreshes += 1;
+
+        Ok((sdram::T_RAS_MIN_CYCLES + sdram::T_RP_CYCLES) as _)
+    }
+}
+
+impl Controller for NaiveController {
+    fn sdram(&mut self) -> &mut sdram::Sdram {
+        NaiveController::sdram(self)
+    }
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        NaiveController::idle(self, num_cycles)
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
+        NaiveController::execute(self, command)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn map_addr() {
+        // The top bits select the bank, then the row, then the burst within the row
+        let num_row_word_bits = sdram::NUM_COL_ADDR_BITS - sdram::NUM_BURST_ADDR_BITS;
+        for bank in 0..sdram::NUM_BANKS {
+            let addr = bank << (NUM_WORD_ADDR_BITS - sdram::NUM_BANK_ADDR_BITS)
+                | 7 << num_row_word_bits
+                | 5;
+            assert_eq!(
+                NaiveController::map_addr(addr),
+                (
+                    sdram::IoBank::from_index(bank as _).unwrap(),
+                    7,
+                    5 * sdram::BURST_LEN
+                )
+            );
+        }
+    }
+
     #[test]
     fn one_write() -> io::Result<()> {
         let mut c = NaiveController::new(sdram::Sdram::new(Some("NaiveController__one_write"))?);
@@ -127,6 +354,7 @@ mod tests {
         let (ret_data, num_cycles) = c.execute(Command::Write {
             addr: 0,
             data: 0xfadebabedeadbeefabad1deacafef00d,
+            mask: 0,
         })?;
         assert!(ret_data.is_none());
 
@@ -145,6 +373,7 @@ mod tests {
             let (ret_data, command_cycles) = c.execute(Command::Write {
                 addr,
                 data: 0xfadebabedeadbeefabad1deacafef00d,
+                mask: 0,
             })?;
             assert!(ret_data.is_none());
             num_cycles += command_cycles;
@@ -168,6 +397,7 @@ mod tests {
         let (ret_data, command_cycles) = c.execute(Command::Write {
             addr,
             data: expected_data,
+            mask: 0,
         })?;
         assert!(ret_data.is_none());
         num_cycles += command_cycles;
@@ -179,6 +409,8 @@ mod tests {
         );
         num_cycles += command_cycles;
 
+        assert_eq!(c.sdram().stats().num_cycles, num_cycles);
+
         println!("Test successful after {} cycles", num_cycles);
 
         Ok(())
@@ -198,6 +430,7 @@ mod tests {
             let (ret_data, command_cycles) = c.execute(Command::Write {
                 addr,
                 data: expected_data,
+                mask: 0,
             })?;
             assert!(ret_data.is_none());
             num_cycles += command_cycles;
@@ -214,4 +447,138 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn masked_write_read() -> io::Result<()> {
+        let mut c = NaiveController::new(sdram::Sdram::new(Some(
+            "NaiveController__masked_write_read",
+        ))?);
+
+        let addr = 0;
+
+        let mut num_cycles = 0;
+
+        let (_, command_cycles) = c.execute(Command::Write {
+            addr,
+            data: 0xfadebabedeadbeefabad1deacafef00d,
+            mask: 0,
+        })?;
+        num_cycles += command_cycles;
+
+        let (_, command_cycles) = c.execute(Command::Write {
+            addr,
+            data: 0x0123456789abcdef0123456789abcdef,
+            mask: 0xf0f0,
+        })?;
+        num_cycles += command_cycles;
+
+        let (ret_data, command_cycles) = c.execute(Command::Read { addr })?;
+        assert_eq!(
+            ret_data.expect("No data returned from read command."),
+            0xfadebabe89abcdefabad1dea89abcdef
+        );
+        num_cycles += command_cycles;
+
+        println!("Test successful after {} cycles", num_cycles);
+
+        Ok(())
+    }
+
+    #[test]
+    fn row_hammer_mitigations() -> io::Result<()> {
+        // Double-sided hammering of row 5 in bank 0, returning the cycles taken, the worst
+        //  disturbance any row saw and how many rows were refreshed to prevent it
+        let hammer = |mitigation| -> io::Result<(u64, u32, u64)> {
+            let mut c = NaiveController::new(sdram::Sdram::new(None)?);
+            c.set_mitigation(mitigation, MitigationCost::default());
+            for i in 0..2000 {
+                let row_addr = if i % 2 == 0 { 4 } else { 6 };
+                c.execute(Command::Write {
+                    addr: row_addr * NUM_ROW_WORDS,
+                    data: i as _,
+                    mask: 0,
+                })?;
+            }
+            let stats = c.sdram().stats();
+            Ok((
+                stats.num_cycles,
+                stats.banks[0].max_neighbor_activations,
+                c.num_mitigation_refreshes(),
+            ))
+        };
+
+        let (base_num_cycles, base_max_neighbor_activations, _) = hammer(None)?;
+        assert_eq!(base_max_neighbor_activations, 2000);
+
+        let threshold = 100;
+        for mitigation in [
+            Mitigation::Para {
+                probability: 0.05,
+                seed: 1,
+            },
+            Mitigation::Trr {
+                num_counters: 4,
+                threshold,
+            },
+        ] {
+            let (num_cycles, max_neighbor_activations, num_refreshes) = hammer(Some(mitigation))?;
+            let overhead = num_cycles as f64 / base_num_cycles as f64 - 1.0;
+            println!(
+                "{:?}: {:.1}% more cycles, {} refreshes, max neighbor ACTs {} (down from {})",
+                mitigation,
+                overhead * 100.0,
+                num_refreshes,
+                max_neighbor_activations,
+                base_max_neighbor_activations
+            );
+            assert!(overhead > 0.0 && overhead < 0.1);
+            assert!(max_neighbor_activations < base_max_neighbor_activations / 8);
+            if let Mitigation::Trr { .. } = mitigation {
+                // Each aggressor resets the victim at least every `threshold` ACTs
+                assert!(max_neighbor_activations <= 2 * threshold);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn trr_num_counters() -> io::Result<()> {
+        // Single-sided hammering of three rows, returning the worst disturbance any row saw
+        let hammer = |num_counters| -> io::Result<u32> {
+            let mut c = NaiveController::new(sdram::Sdram::new(None)?);
+            c.set_mitigation(
+                Some(Mitigation::Trr {
+                    num_counters,
+                    threshold: 30,
+                }),
+                MitigationCost {
+                    blast_radius: 2,
+                    decision_cycles: 1,
+                },
+            );
+            let mut num_cycles = 0;
+            for i in 0..300 {
+                let row_addr = 10 * (i % 3 + 1);
+                num_cycles += c
+                    .execute(Command::Write {
+                        addr: row_addr * NUM_ROW_WORDS,
+                        data: i as _,
+                        mask: 0,
+                    })?
+                    .1;
+            }
+            assert!(c.num_mitigation_refreshes() > 0);
+            assert_eq!(num_cycles, c.sdram().stats().num_cycles);
+            Ok(c.sdram().stats().banks[0].max_neighbor_activations)
+        };
+
+        // Refreshing the rows two away disturbs the nearer victims once more
+        assert_eq!(hammer(3)?, 30 + 1);
+        // With more aggressors than counters, they keep evicting each other before reaching the
+        //  threshold
+        assert_eq!(hammer(2)?, 100);
+
+        Ok(())
+    }
 }
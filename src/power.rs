@@ -0,0 +1,262 @@
+// Energy estimation from `sdram::Stats`, following the same approach as Micron's SDRAM power
+//  calculator: background power depends on whether any bank is active, and each ACT/PRE pair,
+//  burst and refresh adds energy on top of the active standby current.
+//
+// The supply voltage and IDD currents have no defaults, and have to come from the datasheet of
+//  the part being modeled.
+
+use crate::sdram;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PowerParams {
+    pub vdd_v: f64,
+    pub idd0_ma: f64,  // Active-precharge, one bank, tRC apart
+    pub idd2n_ma: f64, // Precharge standby
+    pub idd3n_ma: f64, // Active standby
+    pub idd4r_ma: f64, // Burst read
+    pub idd4w_ma: f64, // Burst write
+    pub idd5_ma: f64,  // Auto refresh, tRFC apart
+    pub idd6_ma: f64,  // Self refresh
+
+    pub clock_period_ns: f64,
+    pub t_ras_cycles: u32,
+    pub t_rc_cycles: u32,
+    pub t_rfc_cycles: u32,
+    pub burst_len: u32,
+}
+
+impl PowerParams {
+    // mA * V * ns = pJ
+    fn energy_pj(&self, current_ma: f64, num_cycles: f64) -> f64 {
+        current_ma * self.vdd_v * num_cycles * self.clock_period_ns
+    }
+
+    // Energy of a single ACT and its PRE, over and above background power
+    pub fn activate_energy_pj(&self) -> f64 {
+        let t_ras = self.t_ras_cycles as f64;
+        let t_rc = self.t_rc_cycles as f64;
+        let background_ma = (self.idd3n_ma * t_ras + self.idd2n_ma * (t_rc - t_ras)) / t_rc;
+        self.energy_pj(self.idd0_ma - background_ma, t_rc)
+    }
+}
+
+// All values in pJ
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Energy {
+    pub precharge_background: f64,
+    pub active_background: f64,
+    pub activate: f64,
+    pub read: f64,
+    pub write: f64,
+    pub refresh: f64,
+    pub self_refresh: f64,
+}
+
+impl Energy {
+    pub fn estimate(stats: &sdram::Stats, params: &PowerParams) -> Energy {
+        let num_precharged_cycles =
+            stats.num_cycles - stats.num_active_cycles - stats.num_self_refresh_cycles;
+        let (num_actives, num_reads, num_writes) =
+            stats
+                .banks
+                .iter()
+                .fold((0, 0, 0), |(actives, reads, writes), bank| {
+                    (
+                        actives + bank.num_actives,
+                        reads + bank.num_reads,
+                        writes + bank.num_writes,
+                    )
+                });
+        let burst_len = params.burst_len as f64;
+
+        Energy {
+            precharge_background: params.energy_pj(params.idd2n_ma, num_precharged_cycles as _),
+            active_background: params.energy_pj(params.idd3n_ma, stats.num_active_cycles as _),
+            activate: num_actives as f64 * params.activate_energy_pj(),
+            read: params.energy_pj(
+                params.idd4r_ma - params.idd3n_ma,
+                num_reads as f64 * burst_len,
+            ),
+            write: params.energy_pj(
+                params.idd4w_ma - params.idd3n_ma,
+                num_writes as f64 * burst_len,
+            ),
+            refresh: params.energy_pj(
+                params.idd5_ma - params.idd3n_ma,
+                (stats.num_auto_refreshes * params.t_rfc_cycles as u64) as _,
+            ),
+            self_refresh: params.energy_pj(params.idd6_ma, stats.num_self_refresh_cycles as _),
+        }
+    }
+
+    pub fn background(&self) -> f64 {
+        self.precharge_background + self.active_background
+    }
+
+    pub fn total(&self) -> f64 {
+        self.background()
+            + self.activate
+            + self.read
+            + self.write
+            + self.refresh
+            + self.self_refresh
+    }
+
+    // Average power in mW over a run of `num_cycles`
+    pub fn average_power_mw(&self, num_cycles: u64, params: &PowerParams) -> f64 {
+        if num_cycles == 0 {
+            return 0.0;
+        }
+
+        // pJ / ns = mW
+        self.total() / (num_cycles as f64 * params.clock_period_ns)
+    }
+}
+
+impl fmt::Display for Energy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "energy (pJ): {:.1}", self.total())?;
+        writeln!(
+            f,
+            "  background: {:.1} (precharged {:.1}, active {:.1})",
+            self.background(),
+            self.precharge_background,
+            self.active_background
+        )?;
+        writeln!(f, "  activate: {:.1}", self.activate)?;
+        writeln!(f, "  read: {:.1}", self.read)?;
+        writeln!(f, "  write: {:.1}", self.write)?;
+        writeln!(f, "  refresh: {:.1}", self.refresh)?;
+        writeln!(f, "  self refresh: {:.1}", self.self_refresh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::{Command, NaiveController};
+
+    use std::io;
+
+    // Round numbers rather than any real part's figures
+    fn params() -> PowerParams {
+        PowerParams {
+            vdd_v: 2.0,
+            idd0_ma: 50.0,
+            idd2n_ma: 10.0,
+            idd3n_ma: 20.0,
+            idd4r_ma: 80.0,
+            idd4w_ma: 70.0,
+            idd5_ma: 120.0,
+            idd6_ma: 1.0,
+
+            clock_period_ns: 5.0,
+            t_ras_cycles: 6,
+            t_rc_cycles: 10,
+            t_rfc_cycles: 8,
+            burst_len: 4,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn idle() {
+        let params = params();
+        let stats = sdram::Stats {
+            num_cycles: 100,
+            ..Default::default()
+        };
+
+        let energy = Energy::estimate(&stats, &params);
+        assert_close(
+            energy.precharge_background,
+            params.idd2n_ma * params.vdd_v * 100.0 * params.clock_period_ns,
+        );
+        assert_close(energy.total(), energy.precharge_background);
+        assert_close(
+            energy.average_power_mw(stats.num_cycles, &params),
+            params.idd2n_ma * params.vdd_v,
+        );
+    }
+
+    #[test]
+    fn components() {
+        let params = params();
+        let mut stats = sdram::Stats {
+            num_cycles: 1000,
+            num_active_cycles: 400,
+            num_auto_refreshes: 2,
+            num_self_refresh_cycles: 100,
+            ..Default::default()
+        };
+        stats.banks[0].num_actives = 3;
+        stats.banks[2].num_actives = 1;
+        stats.banks[0].num_reads = 5;
+        stats.banks[3].num_writes = 2;
+
+        let energy = Energy::estimate(&stats, &params);
+        assert_close(energy.precharge_background, 10.0 * 2.0 * 500.0 * 5.0);
+        assert_close(energy.active_background, 20.0 * 2.0 * 400.0 * 5.0);
+        // (50 - (20 * 6 + 10 * 4) / 10) * 2V * 10 cycles * 5ns per ACT
+        assert_close(energy.activate, 4.0 * 34.0 * 2.0 * 10.0 * 5.0);
+        assert_close(energy.read, (80.0 - 20.0) * 2.0 * 20.0 * 5.0);
+        assert_close(energy.write, (70.0 - 20.0) * 2.0 * 8.0 * 5.0);
+        assert_close(energy.refresh, (120.0 - 20.0) * 2.0 * 16.0 * 5.0);
+        assert_close(energy.self_refresh, 1.0 * 2.0 * 100.0 * 5.0);
+        assert_close(
+            energy.total(),
+            energy.background()
+                + energy.activate
+                + energy.read
+                + energy.write
+                + energy.refresh
+                + energy.self_refresh,
+        );
+    }
+
+    #[test]
+    fn naive_controller() -> io::Result<()> {
+        let mut c = NaiveController::new(sdram::Sdram::new(Some("Power__naive_controller"))?);
+
+        let params = PowerParams {
+            clock_period_ns: sdram::CLOCK_PERIOD_NS as _,
+            t_ras_cycles: sdram::T_RAS_MIN_CYCLES,
+            t_rc_cycles: sdram::T_RC_CYCLES,
+            t_rfc_cycles: sdram::T_RFC_CYCLES,
+            burst_len: sdram::BURST_LEN,
+            ..params()
+        };
+
+        c.execute(Command::Write {
+            addr: 0,
+            data: 0xfadebabedeadbeefabad1deacafef00d,
+            mask: 0,
+        })?;
+        let write_energy = Energy::estimate(&c.sdram().stats(), &params);
+        assert!(write_energy.activate > 0.0);
+        assert!(write_energy.write > 0.0);
+        assert_close(write_energy.read, 0.0);
+
+        c.sdram().reset_stats();
+        c.execute(Command::Read { addr: 0 })?;
+        let read_energy = Energy::estimate(&c.sdram().stats(), &params);
+        assert_close(read_energy.activate, write_energy.activate);
+        assert!(read_energy.read > 0.0);
+        assert_close(read_energy.write, 0.0);
+
+        println!("{}", read_energy);
+
+        Ok(())
+    }
+}
@@ -2,12 +2,17 @@
 //  8M x 16bits x 4 banks (64MBytes)
 //  Assumes 166MHz operation
 
-extern crate vcd;
+use crate::coverage::{self, Coverage};
+use crate::fault::{Fault, FaultId, Faults, FaultyRead};
+use crate::rng::Rng;
+use crate::timing::{Event, MaxDelay, MinDelay, Scope, TimingChecker};
+use crate::trace::{BankState, Burst, TraceCycle, TraceOptions, TraceSink, VcdSink};
 
-use std::{fs, io};
+use std::collections::BTreeSet;
+use std::{fmt, io};
 
 pub const NUM_ELEMENT_BITS: u32 = 16;
-pub const ELEMENT_MASK: u32 = 1 << NUM_ELEMENT_BITS - 1;
+pub const ELEMENT_MASK: u32 = (1 << NUM_ELEMENT_BITS) - 1;
 pub const NUM_ROW_ADDR_BITS: u32 = 13;
 pub const NUM_COL_ADDR_BITS: u32 = 10;
 pub const NUM_ROWS: u32 = 1 << NUM_ROW_ADDR_BITS;
@@ -22,429 +27,500 @@ pub const CAS_LATENCY: u32 = 3;
 pub const NUM_BURST_ADDR_BITS: u32 = 3;
 pub const BURST_LEN: u32 = 1 << NUM_BURST_ADDR_BITS; // 128-bit effective word size
 
-const fn div_ceil(x: u32, y: u32) -> u32 {
-    (x + (y - 1)) / y
-}
-
-const CLOCK_PERIOD_NS: u32 = 6;
+pub const CLOCK_PERIOD_NS: u32 = 6;
 
-// TODO: Perhaps parameterize this and/or fix test scaling problems another way
-const T_REF_US: u32 = 1; //64_000;
+const T_REF_US: u32 = 64_000;
 const T_REF_NS: u32 = T_REF_US * 1_000;
-const T_REF_CYCLES: u32 = div_ceil(T_REF_NS, CLOCK_PERIOD_NS);
+pub const T_REF_CYCLES: u32 = T_REF_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RAS_MIN_NS: u32 = 48;
-const T_RAS_MIN_CYCLES: u32 = div_ceil(T_RAS_MIN_NS, CLOCK_PERIOD_NS);
+pub const T_RAS_MIN_CYCLES: u32 = T_RAS_MIN_NS.div_ceil(CLOCK_PERIOD_NS);
 const T_RAS_MAX_NS: u32 = 100000;
-const T_RAS_MAX_CYCLES: u32 = div_ceil(T_RAS_MAX_NS, CLOCK_PERIOD_NS);
+const T_RAS_MAX_CYCLES: u32 = T_RAS_MAX_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RC_NS: u32 = 60;
-const T_RC_CYCLES: u32 = div_ceil(T_RC_NS, CLOCK_PERIOD_NS);
+pub const T_RC_CYCLES: u32 = T_RC_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RCD_NS: u32 = 18;
-pub const T_RCD_CYCLES: u32 = div_ceil(T_RCD_NS, CLOCK_PERIOD_NS);
+pub const T_RCD_CYCLES: u32 = T_RCD_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RP_NS: u32 = 18;
-pub const T_RP_CYCLES: u32 = div_ceil(T_RP_NS, CLOCK_PERIOD_NS);
+pub const T_RP_CYCLES: u32 = T_RP_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_WR_NS: u32 = 15;
 // TODO: For auto-precharge, make sure this is always at least 1
-pub const T_WR_CYCLES: u32 = div_ceil(T_WR_NS, CLOCK_PERIOD_NS);
+pub const T_WR_CYCLES: u32 = T_WR_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RRD_NS: u32 = 12;
-const T_RRD_CYCLES: u32 = div_ceil(T_RRD_NS, CLOCK_PERIOD_NS);
+pub const T_RRD_CYCLES: u32 = T_RRD_NS.div_ceil(CLOCK_PERIOD_NS);
 
 const T_RFC_NS: u32 = 80;
-const T_RFC_CYCLES: u32 = div_ceil(T_RFC_NS, CLOCK_PERIOD_NS);
+pub const T_RFC_CYCLES: u32 = T_RFC_NS.div_ceil(CLOCK_PERIOD_NS);
+
+pub const T_CCD_CYCLES: u32 = 1;
+// Last write data in to READ command (tCDL on SDR datasheets)
+pub const T_WTR_CYCLES: u32 = 1;
+// Last write data in to ACTIVE when the write auto precharges
+pub const T_DAL_CYCLES: u32 = T_WR_CYCLES + T_RP_CYCLES;
+pub const T_MRD_CYCLES: u32 = 2;
+
+const T_XSR_NS: u32 = 120;
+pub const T_XSR_CYCLES: u32 = T_XSR_NS.div_ceil(CLOCK_PERIOD_NS);
 
 pub const T_DQZ_CYCLES: u32 = 2;
 
+// Tracks the refresh deadline of every row in a bank, so only the earliest one needs to be
+//  tested each cycle
 #[derive(Clone)]
 struct TRefTester {
-    is_active: bool,
-    cycles_since_activation: u32,
+    num_cycles: u32,
+
+    deadlines: BTreeSet<(u64, usize)>,
+    row_deadlines: Box<[Option<u64>]>,
 }
 
 impl TRefTester {
-    fn new() -> TRefTester {
+    fn new(num_cycles: u32) -> TRefTester {
         TRefTester {
-            is_active: false,
-            cycles_since_activation: 0,
+            num_cycles,
+
+            deadlines: BTreeSet::new(),
+            row_deadlines: vec![None; NUM_ROWS as usize].into(),
         }
     }
 
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
+    fn clk(&mut self, cycle: u64) -> Result<(), ViolationKind> {
+        match self.deadlines.first() {
+            Some(&(deadline, row_addr)) if cycle >= deadline => {
+                // Only report each missed deadline once
+                self.remove(row_addr);
+                Err(ViolationKind::TRef)
+            }
+            _ => Ok(()),
         }
+    }
 
-        self.cycles_since_activation += 1;
-
-        if self.cycles_since_activation >= T_REF_CYCLES {
-            panic!("tREF violated.");
-        }
+    fn active(&mut self, row_addr: usize) {
+        self.remove(row_addr);
     }
 
-    fn active(&mut self) {
-        self.is_active = false;
+    fn auto_refresh(&mut self, row_addr: usize, cycle: u64) {
+        self.precharge(row_addr, cycle);
     }
 
-    fn auto_refresh(&mut self) {
-        self.precharge();
+    fn precharge(&mut self, row_addr: usize, cycle: u64) {
+        self.remove(row_addr);
+
+        let deadline = cycle + self.num_cycles as u64;
+        self.row_deadlines[row_addr] = Some(deadline);
+        self.deadlines.insert((deadline, row_addr));
     }
 
-    fn precharge(&mut self) {
-        self.is_active = true;
-        self.cycles_since_activation = 0;
+    fn remove(&mut self, row_addr: usize) {
+        if let Some(deadline) = self.row_deadlines[row_addr].take() {
+            self.deadlines.remove(&(deadline, row_addr));
+        }
     }
 }
 
 #[derive(Clone)]
 struct Row {
     cols: Box<[OptionalBytePair]>,
-
-    t_ref_tester: TRefTester,
 }
 
 impl Row {
     fn new() -> Row {
         Row {
             cols: vec![OptionalBytePair::none(); NUM_COLS as usize].into(),
-
-            t_ref_tester: TRefTester::new(),
         }
     }
-
-    fn active(&mut self) {
-        self.t_ref_tester.active();
-    }
-
-    fn auto_refresh(&mut self) {
-        self.t_ref_tester.auto_refresh();
-    }
-
-    fn precharge(&mut self) {
-        self.t_ref_tester.precharge();
-    }
-
-    fn clk(&mut self) {
-        self.t_ref_tester.clk();
-    }
 }
 
 #[derive(Clone)]
-struct TRasTester {
-    is_active: bool,
-    cycles_since_activation: u32,
-}
-
-impl TRasTester {
-    fn new() -> TRasTester {
-        TRasTester {
-            is_active: false,
-            cycles_since_activation: 0,
-        }
-    }
-
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
+struct Bank {
+    index: IoBank,
 
-        self.cycles_since_activation += 1;
+    rows: Box<[Row]>,
+    active_row: Option<usize>,
+    last_active_row: Option<usize>,
 
-        // The datasheet claims a row can be active for an "indefinite period" after tRAS
-        //  min is met, but it still lists a max value, and hitting that is probably
-        //  indicative of a refresh logic error anyways, so let's still test for it.
-        if self.cycles_since_activation >= T_RAS_MAX_CYCLES {
-            panic!("tRAS max violated.");
-        }
-    }
+    // Activations of each row since it was last refreshed
+    num_activations: Box<[u32]>,
+    // Activations of each row's neighbors since it was last refreshed or activated itself, which
+    //  is what disturbs its cells
+    num_neighbor_activations: Box<[u32]>,
 
-    fn active(&mut self) {
-        self.is_active = true;
-        self.cycles_since_activation = 0;
-    }
+    t_ref_tester: TRefTester,
+}
 
-    fn precharge(&mut self) {
-        if self.cycles_since_activation < T_RAS_MIN_CYCLES {
-            panic!("tRAS min violated.");
-        }
+impl Bank {
+    fn new(index: IoBank) -> Bank {
+        Bank {
+            index,
 
-        self.is_active = false;
-    }
-}
+            rows: vec![Row::new(); NUM_ROWS as usize].into(),
+            active_row: None,
+            last_active_row: None,
 
-#[derive(Clone)]
-struct TRcTester {
-    is_active: bool,
-    cycles_since_activation: u32,
-}
+            num_activations: vec![0; NUM_ROWS as usize].into(),
+            num_neighbor_activations: vec![0; NUM_ROWS as usize].into(),
 
-impl TRcTester {
-    fn new() -> TRcTester {
-        TRcTester {
-            is_active: false,
-            cycles_since_activation: 0,
+            t_ref_tester: TRefTester::new(T_REF_CYCLES),
         }
     }
 
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
+    fn active(&mut self, row_addr: u32) {
+        self.active_row = Some(row_addr as _);
+        self.last_active_row = self.active_row;
+        self.t_ref_tester.active(row_addr as _);
 
-        if self.cycles_since_activation >= T_RC_CYCLES {
-            self.is_active = false;
+        // Activating a row restores its own charge, but disturbs the rows next to it
+        self.num_activations[row_addr as usize] += 1;
+        self.num_neighbor_activations[row_addr as usize] = 0;
+        for neighbor_row_addr in neighbor_row_addrs(row_addr) {
+            self.num_neighbor_activations[neighbor_row_addr as usize] += 1;
         }
     }
 
-    fn active(&mut self) {
-        if self.is_active {
-            panic!("tRC violated.");
-        }
-
-        self.is_active = true;
-        self.cycles_since_activation = 0;
+    fn auto_refresh(&mut self, row_addr: u32, cycle: u64) {
+        self.t_ref_tester.auto_refresh(row_addr as _, cycle);
+        self.num_activations[row_addr as usize] = 0;
+        self.num_neighbor_activations[row_addr as usize] = 0;
     }
-}
 
-#[derive(Clone)]
-struct TRcdTester {
-    is_active: bool,
-    cycles_since_activation: u32,
-}
-
-impl TRcdTester {
-    fn new() -> TRcdTester {
-        TRcdTester {
-            is_active: false,
-            cycles_since_activation: 0,
+    fn refresh_all(&mut self, cycle: u64) {
+        for row_addr in 0..NUM_ROWS {
+            self.auto_refresh(row_addr, cycle);
         }
     }
 
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
-
-        if self.cycles_since_activation >= T_RCD_CYCLES {
-            self.is_active = false;
+    fn precharge(&mut self, cycle: u64) {
+        if let Some(active_row) = self.active_row.take() {
+            self.t_ref_tester.precharge(active_row, cycle);
         }
     }
 
-    fn active(&mut self) {
-        self.is_active = true;
-        self.cycles_since_activation = 0;
+    fn read(&self, col_addr: u32) -> Option<OptionalBytePair> {
+        let active_row = self.active_row?;
+        Some(self.rows[active_row].cols[col_addr as usize])
     }
 
-    fn read_or_write(&self) {
-        if !self.is_active {
-            return;
+    fn write(&mut self, col_addr: u32, data: OptionalBytePair) {
+        if let Some(active_row) = self.active_row {
+            self.rows[active_row].cols[col_addr as usize].replace(data);
         }
+    }
 
-        panic!("tRCD violated.");
+    fn clk(&mut self, violations: &mut Violations) {
+        violations.check(self.t_ref_tester.clk(violations.cycle), Some(self.index));
     }
 }
 
-#[derive(Clone)]
-struct TRpTester {
-    is_active: bool,
-    cycles_since_activation: u32,
+// Rows physically next to `row_addr`, assuming they're laid out in address order
+fn neighbor_row_addrs(row_addr: u32) -> impl Iterator<Item = u32> {
+    [
+        row_addr.checked_sub(1),
+        Some(row_addr + 1).filter(|&row_addr| row_addr < NUM_ROWS),
+    ]
+    .into_iter()
+    .flatten()
 }
 
-impl TRpTester {
-    fn new() -> TRpTester {
-        TRpTester {
-            is_active: false,
-            cycles_since_activation: 0,
-        }
-    }
-
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
+// Row hammer: once a row's neighbors have been activated more than `threshold` times since it
+//  was last refreshed (or activated itself), each further activation of a neighbor flips a random
+//  bit in it with probability `flip_probability`. Bits in uninitialized bytes never flip.
+#[derive(Clone, Copy, Debug)]
+pub struct RowHammerParams {
+    pub threshold: u32,
+    pub flip_probability: f64,
+    pub seed: u64,
+}
 
-        if self.cycles_since_activation >= T_RP_CYCLES {
-            self.is_active = false;
+impl Default for RowHammerParams {
+    fn default() -> RowHammerParams {
+        RowHammerParams {
+            threshold: 50_000,
+            flip_probability: 0.01,
+            seed: 0,
         }
     }
+}
 
-    fn precharge(&mut self) {
-        self.test();
-
-        self.is_active = true;
-        self.cycles_since_activation = 0;
-    }
-
-    fn active_or_read_or_write(&self) {
-        self.test();
-    }
-
-    fn test(&self) {
-        if !self.is_active {
-            return;
-        }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitFlip {
+    pub cycle: u64,
+    pub bank: IoBank,
+    pub row_addr: u32,
+    pub col_addr: u32,
+    // 0-15, as on DQ
+    pub bit: u32,
+}
 
-        panic!("tRP violated.");
+impl fmt::Display for BitFlip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycle {}: bank {}: bit {} of row 0x{:04x} col 0x{:03x} flipped.",
+            self.cycle,
+            self.bank.index(),
+            self.bit,
+            self.row_addr,
+            self.col_addr
+        )
     }
 }
 
-#[derive(Clone)]
-struct TWrTester {
-    is_active: bool,
-    cycles_since_activation: u32,
+struct RowHammer {
+    params: RowHammerParams,
+    rng: Rng,
+    bit_flips: Vec<BitFlip>,
 }
 
-impl TWrTester {
-    fn new() -> TWrTester {
-        TWrTester {
-            is_active: false,
-            cycles_since_activation: 0,
+impl RowHammer {
+    fn new(params: RowHammerParams) -> RowHammer {
+        RowHammer {
+            params,
+            rng: Rng::new(params.seed),
+            bit_flips: Vec::new(),
         }
     }
 
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
-
-        if self.cycles_since_activation >= T_WR_CYCLES {
-            self.is_active = false;
-        }
-    }
+    // Called right after `row_addr` is activated in `bank`
+    fn disturb(&mut self, bank: &mut Bank, row_addr: u32, cycle: u64) {
+        for victim_row_addr in neighbor_row_addrs(row_addr) {
+            if bank.num_neighbor_activations[victim_row_addr as usize] <= self.params.threshold
+                || !self.rng.chance(self.params.flip_probability)
+            {
+                continue;
+            }
 
-    fn precharge(&self) {
-        if !self.is_active {
-            return;
+            let col_addr = self.rng.below(NUM_COLS as _) as u32;
+            let bit = self.rng.below(NUM_ELEMENT_BITS as _) as u32;
+            let col = &mut bank.rows[victim_row_addr as usize].cols[col_addr as usize];
+            let byte = if bit < 8 { &mut col.low } else { &mut col.high };
+            if let Some(byte) = byte {
+                *byte ^= 1 << (bit % 8);
+                self.bit_flips.push(BitFlip {
+                    cycle,
+                    bank: bank.index,
+                    row_addr: victim_row_addr,
+                    col_addr,
+                    bit,
+                });
+            }
         }
-
-        panic!("tWR violated.");
-    }
-
-    fn write(&mut self) {
-        self.is_active = true;
-        self.cycles_since_activation = 0;
     }
 }
 
-#[derive(Clone)]
-struct Bank {
-    rows: Box<[Row]>,
-    active_row: Option<usize>,
-
-    t_ras_tester: TRasTester,
-    t_rc_tester: TRcTester,
-    t_rcd_tester: TRcdTester,
-    t_rp_tester: TRpTester,
-    t_wr_tester: TWrTester,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    TRef,
+    TRasMin,
+    TRasMax,
+    TRc,
+    TRcd,
+    TRp,
+    TWr,
+    TRrd,
+    TRfc,
+    TCcd,
+    TWtr,
+    TDal,
+    TMrd,
+    TXsr,
+    ReadToPrecharge,
+    ReadToWrite,
+    ActiveWithActiveRow,
+    AutoRefreshWithActiveRow,
+    LoadModeRegisterWithActiveRow,
+    ReadWithoutActiveRow,
+    WriteWithoutActiveRow,
+    MissingWriteData,
+    UninitializedRead,
+    DqBusConflict,
+    // Reported by a `TimingChecker` registered with `Sdram::add_timing_checker`
+    Custom(&'static str),
 }
 
-impl Bank {
-    fn new() -> Bank {
-        Bank {
-            rows: vec![Row::new(); NUM_ROWS as usize].into(),
-            active_row: None,
-
-            t_ras_tester: TRasTester::new(),
-            t_rc_tester: TRcTester::new(),
-            t_rcd_tester: TRcdTester::new(),
-            t_rp_tester: TRpTester::new(),
-            t_wr_tester: TWrTester::new(),
+impl ViolationKind {
+    // Short name without whitespace, as used in traces
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViolationKind::TRef => "tREF",
+            ViolationKind::TRasMin => "tRAS_min",
+            ViolationKind::TRasMax => "tRAS_max",
+            ViolationKind::TRc => "tRC",
+            ViolationKind::TRcd => "tRCD",
+            ViolationKind::TRp => "tRP",
+            ViolationKind::TWr => "tWR",
+            ViolationKind::TRrd => "tRRD",
+            ViolationKind::TRfc => "tRFC",
+            ViolationKind::TCcd => "tCCD",
+            ViolationKind::TWtr => "tWTR",
+            ViolationKind::TDal => "tDAL",
+            ViolationKind::TMrd => "tMRD",
+            ViolationKind::TXsr => "tXSR",
+            ViolationKind::ReadToPrecharge => "read_to_precharge",
+            ViolationKind::ReadToWrite => "read_to_write",
+            ViolationKind::ActiveWithActiveRow => "active_with_active_row",
+            ViolationKind::AutoRefreshWithActiveRow => "auto_refresh_with_active_row",
+            ViolationKind::LoadModeRegisterWithActiveRow => "load_mode_register_with_active_row",
+            ViolationKind::ReadWithoutActiveRow => "read_without_active_row",
+            ViolationKind::WriteWithoutActiveRow => "write_without_active_row",
+            ViolationKind::MissingWriteData => "missing_write_data",
+            ViolationKind::UninitializedRead => "uninitialized_read",
+            ViolationKind::DqBusConflict => "dq_bus_conflict",
+            ViolationKind::Custom(name) => name,
         }
     }
+}
 
-    fn active(&mut self, row_addr: u32) {
-        if self.active_row.is_some() {
-            panic!("Attempted to activate a row in a bank which already has an active row.");
-        }
-
-        self.active_row = Some(row_addr as _);
-        self.rows[row_addr as usize].active();
-
-        self.t_ras_tester.active();
-        self.t_rc_tester.active();
-        self.t_rcd_tester.active();
-        self.t_rp_tester.active_or_read_or_write();
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ViolationKind::TRef => "tREF violated.",
+            ViolationKind::TRasMin => "tRAS min violated.",
+            ViolationKind::TRasMax => "tRAS max violated.",
+            ViolationKind::TRc => "tRC violated.",
+            ViolationKind::TRcd => "tRCD violated.",
+            ViolationKind::TRp => "tRP violated.",
+            ViolationKind::TWr => "tWR violated.",
+            ViolationKind::TRrd => "tRRD violated.",
+            ViolationKind::TRfc => "tRFC violated.",
+            ViolationKind::TCcd => "tCCD violated.",
+            ViolationKind::TWtr => "tWTR violated.",
+            ViolationKind::TDal => "tDAL violated.",
+            ViolationKind::TMrd => "tMRD violated.",
+            ViolationKind::TXsr => "tXSR violated.",
+            ViolationKind::ReadToPrecharge => {
+                "Attempted to precharge a bank before the end of its read burst."
+            }
+            ViolationKind::ReadToWrite => {
+                "Attempted to write while read data would still be driven onto the DQ bus."
+            }
+            ViolationKind::ActiveWithActiveRow => {
+                "Attempted to activate a row in a bank which already has an active row."
+            }
+            ViolationKind::AutoRefreshWithActiveRow => {
+                "Attempted to auto refresh a row in a bank which has an active row."
+            }
+            ViolationKind::LoadModeRegisterWithActiveRow => {
+                "Attempted to load the mode register while a bank has an active row."
+            }
+            ViolationKind::ReadWithoutActiveRow => {
+                "Attempted to read from a column in a bank which does not currently have an active row."
+            }
+            ViolationKind::WriteWithoutActiveRow => {
+                "Attempted to write to a column in a bank which does not currently have an active row."
+            }
+            ViolationKind::MissingWriteData => "No data provided for write cycle.",
+            ViolationKind::UninitializedRead => "Attempted to read from an uninitialized column.",
+            ViolationKind::DqBusConflict => "DQ bus conflict occurred.",
+            ViolationKind::Custom(name) => return write!(f, "{} violated.", name),
+        })
     }
+}
 
-    fn auto_refresh(&mut self, row_addr: u32) {
-        if self.active_row.is_some() {
-            // TODO: Test(s)
-            panic!("Attempted to auto refresh a row in a bank which has an active row.");
-        }
-
-        self.rows[row_addr as usize].auto_refresh();
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub cycle: u64,
+    pub kind: ViolationKind,
+    pub bank: Option<IoBank>,
+}
 
-    fn precharge(&mut self) {
-        if self.active_row.is_none() {
-            return;
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycle {}: ", self.cycle)?;
+        if let Some(bank) = self.bank {
+            write!(f, "bank {}: ", bank.index())?;
         }
-
-        self.rows[self.active_row.unwrap()].precharge();
-        self.active_row = None;
-
-        self.t_ras_tester.precharge();
-        self.t_rp_tester.precharge();
-        self.t_wr_tester.precharge();
+        write!(f, "{}", self.kind)
     }
+}
 
-    fn read(&mut self, col_addr: u32) -> OptionalBytePair {
-        self.t_rcd_tester.read_or_write();
-        self.t_rp_tester.active_or_read_or_write();
+// Violations which occurred during a single cycle
+pub struct Violations {
+    cycle: u64,
+    list: Vec<Violation>,
+}
 
-        // TODO: Test(s)
-        let active_row = self.active_row.expect("Attempted to read from a column in a bank which does not currently have an active row.");
-        self.rows[active_row as usize].cols[col_addr as usize]
+impl Violations {
+    fn new(cycle: u64) -> Violations {
+        Violations {
+            cycle,
+            list: Vec::new(),
+        }
     }
 
-    fn write(&mut self, col_addr: u32, data: OptionalBytePair) {
-        self.t_rcd_tester.read_or_write();
-        self.t_rp_tester.active_or_read_or_write();
-        self.t_wr_tester.write();
-
-        // TODO: Test(s)
-        let active_row = self.active_row.expect(
-            "Attempted to write to a column in a bank which does not currently have an active row.",
-        );
-        self.rows[active_row as usize].cols[col_addr as usize].replace(data);
+    pub fn push(&mut self, kind: ViolationKind, bank: Option<IoBank>) {
+        self.list.push(Violation {
+            cycle: self.cycle,
+            kind,
+            bank,
+        });
     }
 
-    fn clk(&mut self) {
-        for row in &mut *self.rows {
-            row.clk();
+    fn check(&mut self, result: Result<(), ViolationKind>, bank: Option<IoBank>) {
+        if let Err(kind) = result {
+            self.push(kind, bank);
         }
-
-        self.t_ras_tester.clk();
-        self.t_rc_tester.clk();
-        self.t_rcd_tester.clk();
-        self.t_rp_tester.clk();
-        self.t_wr_tester.clk();
     }
 }
 
-// TODO: Add LoadModeRegister command
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Command {
     Active,
+    // Enters self refresh instead if CKE is low
     AutoRefresh,
+    LoadModeRegister,
     Nop,
     Precharge,
+    // Auto precharges once the burst is done if A10 is set
     Read,
     Write,
 }
 
-#[derive(Clone, Copy)]
+impl Command {
+    // In the order used by command logs, so new commands go at the end
+    pub const ALL: [Command; 7] = [
+        Command::Active,
+        Command::AutoRefresh,
+        Command::Nop,
+        Command::Precharge,
+        Command::Read,
+        Command::Write,
+        Command::LoadModeRegister,
+    ];
+
+    // Levels as they appear on the (active low) control pins
+    pub fn pins(&self) -> ControlPins {
+        let (ras_n, cas_n, we_n) = match self {
+            Command::Active => (false, true, true),
+            Command::AutoRefresh => (false, false, true),
+            Command::LoadModeRegister => (false, false, false),
+            Command::Nop => (true, true, true),
+            Command::Precharge => (false, true, false),
+            Command::Read => (true, false, true),
+            Command::Write => (true, false, false),
+        };
+        ControlPins {
+            cs_n: false,
+            ras_n,
+            cas_n,
+            we_n,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlPins {
+    pub cs_n: bool,
+    pub ras_n: bool,
+    pub cas_n: bool,
+    pub we_n: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IoBank {
     Bank0,
     Bank1,
@@ -463,7 +539,7 @@ impl IoBank {
         }
     }
 
-    fn index(&self) -> usize {
+    pub fn index(&self) -> usize {
         match *self {
             IoBank::Bank0 => 0,
             IoBank::Bank1 => 1,
@@ -474,6 +550,7 @@ impl IoBank {
 }
 
 // TODO: More specific name?
+#[derive(Clone, Copy)]
 enum State {
     Idle,
     Read { bank: IoBank, num_cycles: u32 },
@@ -486,7 +563,7 @@ struct Dqm {
     udqm: bool,
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct OptionalBytePair {
     pub low: Option<u8>,
     pub high: Option<u8>,
@@ -538,6 +615,9 @@ impl OptionalBytePair {
 
 pub struct Io {
     pub command: Command,
+    // Only used to enter and leave self refresh
+    // TODO: Power-down and clock suspend
+    pub cke: bool,
     pub ldqm: bool,
     pub udqm: bool,
     pub bank: IoBank,
@@ -546,10 +626,17 @@ pub struct Io {
     dq_out: OptionalBytePair,
 }
 
+impl Default for Io {
+    fn default() -> Io {
+        Io::new()
+    }
+}
+
 impl Io {
     pub fn new() -> Io {
         Io {
             command: Command::Nop,
+            cke: true,
             ldqm: false,
             udqm: false,
             bank: IoBank::Bank0,
@@ -560,413 +647,840 @@ impl Io {
     }
 
     pub fn dq(&self) -> OptionalBytePair {
-        self.check_dq_bus_conflict();
-
-        self.dq_in.or(self.dq_out)
-    }
-
-    fn check_dq_bus_conflict(&self) {
-        if (self.dq_in.low.is_some() && self.dq_out.low.is_some())
-            || (self.dq_in.high.is_some() && self.dq_out.high.is_some())
-        {
+        if self.has_dq_bus_conflict() {
             // TODO: Test(s)
-            panic!("DQ bus conflict occurred.");
+            panic!("{}", ViolationKind::DqBusConflict);
         }
-    }
-}
-
-struct TRrdTester {
-    is_active: bool,
-    cycles_since_activation: u32,
-}
 
-impl TRrdTester {
-    fn new() -> TRrdTester {
-        TRrdTester {
-            is_active: false,
-            cycles_since_activation: 0,
-        }
+        self.dq_in.or(self.dq_out)
     }
 
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
-
-        if self.cycles_since_activation >= T_RRD_CYCLES {
-            self.is_active = false;
-        }
+    // Data driven by the SDRAM only
+    pub fn dq_out(&self) -> OptionalBytePair {
+        self.dq_out
     }
 
-    fn active(&mut self) {
-        if self.is_active {
-            panic!("tRRD violated.");
-        }
-
-        self.is_active = true;
-        self.cycles_since_activation = 0;
+    fn has_dq_bus_conflict(&self) -> bool {
+        (self.dq_in.low.is_some() && self.dq_out.low.is_some())
+            || (self.dq_in.high.is_some() && self.dq_out.high.is_some())
     }
 }
 
-struct TRfcTester {
-    is_active: bool,
-    cycles_since_activation: u32,
+fn is_active(event: &Event) -> bool {
+    matches!(event, Event::Active { .. })
 }
 
-impl TRfcTester {
-    fn new() -> TRfcTester {
-        TRfcTester {
-            is_active: false,
-            cycles_since_activation: 0,
-        }
-    }
-
-    fn clk(&mut self) {
-        if !self.is_active {
-            return;
-        }
-
-        self.cycles_since_activation += 1;
-
-        if self.cycles_since_activation >= T_RFC_CYCLES {
-            self.is_active = false;
-        }
-    }
-
-    fn any_command_except_auto_refresh_and_nop(&self) {
-        self.test();
-    }
-
-    fn auto_refresh(&mut self) {
-        self.test();
-
-        self.is_active = true;
-        self.cycles_since_activation = 0;
-    }
-
-    fn test(&self) {
-        if !self.is_active {
-            return;
-        }
-
-        panic!("tRFC violated.");
-    }
+fn is_precharge(event: &Event) -> bool {
+    matches!(event, Event::Precharge { .. })
 }
 
-struct ScalarSignal {
-    value: Option<bool>,
-    id: vcd::IdCode,
+fn is_auto_precharge(event: &Event) -> bool {
+    matches!(event, Event::AutoPrecharge { .. })
 }
 
-impl ScalarSignal {
-    fn new(reference: &str, w: &mut vcd::Writer<impl io::Write>) -> io::Result<ScalarSignal> {
-        let id = w.add_wire(1, reference)?;
-        Ok(ScalarSignal { value: None, id })
-    }
+fn is_read_or_write(event: &Event) -> bool {
+    matches!(event, Event::Read { .. } | Event::Write { .. })
+}
 
-    fn update(&mut self, value: bool, w: &mut vcd::Writer<impl io::Write>) -> io::Result<()> {
-        if self.value.map_or(false, |x| x == value) {
-            return Ok(());
-        }
+fn is_write(event: &Event) -> bool {
+    matches!(event, Event::Write { .. })
+}
 
-        w.change_scalar(self.id, value)?;
-        self.value = Some(value);
+fn is_command(event: &Event) -> bool {
+    matches!(event, Event::Command(_))
+}
 
-        Ok(())
-    }
+fn is_read_or_write_command(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Command(Command::Read) | Event::Command(Command::Write)
+    )
 }
 
-struct VectorSignal {
-    width: u32,
-    value: Option<Box<[vcd::Value]>>,
-    id: vcd::IdCode,
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BankStats {
+    pub num_actives: u64,
+    pub num_reads: u64,
+    pub num_writes: u64,
+    pub num_precharges: u64,
+    // Inferred from ACT patterns: activating the same row that was last active in the bank is
+    //  a hit (an open-page policy could have avoided the ACT and its precharge altogether),
+    //  while activating any other row is a miss
+    pub num_row_hits: u64,
+    pub num_row_misses: u64,
+    // Most times any one row was activated between refreshes
+    pub max_row_activations: u32,
+    // Most times any one row's neighbors were activated before it was refreshed (or activated
+    //  itself), ie. the worst row hammer disturbance
+    pub max_neighbor_activations: u32,
 }
 
-impl VectorSignal {
-    fn new(
-        width: u32,
-        reference: &str,
-        w: &mut vcd::Writer<impl io::Write>,
-    ) -> io::Result<VectorSignal> {
-        let id = w.add_wire(width, reference)?;
-        Ok(VectorSignal {
-            width,
-            value: None,
-            id,
-        })
-    }
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub num_cycles: u64,
+    // Cycles which started with at least one bank active; all banks were precharged for the rest
+    pub num_active_cycles: u64,
+    // Cycles where any DQ byte carried valid data, in either direction
+    pub num_data_cycles: u64,
+    // Cycles with a NOP and nothing on DQ
+    pub num_idle_cycles: u64,
+    // Cycles which started in self refresh; commands are ignored (and not counted) during these
+    pub num_self_refresh_cycles: u64,
+
+    // Commands which don't target a single bank
+    pub num_nops: u64,
+    pub num_auto_refreshes: u64,
+    pub num_precharge_alls: u64,
+
+    pub banks: [BankStats; NUM_BANKS as usize],
+}
 
-    fn update(
-        &mut self,
-        value: Box<[vcd::Value]>,
-        w: &mut vcd::Writer<impl io::Write>,
-    ) -> io::Result<()> {
-        assert_eq!(self.width, value.len() as _);
-        if self.value.as_ref().map_or(false, |x| *x == value) {
-            return Ok(());
+impl Stats {
+    pub fn bus_utilization(&self) -> f64 {
+        if self.num_cycles == 0 {
+            return 0.0;
         }
 
-        w.change_vector(self.id, &value)?;
-        self.value = Some(value);
-
-        Ok(())
+        self.num_data_cycles as f64 / self.num_cycles as f64
     }
-}
 
-struct StringSignal {
-    value: Option<String>,
-    id: vcd::IdCode,
+    pub fn num_commands(&self) -> u64 {
+        self.num_nops
+            + self.num_auto_refreshes
+            + self.num_precharge_alls
+            + self
+                .banks
+                .iter()
+                .map(|bank| {
+                    bank.num_actives + bank.num_reads + bank.num_writes + bank.num_precharges
+                })
+                .sum::<u64>()
+    }
 }
 
-impl StringSignal {
-    fn new(
-        width: u32,
-        reference: &str,
-        w: &mut vcd::Writer<impl io::Write>,
-    ) -> io::Result<StringSignal> {
-        let id = w.add_var(vcd::VarType::String, width, reference, None)?;
-        Ok(StringSignal { value: None, id })
-    }
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |x: u64| {
+            if self.num_cycles == 0 {
+                0.0
+            } else {
+                x as f64 * 100.0 / self.num_cycles as f64
+            }
+        };
 
-    fn update(&mut self, value: String, w: &mut vcd::Writer<impl io::Write>) -> io::Result<()> {
-        if self.value.as_ref().map_or(false, |x| *x == value) {
-            return Ok(());
+        writeln!(f, "cycles: {}", self.num_cycles)?;
+        writeln!(
+            f,
+            "  active: {} ({:.1}%)",
+            self.num_active_cycles,
+            percent(self.num_active_cycles)
+        )?;
+        writeln!(
+            f,
+            "  data: {} ({:.1}%)",
+            self.num_data_cycles,
+            percent(self.num_data_cycles)
+        )?;
+        writeln!(
+            f,
+            "  idle: {} ({:.1}%)",
+            self.num_idle_cycles,
+            percent(self.num_idle_cycles)
+        )?;
+        writeln!(
+            f,
+            "commands: NOP {}, REF {}, PREA {}",
+            self.num_nops, self.num_auto_refreshes, self.num_precharge_alls
+        )?;
+        for (index, bank) in self.banks.iter().enumerate() {
+            writeln!(
+                f,
+                "  bank {}: ACT {}, RD {}, WR {}, PRE {}, row hits {}, row misses {}, \
+                max row ACTs {}, max neighbor ACTs {}",
+                index,
+                bank.num_actives,
+                bank.num_reads,
+                bank.num_writes,
+                bank.num_precharges,
+                bank.num_row_hits,
+                bank.num_row_misses,
+                bank.max_row_activations,
+                bank.max_neighbor_activations
+            )?;
         }
 
-        w.change_string(self.id, &value)?;
-        self.value = Some(value);
-
         Ok(())
     }
 }
 
-struct Trace {
-    w: vcd::Writer<io::BufWriter<fs::File>>,
-
-    clk: ScalarSignal,
-    command: StringSignal,
-    ldqm: ScalarSignal,
-    udqm: ScalarSignal,
-    bank: VectorSignal,
-    a: VectorSignal,
-    dq: VectorSignal,
-
-    time_stamp: u64,
-}
-
-// TODO: Mode registers
+// TODO: Mode register contents (the burst length and CAS latency are fixed)
 pub struct Sdram {
     banks: Box<[Bank]>,
 
     state: State,
+    // Cycle on which each bank's row closes after a READ or WRITE with auto precharge
+    auto_precharge_cycles: [Option<u64>; NUM_BANKS as usize],
+    is_self_refreshing: bool,
     dq_out_pipeline: Box<[OptionalBytePair]>,
     dqm_output_buffer_pipeline: Box<[Dqm]>,
 
     auto_refresh_row_addr: u32,
 
-    t_rrd_tester: TRrdTester,
-    t_rfc_tester: TRfcTester,
-
-    trace: Option<Trace>,
-}
-
-trait Bits {
-    fn bits(&self) -> Box<[vcd::Value]>;
-}
-
-impl Bits for u8 {
-    fn bits(&self) -> Box<[vcd::Value]> {
-        let mut ret = vec![vcd::Value::X; 8];
-        for (i, value) in ret.iter_mut().enumerate() {
-            *value = match (self >> (7 - i)) & 1 {
-                0 => vcd::Value::V0,
-                1 => vcd::Value::V1,
-                _ => unreachable!(),
-            };
-        }
-        ret.into()
-    }
+    t_ras_min: MinDelay,
+    t_ras_max: MaxDelay,
+    t_rc: MinDelay,
+    t_rcd: MinDelay,
+    t_rp: MinDelay,
+    t_wr: MinDelay,
+    t_rrd: MinDelay,
+    t_rfc: MinDelay,
+    t_ccd: MinDelay,
+    t_wtr: MinDelay,
+    t_dal: MinDelay,
+    t_mrd: MinDelay,
+    t_xsr: MinDelay,
+    read_to_precharge: MinDelay,
+    read_to_write: MinDelay,
+    timing_checkers: Vec<Box<dyn TimingChecker>>,
+
+    cycle: u64,
+    panic_on_violation: bool,
+    violations: Vec<Violation>,
+
+    stats: Stats,
+    coverage: Option<Coverage>,
+    row_hammer: Option<RowHammer>,
+    faults: Faults,
+    faulty_reads: Vec<FaultyRead>,
+
+    trace: Option<Box<dyn TraceSink>>,
 }
 
-impl Bits for u16 {
-    fn bits(&self) -> Box<[vcd::Value]> {
-        let mut ret = vec![vcd::Value::X; 16];
-        for (i, value) in ret.iter_mut().enumerate() {
-            *value = match (self >> (15 - i)) & 1 {
-                0 => vcd::Value::V0,
-                1 => vcd::Value::V1,
-                _ => unreachable!(),
-            };
-        }
-        ret.into()
+impl Sdram {
+    pub fn new(trace_file_name_prefix: Option<&str>) -> io::Result<Sdram> {
+        Sdram::with_trace_options(trace_file_name_prefix, TraceOptions::default())
     }
-}
 
-impl Bits for IoBank {
-    fn bits(&self) -> Box<[vcd::Value]> {
-        let index = self.index();
-        let mut ret = vec![vcd::Value::X; 2];
-        for (i, value) in ret.iter_mut().enumerate() {
-            *value = match (index >> (1 - i)) & 1 {
-                0 => vcd::Value::V0,
-                1 => vcd::Value::V1,
-                _ => unreachable!(),
-            };
-        }
-        ret.into()
+    // Traces to `vcd/{trace_file_name_prefix}.vcd`
+    pub fn with_trace_options(
+        trace_file_name_prefix: Option<&str>,
+        trace_options: TraceOptions,
+    ) -> io::Result<Sdram> {
+        let trace = match trace_file_name_prefix {
+            Some(prefix) => {
+                let path = format!("vcd/{}.vcd", prefix);
+                println!("Writing trace to {}", path);
+                Some(Box::new(VcdSink::create(path, trace_options)?) as Box<dyn TraceSink>)
+            }
+            None => None,
+        };
+        Ok(Sdram::with_trace_sink(trace))
     }
-}
 
-impl Sdram {
-    pub fn new(trace_file_name_prefix: Option<&str>) -> io::Result<Sdram> {
-        Ok(Sdram {
-            banks: vec![Bank::new(); NUM_BANKS as usize].into(),
+    pub fn with_trace_sink(trace: Option<Box<dyn TraceSink>>) -> Sdram {
+        Sdram {
+            banks: (0..NUM_BANKS as usize)
+                .map(|index| Bank::new(IoBank::from_index(index).unwrap()))
+                .collect(),
 
             state: State::Idle,
+            auto_precharge_cycles: [None; NUM_BANKS as usize],
+            is_self_refreshing: false,
             dq_out_pipeline: vec![OptionalBytePair::none(); CAS_LATENCY as usize - 1].into(),
             dqm_output_buffer_pipeline: vec![Default::default(); T_DQZ_CYCLES as usize - 1].into(),
 
             auto_refresh_row_addr: 0,
 
-            t_rrd_tester: TRrdTester::new(),
-            t_rfc_tester: TRfcTester::new(),
+            // Auto precharge waits for tRAS min on its own
+            t_ras_min: MinDelay::new(
+                ViolationKind::TRasMin,
+                T_RAS_MIN_CYCLES,
+                Scope::Bank,
+                is_active,
+                is_precharge,
+            ),
+            // The datasheet claims a row can be active for an "indefinite period" after tRAS
+            //  min is met, but it still lists a max value, and hitting that is probably
+            //  indicative of a refresh logic error anyways, so let's still test for it.
+            t_ras_max: MaxDelay::new(
+                ViolationKind::TRasMax,
+                T_RAS_MAX_CYCLES,
+                Scope::Bank,
+                is_active,
+                |event| is_precharge(event) || is_auto_precharge(event),
+            ),
+            t_rc: MinDelay::new(
+                ViolationKind::TRc,
+                T_RC_CYCLES,
+                Scope::Bank,
+                is_active,
+                is_active,
+            ),
+            t_rcd: MinDelay::new(
+                ViolationKind::TRcd,
+                T_RCD_CYCLES,
+                Scope::Bank,
+                is_active,
+                is_read_or_write,
+            ),
+            t_rp: MinDelay::new(
+                ViolationKind::TRp,
+                T_RP_CYCLES,
+                Scope::Bank,
+                |event| is_precharge(event) || is_auto_precharge(event),
+                |event| is_active(event) || is_precharge(event) || is_read_or_write(event),
+            ),
+            t_wr: MinDelay::new(
+                ViolationKind::TWr,
+                T_WR_CYCLES,
+                Scope::Bank,
+                is_write,
+                is_precharge,
+            ),
+            t_rrd: MinDelay::new(
+                ViolationKind::TRrd,
+                T_RRD_CYCLES,
+                Scope::Device,
+                is_active,
+                is_active,
+            ),
+            t_rfc: MinDelay::new(
+                ViolationKind::TRfc,
+                T_RFC_CYCLES,
+                Scope::Device,
+                |event| *event == Event::Command(Command::AutoRefresh),
+                is_command,
+            ),
+            t_ccd: MinDelay::new(
+                ViolationKind::TCcd,
+                T_CCD_CYCLES,
+                Scope::Device,
+                is_read_or_write_command,
+                is_read_or_write_command,
+            ),
+            t_wtr: MinDelay::new(
+                ViolationKind::TWtr,
+                T_WTR_CYCLES,
+                Scope::Device,
+                is_write,
+                |event| *event == Event::Command(Command::Read),
+            ),
+            // Writes with auto precharge close their row right after the burst, so this covers
+            //  tWR before the internal precharge as well as tRP after it. With an explicit
+            //  PRECHARGE, tWR and tRP already add up to this.
+            t_dal: MinDelay::new(
+                ViolationKind::TDal,
+                T_DAL_CYCLES,
+                Scope::Bank,
+                is_write,
+                is_active,
+            ),
+            t_mrd: MinDelay::new(
+                ViolationKind::TMrd,
+                T_MRD_CYCLES,
+                Scope::Device,
+                |event| *event == Event::Command(Command::LoadModeRegister),
+                is_command,
+            ),
+            t_xsr: MinDelay::new(
+                ViolationKind::TXsr,
+                T_XSR_CYCLES,
+                Scope::Device,
+                |event| *event == Event::SelfRefreshExit,
+                is_command,
+            ),
+            // A PRECHARGE cuts off the rest of the burst, so it can only come once the last
+            //  column has been read (the data itself comes out CAS latency cycles later)
+            read_to_precharge: MinDelay::new(
+                ViolationKind::ReadToPrecharge,
+                1,
+                Scope::Bank,
+                |event| matches!(event, Event::Read { .. }),
+                is_precharge,
+            ),
+            // Write data would collide with read data still coming out of the pipeline, unless
+            //  DQM masked it
+            read_to_write: MinDelay::new(
+                ViolationKind::ReadToWrite,
+                CAS_LATENCY + 1,
+                Scope::Device,
+                |event| {
+                    matches!(
+                        event,
+                        Event::Read {
+                            drives_dq: true,
+                            ..
+                        }
+                    )
+                },
+                |event| *event == Event::Command(Command::Write),
+            ),
+            timing_checkers: Vec::new(),
 
-            trace: if let Some(prefix) = trace_file_name_prefix {
-                let path = format!("vcd/{}.vcd", prefix);
-                println!("Writing trace to {}", path);
-                let file = fs::File::create(path)?;
-                let mut w = vcd::Writer::new(io::BufWriter::new(file));
+            cycle: 0,
+            panic_on_violation: true,
+            violations: Vec::new(),
 
-                w.timescale(CLOCK_PERIOD_NS / 2, vcd::TimescaleUnit::NS)?;
+            stats: Default::default(),
+            coverage: None,
+            row_hammer: None,
+            faults: Default::default(),
+            faulty_reads: Vec::new(),
 
-                w.add_module("sdram")?;
+            trace,
+        }
+    }
 
-                let clk = ScalarSignal::new("clk", &mut w)?;
-                let command =
-                    StringSignal::new(4 /* TODO: Verify correct width */, "command", &mut w)?;
-                let ldqm = ScalarSignal::new("ldqm", &mut w)?;
-                let udqm = ScalarSignal::new("udqm", &mut w)?;
-                let bank = VectorSignal::new(2, "bank", &mut w)?;
-                let a = VectorSignal::new(NUM_ROW_ADDR_BITS, "a", &mut w)?;
-                let dq = VectorSignal::new(16, "dq", &mut w)?;
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
 
-                w.upscope()?;
-                w.enddefinitions()?;
+    // By default, any violation panics. Otherwise, violations are recorded and can be
+    //  retrieved with `take_violations`, and the simulation carries on as if the offending
+    //  command was legal.
+    pub fn set_panic_on_violation(&mut self, panic_on_violation: bool) {
+        self.panic_on_violation = panic_on_violation;
+    }
 
-                let time_stamp = 0;
-                w.timestamp(time_stamp)?;
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        std::mem::take(&mut self.violations)
+    }
 
-                Some(Trace {
-                    w,
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
 
-                    clk,
-                    command,
-                    ldqm,
-                    udqm,
-                    bank,
-                    a,
-                    dq,
+    pub fn reset_stats(&mut self) {
+        self.stats = Default::default();
+    }
 
-                    time_stamp,
-                })
-            } else {
-                None
-            },
-        })
+    // `T_REF_CYCLES` by default. A shorter period lets tests reach the deadline without
+    //  simulating 64ms. Only rows precharged or refreshed from now on get the new deadline.
+    pub fn set_t_ref_cycles(&mut self, num_cycles: u32) {
+        for bank in self.banks.iter_mut() {
+            bank.t_ref_tester.num_cycles = num_cycles;
+        }
     }
 
-    pub fn clk(&mut self, io: &mut Io) -> io::Result<()> {
-        io.check_dq_bus_conflict();
-
-        if let Some(trace) = &mut self.trace {
-            trace.clk.update(false, &mut trace.w)?;
-
-            trace
-                .command
-                .update(format!("{:?}", io.command), &mut trace.w)?;
-            trace.ldqm.update(io.ldqm, &mut trace.w)?;
-            trace.udqm.update(io.udqm, &mut trace.w)?;
-            trace.bank.update(io.bank.bits(), &mut trace.w)?;
-            trace.a.update(
-                io.a.bits()[16 - (NUM_ROW_ADDR_BITS as usize)..]
-                    .to_vec()
-                    .into(),
-                &mut trace.w,
-            )?;
-            let dq = io.dq();
-            trace.dq.update(
-                dq.high
-                    .map_or_else(|| vec![vcd::Value::Z; 8].into(), |dq| dq.bits())
-                    .into_iter()
-                    .cloned()
-                    .chain(
-                        dq.low
-                            .map_or_else(|| vec![vcd::Value::Z; 8].into(), |dq| dq.bits())
-                            .into_iter()
-                            .cloned(),
-                    )
-                    .collect::<Vec<_>>()
-                    .into(),
-                &mut trace.w,
-            )?;
+    // Off by default. Enabling it starts over with nothing covered.
+    pub fn set_collect_coverage(&mut self, collect_coverage: bool) {
+        self.coverage = collect_coverage
+            .then(|| Coverage::new(self.min_delays().map(|checker| checker.kind())));
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Off by default, so cells never lose their contents
+    pub fn set_row_hammer(&mut self, params: Option<RowHammerParams>) {
+        self.row_hammer = params.map(RowHammer::new);
+    }
+
+    // Bits flipped by row hammer since the last call
+    pub fn take_bit_flips(&mut self) -> Vec<BitFlip> {
+        self.row_hammer
+            .as_mut()
+            .map(|row_hammer| std::mem::take(&mut row_hammer.bit_flips))
+            .unwrap_or_default()
+    }
+
+    // Faults can be added before the first cycle, or at any point after it, and take effect from
+    //  the next cycle
+    pub fn add_fault(&mut self, fault: Fault) -> io::Result<FaultId> {
+        self.faults.add(fault)
+    }
+
+    pub fn remove_fault(&mut self, id: FaultId) {
+        self.faults.remove(id);
+    }
+
+    pub fn fault(&self, id: FaultId) -> Option<Fault> {
+        self.faults.get(id)
+    }
+
+    // Reads which any fault went into since the last call, one per column
+    pub fn take_faulty_reads(&mut self) -> Vec<FaultyRead> {
+        std::mem::take(&mut self.faulty_reads)
+    }
 
-            trace.time_stamp += 1;
-            trace.w.timestamp(trace.time_stamp)?;
-            trace.clk.update(true, &mut trace.w)?;
-            trace.time_stamp += 1;
-            trace.w.timestamp(trace.time_stamp)?;
+    // Backdoor access to the cell array, bypassing the pins, timing and faults (eg. to corrupt
+    //  stored data in tests). `poke` overwrites both bytes, including with `None`.
+    pub fn peek(&self, bank: IoBank, row_addr: u32, col_addr: u32) -> OptionalBytePair {
+        self.banks[bank.index()].rows[row_addr as usize].cols[col_addr as usize]
+    }
+
+    pub fn poke(&mut self, bank: IoBank, row_addr: u32, col_addr: u32, data: OptionalBytePair) {
+        self.banks[bank.index()].rows[row_addr as usize].cols[col_addr as usize] = data;
+    }
+
+    // Activations of a row since it was last refreshed
+    pub fn row_activations(&self, bank: IoBank, row_addr: u32) -> u32 {
+        self.banks[bank.index()].num_activations[row_addr as usize]
+    }
+
+    // Registers an additional rule, which is checked after the built-in ones
+    pub fn add_timing_checker(&mut self, timing_checker: Box<dyn TimingChecker>) {
+        self.timing_checkers.push(timing_checker);
+    }
+
+    // The built-in rules other than tRAS max, in the order they're checked
+    fn min_delays(&self) -> [&MinDelay; 14] {
+        [
+            &self.t_rrd,
+            &self.t_rfc,
+            &self.t_ras_min,
+            &self.t_rc,
+            &self.t_rcd,
+            &self.t_rp,
+            &self.t_wr,
+            &self.t_ccd,
+            &self.t_wtr,
+            &self.t_dal,
+            &self.t_mrd,
+            &self.t_xsr,
+            &self.read_to_precharge,
+            &self.read_to_write,
+        ]
+    }
+
+    fn timing_checkers(&self) -> impl Iterator<Item = &dyn TimingChecker> {
+        self.min_delays()
+            .into_iter()
+            .map(|checker| checker as &dyn TimingChecker)
+            .chain([&self.t_ras_max as &dyn TimingChecker])
+            .chain(self.timing_checkers.iter().map(|checker| checker.as_ref()))
+    }
+
+    fn timing_checkers_mut(&mut self) -> impl Iterator<Item = &mut dyn TimingChecker> {
+        [
+            &mut self.t_rrd as &mut dyn TimingChecker,
+            &mut self.t_rfc,
+            &mut self.t_ras_min,
+            &mut self.t_rc,
+            &mut self.t_rcd,
+            &mut self.t_rp,
+            &mut self.t_wr,
+            &mut self.t_ccd,
+            &mut self.t_wtr,
+            &mut self.t_dal,
+            &mut self.t_mrd,
+            &mut self.t_xsr,
+            &mut self.read_to_precharge,
+            &mut self.read_to_write,
+            &mut self.t_ras_max,
+        ]
+        .into_iter()
+        .chain(
+            self.timing_checkers
+                .iter_mut()
+                .map(|checker| checker.as_mut() as &mut dyn TimingChecker),
+        )
+    }
+
+    // Checks `io`'s command against the rules `clk` would apply to it on the next cycle,
+    //  without changing any state, and returns the first violation it would cause. Only rules
+    //  which depend on the command are covered, so data checks (missing write data, uninitialized
+    //  reads, bus conflicts) and deadlines which expire regardless (tRAS max, tREF) are not.
+    pub fn can_issue(&self, io: &Io) -> Result<(), Violation> {
+        let mut violations = Violations::new(self.cycle);
+        self.check_events(&self.events(io, 0), 0, &mut violations);
+        match violations.list.first() {
+            Some(violation) => Err(*violation),
+            None => Ok(()),
+        }
+    }
+
+    // Earliest cycle (as returned by `cycle`) at which `io`'s command could be issued without
+    //  violating any rule `can_issue` covers, assuming only NOPs until then, or `None` if
+    //  waiting alone won't make it legal (eg. READ from a bank without an active row)
+    pub fn earliest_cycle(&self, io: &Io) -> Option<u64> {
+        let max_num_nops = self
+            .timing_checkers()
+            .map(|checker| checker.max_num_wait_cycles())
+            .fold(BURST_LEN, u32::max);
+        (0..=max_num_nops)
+            .find(|&num_nops| {
+                let mut violations = Violations::new(self.cycle + num_nops as u64);
+                self.check_events(&self.events(io, num_nops), num_nops, &mut violations);
+                violations.list.is_empty()
+            })
+            .map(|num_nops| self.cycle + num_nops as u64)
+    }
+
+    // What issuing `io` would do on the upcoming cycle, or `num_nops` cycles after it, each
+    //  with how many cycles before `io`'s command it happens. Any NOPs in between are assumed
+    //  to have CKE high and DQM low.
+    fn events(&self, io: &Io, num_nops: u32) -> Vec<(u32, Event)> {
+        let mut events = Vec::new();
+        for num_cycles in 0..num_nops {
+            self.cycle_events(&Io::new(), num_cycles, num_nops - num_cycles, &mut events);
+        }
+        self.cycle_events(io, num_nops, 0, &mut events);
+        events
+    }
+
+    // Events `num_cycles` cycles after the upcoming one
+    fn cycle_events(
+        &self,
+        io: &Io,
+        num_cycles: u32,
+        num_cycles_before: u32,
+        events: &mut Vec<(u32, Event)>,
+    ) {
+        let mut push = |event| events.push((num_cycles_before, event));
+
+        // Only the first of the NOPs can leave self refresh
+        if self.is_self_refreshing && num_cycles == 0 {
+            if !io.cke {
+                return;
+            }
+            push(Event::SelfRefreshExit);
         }
 
-        for bank in &mut *self.banks {
-            bank.clk();
+        let cycle = self.cycle + num_cycles as u64;
+        for (index, auto_precharge_cycle) in self.auto_precharge_cycles.iter().enumerate() {
+            if *auto_precharge_cycle == Some(cycle) {
+                push(Event::AutoPrecharge {
+                    bank: IoBank::from_index(index).unwrap(),
+                });
+            }
         }
-        self.t_rrd_tester.clk();
-        self.t_rfc_tester.clk();
 
-        match io.command {
-            Command::Active => {
-                self.t_rrd_tester.active();
-                self.t_rfc_tester.any_command_except_auto_refresh_and_nop();
+        if io.command != Command::Nop {
+            push(Event::Command(io.command));
+        }
 
-                self.banks[io.bank.index()].active(io.a as u32 & ROW_ADDR_MASK);
+        if io.command == Command::Active {
+            push(Event::Active {
+                bank: io.bank,
+                row_addr: io.a as u32 & ROW_ADDR_MASK,
+            });
+        }
+
+        // Read data is masked by DQM from the previous cycle, which is still in the pipeline
+        //  for the upcoming one
+        let dqm = match num_cycles {
+            0 => self.dqm_output_buffer_pipeline.last().copied().unwrap(),
+            _ => Dqm::default(),
+        };
+        let drives_dq = !(dqm.ldqm && dqm.udqm);
+        // TODO: Technically we only need to test timings for the first cycle of a burst, but
+        //  doing them each time doesn't hurt
+        match (io.command, self.state) {
+            (Command::Read, _) => push(Event::Read {
+                bank: io.bank,
+                drives_dq,
+            }),
+            (Command::Write, _) => push(Event::Write { bank: io.bank }),
+            (
+                _,
+                State::Read {
+                    bank,
+                    num_cycles: n,
+                },
+            ) if n + num_cycles < BURST_LEN => push(Event::Read { bank, drives_dq }),
+            (
+                _,
+                State::Write {
+                    bank,
+                    num_cycles: n,
+                },
+            ) if n + num_cycles < BURST_LEN => push(Event::Write { bank }),
+            _ => (),
+        }
+
+        if io.command == Command::Precharge {
+            let is_all = (io.a & A_10_MASK as u16) != 0;
+            for bank in &*self.banks {
+                if (is_all || bank.index == io.bank) && self.has_active_row(bank.index, events) {
+                    events.push((num_cycles_before, Event::Precharge { bank: bank.index }));
+                }
             }
-            Command::AutoRefresh => {
-                self.t_rfc_tester.auto_refresh();
+        }
 
-                for bank in &mut *self.banks {
-                    bank.auto_refresh(self.auto_refresh_row_addr);
-                    self.auto_refresh_row_addr = (self.auto_refresh_row_addr + 1) & ROW_ADDR_MASK;
+        if io.command == Command::AutoRefresh && !io.cke {
+            events.push((num_cycles_before, Event::SelfRefreshEntry));
+        }
+    }
+
+    // Whether `bank` has an active row once `earlier_events` have happened
+    fn has_active_row(&self, bank: IoBank, earlier_events: &[(u32, Event)]) -> bool {
+        earlier_events.iter().fold(
+            self.banks[bank.index()].active_row.is_some(),
+            |has_active_row, (_, earlier_event)| match *earlier_event {
+                Event::Active {
+                    bank: earlier_bank, ..
+                } if earlier_bank == bank => true,
+                Event::Precharge { bank: earlier_bank }
+                | Event::AutoPrecharge { bank: earlier_bank }
+                    if earlier_bank == bank =>
+                {
+                    false
                 }
+                _ => has_active_row,
+            },
+        )
+    }
+
+    // Checks the events of `io`'s cycle, ie. the ones which happen 0 cycles before it
+    fn check_events(&self, events: &[(u32, Event)], num_nops: u32, violations: &mut Violations) {
+        for (index, (num_cycles_before, event)) in events.iter().enumerate() {
+            if *num_cycles_before != 0 {
+                continue;
             }
-            Command::Nop => (), // Do nothing
-            Command::Precharge => {
-                self.t_rfc_tester.any_command_except_auto_refresh_and_nop();
+            let earlier_events = &events[..index];
 
-                if (io.a & A_10_MASK as u16) == 0 {
-                    self.banks[io.bank.index()].precharge();
-                } else {
-                    for bank in &mut *self.banks {
-                        bank.precharge();
+            match *event {
+                Event::Command(command @ (Command::AutoRefresh | Command::LoadModeRegister)) => {
+                    for bank in &*self.banks {
+                        if self.has_active_row(bank.index, earlier_events) {
+                            // TODO: Test(s)
+                            violations.push(
+                                match command {
+                                    Command::AutoRefresh => ViolationKind::AutoRefreshWithActiveRow,
+                                    _ => ViolationKind::LoadModeRegisterWithActiveRow,
+                                },
+                                Some(bank.index),
+                            );
+                        }
                     }
                 }
+                Event::Active { bank, .. } if self.has_active_row(bank, earlier_events) => {
+                    violations.push(ViolationKind::ActiveWithActiveRow, Some(bank));
+                }
+                Event::Read { bank, .. } | Event::Write { bank }
+                    if !self.has_active_row(bank, earlier_events) =>
+                {
+                    // TODO: Test(s)
+                    violations.push(
+                        match event {
+                            Event::Read { .. } => ViolationKind::ReadWithoutActiveRow,
+                            _ => ViolationKind::WriteWithoutActiveRow,
+                        },
+                        Some(bank),
+                    );
+                }
+                _ => (),
             }
-            Command::Read => {
-                self.t_rfc_tester.any_command_except_auto_refresh_and_nop();
 
-                self.state = State::Read {
-                    bank: io.bank,
-                    num_cycles: 0,
-                };
+            for checker in self.timing_checkers() {
+                violations.check(checker.check(event, earlier_events, num_nops), event.bank());
             }
-            Command::Write => {
-                self.t_rfc_tester.any_command_except_auto_refresh_and_nop();
+        }
+    }
+
+    pub fn clk(&mut self, io: &mut Io) -> io::Result<()> {
+        if self.faults.is_empty() {
+            return self.clk_pins(io);
+        }
 
-                self.state = State::Write {
-                    bank: io.bank,
-                    num_cycles: 0,
-                };
+        // Pin faults only change what the part sees, not what the controller drives
+        let (bank, a, dq_in) = (io.bank, io.a, io.dq_in);
+        self.faults.apply_to_inputs(io);
+        self.inject_transient_flips();
+        let result = self.clk_pins(io);
+        (io.bank, io.a, io.dq_in) = (bank, a, dq_in);
+        result
+    }
+
+    fn inject_transient_flips(&mut self) {
+        for (id, cell) in self.faults.transient_flips(self.cycle) {
+            let col = &mut self.banks[cell.bank.index()].rows[cell.row_addr as usize].cols
+                [cell.col_addr as usize];
+            let byte = if cell.bit < 8 {
+                &mut col.low
+            } else {
+                &mut col.high
+            };
+            if let Some(byte) = byte {
+                *byte ^= 1 << (cell.bit % 8);
+                self.faults.mark_flipped(id);
+            }
+        }
+    }
+
+    fn clk_pins(&mut self, io: &mut Io) -> io::Result<()> {
+        let mut violations = Violations::new(self.cycle);
+
+        if io.has_dq_bus_conflict() {
+            violations.push(ViolationKind::DqBusConflict, None);
+        }
+
+        self.update_stats(io);
+
+        // Bank state is traced as of the start of the cycle
+        let mut trace_cycle = self.trace.as_ref().map(|_| self.trace_cycle(io));
+
+        let events = self.events(io, 0);
+        self.check_events(&events, 0, &mut violations);
+        if self.coverage.is_some() {
+            self.update_coverage(io, &events);
+        }
+
+        // The part refreshes itself during self refresh
+        if !self.is_self_refreshing {
+            for bank in &mut *self.banks {
+                bank.clk(&mut violations);
+            }
+        }
+        for checker in self.timing_checkers_mut() {
+            checker.clk(&mut violations);
+            for (_, event) in &events {
+                checker.record(event);
+            }
+        }
+
+        for (_, event) in &events {
+            match *event {
+                Event::AutoPrecharge { bank } => {
+                    self.auto_precharge_cycles[bank.index()] = None;
+                    self.banks[bank.index()].precharge(self.cycle);
+                }
+                Event::Command(Command::AutoRefresh) => {
+                    // Every bank refreshes the same row, as with the internal refresh counter on
+                    //  a real part
+                    for bank in &mut *self.banks {
+                        bank.auto_refresh(self.auto_refresh_row_addr, self.cycle);
+                    }
+                    self.auto_refresh_row_addr = (self.auto_refresh_row_addr + 1) & ROW_ADDR_MASK;
+                }
+                Event::Command(command @ (Command::Read | Command::Write)) => {
+                    if command == Command::Read {
+                        self.faults.read();
+                    }
+                    self.state = match command {
+                        Command::Read => State::Read {
+                            bank: io.bank,
+                            num_cycles: 0,
+                        },
+                        _ => State::Write {
+                            bank: io.bank,
+                            num_cycles: 0,
+                        },
+                    };
+                    if (io.a & A_10_MASK as u16) != 0 {
+                        self.auto_precharge_cycles[io.bank.index()] =
+                            Some(self.cycle + BURST_LEN as u64);
+                    }
+                }
+                Event::Active { bank, row_addr } => {
+                    self.faults.active(bank);
+                    let bank = &mut self.banks[bank.index()];
+                    bank.active(row_addr);
+                    if let Some(row_hammer) = &mut self.row_hammer {
+                        row_hammer.disturb(bank, row_addr, self.cycle);
+                    }
+                }
+                Event::Precharge { bank } => self.banks[bank.index()].precharge(self.cycle),
+                Event::SelfRefreshEntry => self.is_self_refreshing = true,
+                Event::SelfRefreshExit => {
+                    self.is_self_refreshing = false;
+                    for bank in &mut *self.banks {
+                        bank.refresh_all(self.cycle);
+                    }
+                }
+                _ => (),
             }
         }
 
@@ -981,37 +1495,51 @@ impl Sdram {
             State::Idle => (), // Do nothing
             State::Read { bank, num_cycles } => {
                 let delayed_dqm = self.dqm_output_buffer_pipeline.last().copied().unwrap();
-                // TODO: Technically we only need to test timings for the first read cycle, but doing them each time doesn't hurt
-                let data = self.banks[bank.index()]
-                    .read((io.a as u32).wrapping_add(*num_cycles) & COL_ADDR_MASK);
-                next_dq_out = data.mask(delayed_dqm);
+                let col_addr = (io.a as u32).wrapping_add(*num_cycles) & COL_ADDR_MASK;
+                if let Some(data) = self.banks[bank.index()].read(col_addr) {
+                    // Nothing was ever going to come out of a dead bank
+                    if ((!delayed_dqm.ldqm && data.low.is_none())
+                        || (!delayed_dqm.udqm && data.high.is_none()))
+                        && !self.faults.is_dead(*bank)
+                    {
+                        violations.push(ViolationKind::UninitializedRead, Some(*bank));
+                    }
+                    next_dq_out = data.mask(delayed_dqm);
+
+                    if !self.faults.is_empty() {
+                        let row_addr = self.banks[bank.index()].active_row.unwrap() as u32;
+                        let fault_ids =
+                            self.faults
+                                .apply_to_read(*bank, row_addr, col_addr, &mut next_dq_out);
+                        if !fault_ids.is_empty() {
+                            self.faulty_reads.push(FaultyRead {
+                                cycle: self.cycle,
+                                bank: *bank,
+                                row_addr,
+                                col_addr,
+                                fault_ids,
+                            });
+                        }
+                    }
+                }
                 *num_cycles += 1;
                 if *num_cycles == BURST_LEN {
                     self.state = State::Idle;
                 }
             }
             State::Write { bank, num_cycles } => {
-                self.banks[bank.index()].write(
-                    (io.a as u32).wrapping_add(*num_cycles) & COL_ADDR_MASK,
-                    OptionalBytePair {
-                        low: if !dqm.ldqm {
-                            // TODO: Test(s)
-                            Some(io.dq_in.low.expect("No low data provided for write cycle."))
-                        } else {
-                            None
-                        },
-                        high: if !dqm.udqm {
-                            // TODO: Test(s)
-                            Some(
-                                io.dq_in
-                                    .high
-                                    .expect("No high data provided for write cycle."),
-                            )
-                        } else {
-                            None
-                        },
-                    },
-                );
+                // TODO: Test(s)
+                if (!dqm.ldqm && io.dq_in.low.is_none()) || (!dqm.udqm && io.dq_in.high.is_none()) {
+                    violations.push(ViolationKind::MissingWriteData, Some(*bank));
+                }
+                let col_addr = (io.a as u32).wrapping_add(*num_cycles) & COL_ADDR_MASK;
+                let data = io.dq_in.mask(dqm);
+                if !self.faults.is_dead(*bank) {
+                    self.banks[bank.index()].write(col_addr, data);
+                    if let Some(row_addr) = self.banks[bank.index()].active_row {
+                        self.faults.written(*bank, row_addr as _, col_addr, data);
+                    }
+                }
                 *num_cycles += 1;
                 if *num_cycles == BURST_LEN {
                     self.state = State::Idle;
@@ -1031,13 +1559,170 @@ impl Sdram {
         }
         self.dqm_output_buffer_pipeline[0] = dqm;
 
+        self.cycle += 1;
+
+        if let (Some(trace), Some(trace_cycle)) = (&mut self.trace, &mut trace_cycle) {
+            trace_cycle.violations.clone_from(&violations.list);
+            trace.cycle(trace_cycle)?;
+
+            // Make sure the trace is complete up to here, in case the violation panics below
+            //  or the process doesn't get to drop the sink cleanly
+            if !violations.list.is_empty() {
+                trace.flush()?;
+            }
+        }
+
+        if let Some(violation) = violations.list.first() {
+            if self.panic_on_violation {
+                panic!("{}", violation.kind);
+            }
+        }
+        self.violations.append(&mut violations.list);
+
         Ok(())
     }
+
+    fn trace_cycle(&self, io: &Io) -> TraceCycle {
+        let banks = std::array::from_fn(|index| {
+            let bank = &self.banks[index];
+            let (burst, num_burst_cycles) = match self.state {
+                State::Read {
+                    bank: burst_bank,
+                    num_cycles,
+                } if burst_bank == bank.index => (Burst::Read, num_cycles),
+                State::Write {
+                    bank: burst_bank,
+                    num_cycles,
+                } if burst_bank == bank.index => (Burst::Write, num_cycles),
+                _ => (Burst::Idle, BURST_LEN),
+            };
+            BankState {
+                active_row: bank.active_row.map(|row_addr| row_addr as _),
+                burst,
+                burst_remaining: BURST_LEN - num_burst_cycles,
+                t_ras: self.t_ras_min.remaining_cycles(bank.index),
+                t_rc: self.t_rc.remaining_cycles(bank.index),
+                t_rcd: self.t_rcd.remaining_cycles(bank.index),
+                t_rp: self.t_rp.remaining_cycles(bank.index),
+                t_wr: self.t_wr.remaining_cycles(bank.index),
+            }
+        });
+
+        TraceCycle {
+            cycle: self.cycle,
+            command: io.command,
+            cke: io.cke,
+            bank: io.bank,
+            a: io.a,
+            ldqm: io.ldqm,
+            udqm: io.udqm,
+            dq: io.dq_in.or(io.dq_out),
+            violations: Vec::new(),
+
+            t_rrd: self.t_rrd.remaining_cycles(io.bank),
+            t_rfc: self.t_rfc.remaining_cycles(io.bank),
+            banks,
+        }
+    }
+
+    fn update_stats(&mut self, io: &Io) {
+        let stats = &mut self.stats;
+
+        stats.num_cycles += 1;
+        if self.is_self_refreshing {
+            stats.num_self_refresh_cycles += 1;
+            return;
+        }
+        if self.banks.iter().any(|bank| bank.active_row.is_some()) {
+            stats.num_active_cycles += 1;
+        }
+        let dq = io.dq_in.or(io.dq_out);
+        let has_data = dq.low.is_some() || dq.high.is_some();
+        if has_data {
+            stats.num_data_cycles += 1;
+        }
+
+        let bank_index = io.bank.index();
+        let bank_stats = &mut stats.banks[bank_index];
+        match io.command {
+            Command::Active => {
+                bank_stats.num_actives += 1;
+                let row_addr = (io.a as u32 & ROW_ADDR_MASK) as usize;
+                let bank = &self.banks[bank_index];
+                if bank.last_active_row == Some(row_addr) {
+                    bank_stats.num_row_hits += 1;
+                } else {
+                    bank_stats.num_row_misses += 1;
+                }
+                // Including this activation
+                bank_stats.max_row_activations = bank_stats
+                    .max_row_activations
+                    .max(bank.num_activations[row_addr] + 1);
+                for neighbor_row_addr in neighbor_row_addrs(row_addr as _) {
+                    bank_stats.max_neighbor_activations = bank_stats
+                        .max_neighbor_activations
+                        .max(bank.num_neighbor_activations[neighbor_row_addr as usize] + 1);
+                }
+            }
+            Command::AutoRefresh => stats.num_auto_refreshes += 1,
+            Command::LoadModeRegister => (),
+            Command::Nop => {
+                stats.num_nops += 1;
+                if !has_data {
+                    stats.num_idle_cycles += 1;
+                }
+            }
+            Command::Precharge => {
+                if (io.a & A_10_MASK as u16) == 0 {
+                    bank_stats.num_precharges += 1;
+                } else {
+                    stats.num_precharge_alls += 1;
+                }
+            }
+            Command::Read => bank_stats.num_reads += 1,
+            Command::Write => bank_stats.num_writes += 1,
+        }
+    }
+
+    fn update_coverage(&mut self, io: &Io, events: &[(u32, Event)]) {
+        // Rules are checked before the cycle's events are recorded, so this is the last chance
+        //  to tell how close they came
+        let min_delay_hits = self
+            .min_delays()
+            .map(|checker| events.iter().any(|(_, event)| checker.is_at_minimum(event)));
+        let coverage = self.coverage.as_mut().unwrap();
+        for (index, is_hit) in min_delay_hits.into_iter().enumerate() {
+            if is_hit {
+                coverage.record_min_delay_hit(index);
+            }
+        }
+
+        for (_, event) in events {
+            match *event {
+                Event::Command(command) => {
+                    let command = coverage::Command::decode(command, io.a, io.cke).unwrap();
+                    if command == coverage::Command::LoadModeRegister {
+                        coverage.record_mode_register(io.a);
+                    }
+                    if command.is_all_banks() {
+                        for index in 0..NUM_BANKS as usize {
+                            coverage.record_command(IoBank::from_index(index).unwrap(), command);
+                        }
+                    } else {
+                        coverage.record_command(io.bank, command);
+                    }
+                }
+                Event::Active { bank, row_addr } => coverage.record_active(bank, row_addr),
+                _ => (),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fault::{Cell, Pin};
 
     #[test]
     fn one_active_precharge() -> io::Result<()> {
@@ -1155,12 +1840,15 @@ mod tests {
     #[should_panic(expected = "tREF violated.")]
     fn violate_t_ref() {
         let mut sdram = Sdram::new(Some("Sdram__violate_t_ref")).unwrap();
+        // The real tREF spans millions of cycles
+        let t_ref_cycles = 100;
+        sdram.set_t_ref_cycles(t_ref_cycles);
 
         // TODO: Initialization
 
         let mut io = Io::new();
         io.command = Command::AutoRefresh;
-        for _ in 0..T_REF_CYCLES + 1 {
+        for _ in 0..t_ref_cycles + 1 {
             sdram.clk(&mut io).unwrap();
             assert!(io.dq().are_both_none());
             io.command = Command::Nop;
@@ -1250,6 +1938,59 @@ mod tests {
         sdram.clk(&mut io).unwrap();
     }
 
+    #[test]
+    #[should_panic(expected = "Attempted to read from an uninitialized column.")]
+    fn uninitialized_read() {
+        let mut sdram = Sdram::new(Some("Sdram__uninitialized_read")).unwrap();
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        io.command = Command::Active;
+        for _ in 0..T_RCD_CYCLES {
+            sdram.clk(&mut io).unwrap();
+            io.command = Command::Nop;
+        }
+        io.command = Command::Read;
+        sdram.clk(&mut io).unwrap();
+    }
+
+    #[test]
+    fn record_violations() -> io::Result<()> {
+        let mut sdram = Sdram::new(Some("Sdram__record_violations"))?;
+        sdram.set_panic_on_violation(false);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        io.command = Command::Active;
+        io.bank = IoBank::Bank2;
+        sdram.clk(&mut io)?;
+        io.command = Command::Read;
+        sdram.clk(&mut io)?;
+
+        let violations = sdram.take_violations();
+        assert_eq!(
+            violations,
+            [
+                Violation {
+                    cycle: 1,
+                    kind: ViolationKind::TRcd,
+                    bank: Some(IoBank::Bank2),
+                },
+                Violation {
+                    cycle: 1,
+                    kind: ViolationKind::UninitializedRead,
+                    bank: Some(IoBank::Bank2),
+                },
+            ]
+        );
+        assert_eq!(violations[0].to_string(), "cycle 1: bank 2: tRCD violated.");
+        assert!(sdram.take_violations().is_empty());
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic(expected = "tRP violated.")]
     fn violate_t_rp() {
@@ -1386,4 +2127,1156 @@ mod tests {
         io.command = Command::Write;
         sdram.clk(&mut io).unwrap();
     }
+
+    #[test]
+    fn stats() -> io::Result<()> {
+        let mut sdram = Sdram::new(Some("Sdram__stats"))?;
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        for _ in 0..2 {
+            io.command = Command::Active;
+            io.bank = IoBank::Bank1;
+            io.a = 42;
+            for _ in 0..T_RCD_CYCLES {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+            io.command = Command::Write;
+            io.a = 0;
+            for _ in 0..BURST_LEN {
+                io.dq_in = OptionalBytePair::some(0xbabe);
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+            io.dq_in = OptionalBytePair::none();
+            for _ in 0..T_RAS_MIN_CYCLES {
+                sdram.clk(&mut io)?;
+            }
+            io.command = Command::Precharge;
+            io.a = A_10_MASK as _;
+            for _ in 0..T_RP_CYCLES {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+        }
+
+        let stats = sdram.stats();
+        let num_cycles_per_access = T_RCD_CYCLES + BURST_LEN + T_RAS_MIN_CYCLES + T_RP_CYCLES;
+        assert_eq!(stats.num_cycles, 2 * num_cycles_per_access as u64);
+        assert_eq!(stats.num_data_cycles, 2 * BURST_LEN as u64);
+        assert_eq!(
+            stats.num_active_cycles,
+            2 * (num_cycles_per_access - T_RP_CYCLES) as u64
+        );
+        // Every cycle is either idle, carries data (including the WRITE cycles) or issues an
+        //  ACT or PRE
+        assert_eq!(
+            stats.num_idle_cycles,
+            stats.num_cycles - stats.num_data_cycles - 4
+        );
+        assert_eq!(stats.num_precharge_alls, 2);
+        assert_eq!(stats.num_auto_refreshes, 0);
+        assert_eq!(stats.num_commands(), stats.num_cycles);
+        let bank = stats.banks[1];
+        assert_eq!(bank.num_actives, 2);
+        assert_eq!(bank.num_writes, 2);
+        assert_eq!(bank.num_reads, 0);
+        assert_eq!(bank.num_row_misses, 1);
+        assert_eq!(bank.num_row_hits, 1);
+        assert_eq!(bank.max_row_activations, 2);
+        assert_eq!(stats.banks[0].num_actives, 0);
+        assert!((stats.bus_utilization() - 16.0 / stats.num_cycles as f64).abs() < 1e-9);
+        println!("{}", stats);
+
+        sdram.reset_stats();
+        let stats = sdram.stats();
+        assert_eq!(stats.num_cycles, 0);
+        assert_eq!(stats.banks[1].num_actives, 0);
+
+        // Row hits are still tracked across resets
+        io.command = Command::Active;
+        io.a = 42;
+        sdram.clk(&mut io)?;
+        assert_eq!(sdram.stats().banks[1].num_row_hits, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn earliest_cycle() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+        // Nothing is written, so the read is only checked for timing
+        sdram.set_panic_on_violation(false);
+        let io_for = |command, bank, a| Io {
+            command,
+            bank,
+            a,
+            ..Io::new()
+        };
+
+        // TODO: Initialization
+
+        let mut io = io_for(Command::Active, IoBank::Bank0, 0);
+        assert_eq!(sdram.earliest_cycle(&io), Some(0));
+        sdram.clk(&mut io)?;
+
+        let read = io_for(Command::Read, IoBank::Bank0, 0);
+        let precharge = io_for(Command::Precharge, IoBank::Bank0, 0);
+        let precharge_all = io_for(Command::Precharge, IoBank::Bank2, A_10_MASK as _);
+        assert_eq!(sdram.earliest_cycle(&read), Some(T_RCD_CYCLES as _));
+        assert_eq!(
+            sdram.earliest_cycle(&precharge),
+            Some(T_RAS_MIN_CYCLES as _)
+        );
+        assert_eq!(
+            sdram.earliest_cycle(&precharge_all),
+            Some(T_RAS_MIN_CYCLES as _)
+        );
+        assert_eq!(
+            sdram.earliest_cycle(&io_for(Command::Active, IoBank::Bank1, 0)),
+            Some(T_RRD_CYCLES as _)
+        );
+        assert_eq!(
+            sdram.earliest_cycle(&io_for(Command::Active, IoBank::Bank0, 0)),
+            None
+        );
+        assert_eq!(
+            sdram.earliest_cycle(&io_for(Command::Read, IoBank::Bank1, 0)),
+            None
+        );
+        assert_eq!(
+            sdram.earliest_cycle(&io_for(Command::AutoRefresh, IoBank::Bank0, 0)),
+            None
+        );
+        assert_eq!(
+            sdram.can_issue(&read),
+            Err(Violation {
+                cycle: 1,
+                kind: ViolationKind::TRcd,
+                bank: Some(IoBank::Bank0),
+            })
+        );
+
+        // Nothing changed, so the answers are the same, and they hold
+        assert_eq!(sdram.earliest_cycle(&read), Some(T_RCD_CYCLES as _));
+        let mut io = Io::new();
+        while sdram.cycle() < T_RCD_CYCLES as u64 {
+            sdram.clk(&mut io)?;
+        }
+        assert_eq!(sdram.can_issue(&read), Ok(()));
+        let mut io = io_for(Command::Read, IoBank::Bank0, 0);
+        sdram.clk(&mut io)?;
+
+        // Closing the row mid-burst would cut the read short
+        assert_eq!(
+            sdram.earliest_cycle(&precharge),
+            Some((T_RCD_CYCLES + BURST_LEN) as _)
+        );
+        assert!(sdram
+            .take_violations()
+            .iter()
+            .all(|violation| violation.kind == ViolationKind::UninitializedRead));
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_issue_matches_clk() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+        sdram.set_panic_on_violation(false);
+        let mut rng = Rng::new(41);
+
+        // TODO: Initialization
+
+        let mut num_violations = 0;
+        let mut cke = true;
+        for _ in 0..20_000 {
+            let mut io = Io::new();
+            // Occasionally enters and leaves self refresh
+            cke ^= rng.chance(0.05);
+            io.cke = cke;
+            if rng.chance(0.3) {
+                io.command = Command::ALL[rng.below(Command::ALL.len() as _) as usize];
+                io.bank = IoBank::from_index(rng.below(NUM_BANKS as _) as _).unwrap();
+                io.a = rng.next_u64() as u16 & (A_10_MASK | ROW_ADDR_MASK) as u16;
+            }
+
+            let predicted = sdram.can_issue(&io);
+            match sdram.earliest_cycle(&io) {
+                Some(cycle) => assert_eq!(predicted.is_ok(), cycle == sdram.cycle()),
+                None => assert!(predicted.is_err()),
+            }
+
+            sdram.clk(&mut io)?;
+            let actual = sdram.take_violations().into_iter().find(|violation| {
+                !matches!(
+                    violation.kind,
+                    ViolationKind::TRasMax
+                        | ViolationKind::TRef
+                        | ViolationKind::MissingWriteData
+                        | ViolationKind::UninitializedRead
+                        | ViolationKind::DqBusConflict
+                )
+            });
+            assert_eq!(predicted.err(), actual);
+            num_violations += actual.is_some() as u32;
+        }
+        assert!(num_violations > 1000);
+
+        Ok(())
+    }
+
+    // Issues each command after the given number of NOPs, and returns the violations other than
+    //  the ones about data
+    fn command_violations(
+        commands: &[(u32, Command, IoBank, u32)],
+    ) -> io::Result<Vec<ViolationKind>> {
+        cke_command_violations(
+            &commands
+                .iter()
+                .map(|&(num_nops, command, bank, a)| (num_nops, true, command, bank, a))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // Like `command_violations`, with an extra rule registered before the commands are issued
+    fn checked_command_violations(
+        timing_checker: Box<dyn TimingChecker>,
+        commands: &[(u32, Command, IoBank, u32)],
+    ) -> io::Result<Vec<ViolationKind>> {
+        let mut sdram = Sdram::new(None)?;
+        sdram.add_timing_checker(timing_checker);
+        sdram_command_violations(
+            sdram,
+            &commands
+                .iter()
+                .map(|&(num_nops, command, bank, a)| (num_nops, true, command, bank, a))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // Like `command_violations`, with CKE set for each command and the NOPs before it
+    fn cke_command_violations(
+        commands: &[(u32, bool, Command, IoBank, u32)],
+    ) -> io::Result<Vec<ViolationKind>> {
+        sdram_command_violations(Sdram::new(None)?, commands)
+    }
+
+    fn sdram_command_violations(
+        mut sdram: Sdram,
+        commands: &[(u32, bool, Command, IoBank, u32)],
+    ) -> io::Result<Vec<ViolationKind>> {
+        sdram.set_panic_on_violation(false);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        for &(num_nops, cke, command, bank, a) in commands {
+            io.command = Command::Nop;
+            io.cke = cke;
+            for _ in 0..num_nops {
+                sdram.clk(&mut io)?;
+            }
+            io.command = command;
+            io.bank = bank;
+            io.a = a as _;
+            sdram.clk(&mut io)?;
+        }
+
+        Ok(sdram
+            .take_violations()
+            .into_iter()
+            .map(|violation| violation.kind)
+            .filter(|kind| {
+                !matches!(
+                    kind,
+                    ViolationKind::MissingWriteData | ViolationKind::UninitializedRead
+                )
+            })
+            .collect())
+    }
+
+    #[test]
+    fn t_ccd_and_t_wtr() -> io::Result<()> {
+        // Both are a single cycle on this part, and only one command can be issued per cycle,
+        //  so there's nothing below the boundary to test
+        assert_eq!(
+            command_violations(&[
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, 0),
+                (T_CCD_CYCLES - 1, Command::Read, IoBank::Bank0, 8),
+                (T_CCD_CYCLES - 1, Command::Write, IoBank::Bank0, 16),
+                (
+                    BURST_LEN - 1 + T_WTR_CYCLES - 1,
+                    Command::Read,
+                    IoBank::Bank0,
+                    24
+                ),
+            ])?,
+            [ViolationKind::ReadToWrite]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_ccd_longer() -> io::Result<()> {
+        // The same rule as the built-in one, on a part which needs a gap between column commands
+        let t_ccd = || {
+            Box::new(MinDelay::new(
+                ViolationKind::TCcd,
+                2,
+                Scope::Device,
+                is_read_or_write_command,
+                is_read_or_write_command,
+            ))
+        };
+        for command in [Command::Read, Command::Write] {
+            let commands = |num_nops| {
+                [
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, command, IoBank::Bank0, 0),
+                    (num_nops, command, IoBank::Bank0, 8),
+                ]
+            };
+            assert_eq!(checked_command_violations(t_ccd(), &commands(1))?, []);
+            assert_eq!(
+                checked_command_violations(t_ccd(), &commands(0))?,
+                [ViolationKind::TCcd]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_wtr_longer() -> io::Result<()> {
+        let t_wtr = || {
+            Box::new(MinDelay::new(
+                ViolationKind::TWtr,
+                2,
+                Scope::Device,
+                is_write,
+                |event| *event == Event::Command(Command::Read),
+            ))
+        };
+        let commands = |num_nops| {
+            [
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RCD_CYCLES - 1, Command::Write, IoBank::Bank0, 0),
+                (num_nops, Command::Read, IoBank::Bank0, 8),
+            ]
+        };
+        // The READ comes 2 cycles after the last data in
+        let num_nops = BURST_LEN - 1 + 2 - 1;
+        assert_eq!(
+            checked_command_violations(t_wtr(), &commands(num_nops))?,
+            []
+        );
+        assert_eq!(
+            checked_command_violations(t_wtr(), &commands(num_nops - 1))?,
+            [ViolationKind::TWtr]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_dal() -> io::Result<()> {
+        let commands = |num_nops| {
+            [
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RCD_CYCLES - 1, Command::Write, IoBank::Bank0, A_10_MASK),
+                (num_nops, Command::Active, IoBank::Bank0, 0),
+            ]
+        };
+        // The ACTIVE comes `T_DAL_CYCLES` after the last data in
+        let num_nops = BURST_LEN - 1 + T_DAL_CYCLES - 1;
+        assert_eq!(command_violations(&commands(num_nops))?, []);
+        assert_eq!(
+            command_violations(&commands(num_nops - 1))?,
+            [ViolationKind::TDal]
+        );
+
+        // The row is closed by then, without waiting for tRP after the burst
+        assert_eq!(
+            command_violations(&commands(BURST_LEN - 1 + T_RP_CYCLES))?,
+            [ViolationKind::TDal]
+        );
+        assert_eq!(
+            command_violations(&commands(BURST_LEN - 1))?,
+            [ViolationKind::TRp, ViolationKind::TDal]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_auto_precharge() -> io::Result<()> {
+        let commands = |num_nops, command| {
+            [
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, A_10_MASK),
+                (num_nops, command, IoBank::Bank0, 0),
+            ]
+        };
+        // The row closes once the burst is done
+        let num_nops = BURST_LEN - 1 + T_RP_CYCLES;
+        assert_eq!(
+            command_violations(&commands(num_nops, Command::Active))?,
+            []
+        );
+        assert_eq!(
+            command_violations(&commands(num_nops - 1, Command::Active))?,
+            [ViolationKind::TRp]
+        );
+        assert_eq!(
+            command_violations(&commands(num_nops, Command::Read))?,
+            [ViolationKind::ReadWithoutActiveRow]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_mrd() -> io::Result<()> {
+        let commands = |num_nops| {
+            [
+                (0, Command::LoadModeRegister, IoBank::Bank0, 0x0033),
+                (num_nops, Command::Active, IoBank::Bank0, 0),
+            ]
+        };
+        assert_eq!(command_violations(&commands(T_MRD_CYCLES - 1))?, []);
+        assert_eq!(
+            command_violations(&commands(T_MRD_CYCLES - 2))?,
+            [ViolationKind::TMrd]
+        );
+
+        assert_eq!(
+            command_violations(&[
+                (0, Command::Active, IoBank::Bank1, 0),
+                (0, Command::LoadModeRegister, IoBank::Bank0, 0x0033),
+            ])?,
+            [ViolationKind::LoadModeRegisterWithActiveRow]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_to_precharge() -> io::Result<()> {
+        let commands = |num_nops| {
+            [
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, 0),
+                (num_nops, Command::Precharge, IoBank::Bank0, 0),
+            ]
+        };
+        assert_eq!(command_violations(&commands(BURST_LEN - 1))?, []);
+        assert_eq!(
+            command_violations(&commands(BURST_LEN - 2))?,
+            [ViolationKind::ReadToPrecharge]
+        );
+
+        // Reads from other banks don't count
+        assert_eq!(
+            command_violations(&[
+                (0, Command::Active, IoBank::Bank0, 0),
+                (T_RRD_CYCLES - 1, Command::Active, IoBank::Bank1, 0),
+                (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank1, 0),
+                (T_RAS_MIN_CYCLES, Command::Precharge, IoBank::Bank0, 0),
+            ])?,
+            []
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_to_write() -> io::Result<()> {
+        // Writes after a read, with DQM masking all read data if `is_masked`
+        let violations = |num_nops: u32, is_masked: bool| -> io::Result<Vec<ViolationKind>> {
+            let mut sdram = Sdram::new(None)?;
+            sdram.set_panic_on_violation(false);
+
+            // TODO: Initialization
+
+            let mut io = Io::new();
+            io.command = Command::Active;
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+            for _ in 0..T_RCD_CYCLES - 1 {
+                sdram.clk(&mut io)?;
+            }
+
+            // Initialize the burst so that the read actually drives the DQ bus
+            io.command = Command::Write;
+            for _ in 0..BURST_LEN {
+                io.dq_in = OptionalBytePair::some(0x1234);
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+            io.dq_in = OptionalBytePair::none();
+
+            // DQM masks read data two cycles later, so the cycle before the READ is the first
+            //  that matters
+            io.ldqm = is_masked;
+            io.udqm = is_masked;
+            sdram.clk(&mut io)?;
+            io.command = Command::Read;
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+            for _ in 0..num_nops {
+                sdram.clk(&mut io)?;
+            }
+
+            io.ldqm = false;
+            io.udqm = false;
+            io.command = Command::Write;
+            for _ in 0..BURST_LEN {
+                io.dq_in = OptionalBytePair::some(0xbabe);
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+
+            Ok(sdram
+                .take_violations()
+                .into_iter()
+                .map(|violation| violation.kind)
+                .filter(|kind| *kind != ViolationKind::UninitializedRead)
+                .collect())
+        };
+
+        // Until the last read data is out of the way
+        let num_nops = BURST_LEN - 1 + CAS_LATENCY;
+        assert_eq!(violations(num_nops, false)?, []);
+        assert_eq!(
+            violations(num_nops - 1, false)?,
+            [ViolationKind::DqBusConflict, ViolationKind::ReadToWrite]
+        );
+
+        // Masking the read data frees up the bus straight away. Otherwise the WRITE still ends
+        //  the burst, but the data already in the output pipeline runs into the write data.
+        assert_eq!(violations(0, true)?, []);
+        assert_eq!(
+            violations(0, false)?,
+            [ViolationKind::ReadToWrite, ViolationKind::DqBusConflict]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_xsr() -> io::Result<()> {
+        // Enters self refresh, stays in it for `num_self_refresh_cycles`, then issues an ACTIVE
+        //  `num_nops` after leaving it
+        let violations = |num_nops| -> io::Result<(Vec<ViolationKind>, Stats)> {
+            let mut sdram = Sdram::new(Some("Sdram__t_xsr"))?;
+            sdram.set_panic_on_violation(false);
+
+            // TODO: Initialization
+
+            let mut io = Io::new();
+            io.command = Command::AutoRefresh;
+            io.cke = false;
+            sdram.clk(&mut io)?;
+            for _ in 0..T_RFC_CYCLES {
+                // Ignored while CKE is low
+                io.command = Command::Active;
+                sdram.clk(&mut io)?;
+            }
+
+            io.command = Command::Active;
+            io.cke = true;
+            assert_eq!(
+                sdram.earliest_cycle(&io),
+                Some(sdram.cycle() + T_XSR_CYCLES as u64)
+            );
+            io.command = Command::Nop;
+            sdram.clk(&mut io)?;
+            for _ in 0..num_nops {
+                sdram.clk(&mut io)?;
+            }
+            io.command = Command::Active;
+            sdram.clk(&mut io)?;
+
+            Ok((
+                sdram
+                    .take_violations()
+                    .into_iter()
+                    .map(|violation| violation.kind)
+                    .collect(),
+                sdram.stats(),
+            ))
+        };
+
+        let (kinds, stats) = violations(T_XSR_CYCLES - 1)?;
+        assert_eq!(kinds, []);
+        // Including the cycle CKE went high on
+        assert_eq!(stats.num_self_refresh_cycles, T_RFC_CYCLES as u64 + 1);
+        assert_eq!(stats.banks[0].num_actives, 1);
+        assert_eq!(violations(T_XSR_CYCLES - 2)?.0, [ViolationKind::TXsr]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn earliest_cycle_after_write() -> io::Result<()> {
+        // A plain write has to wait out tWR before it can be precharged, and a write with auto
+        //  precharge closes the row itself, so the next ACTIVE waits out tDAL
+        let last_data_in_cycle = (T_RCD_CYCLES + BURST_LEN - 1) as u64;
+        for (a, command, expected_cycle) in [
+            (
+                0,
+                Command::Precharge,
+                last_data_in_cycle + T_WR_CYCLES as u64,
+            ),
+            (
+                A_10_MASK,
+                Command::Active,
+                last_data_in_cycle + T_DAL_CYCLES as u64,
+            ),
+        ] {
+            let mut sdram = Sdram::new(None)?;
+
+            // TODO: Initialization
+
+            let mut io = Io::new();
+            io.command = Command::Active;
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+            for _ in 0..T_RCD_CYCLES - 1 {
+                sdram.clk(&mut io)?;
+            }
+            io.command = Command::Write;
+            io.a = a as _;
+            io.dq_in = OptionalBytePair::some(0xbabe);
+            sdram.clk(&mut io)?;
+
+            // The rest of the burst happens while waiting
+            let mut next = Io::new();
+            next.command = command;
+            assert_eq!(sdram.earliest_cycle(&next), Some(expected_cycle));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn timing_boundaries() -> io::Result<()> {
+        // For each rule, the violations when the guarded command comes the given number of
+        //  cycles after the event which starts the rule. The delays themselves come from the
+        //  checkers, so these follow the part's timing parameters (there's only one part, with a
+        //  fixed CAS latency, for now).
+        type Scenario = fn(u32) -> io::Result<Vec<ViolationKind>>;
+        let scenarios: [(ViolationKind, Scenario); 14] = [
+            (ViolationKind::TRrd, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (n - 1, Command::Active, IoBank::Bank1, 0),
+                ])
+            }),
+            (ViolationKind::TRfc, |n| {
+                command_violations(&[
+                    (0, Command::AutoRefresh, IoBank::Bank0, 0),
+                    (n - 1, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TRasMin, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (n - 1, Command::Precharge, IoBank::Bank0, 0),
+                ])
+            }),
+            // tRAS min and tRP add up to more than tRC on this part, so tRP fails too
+            (ViolationKind::TRc, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RAS_MIN_CYCLES - 1, Command::Precharge, IoBank::Bank0, 0),
+                    (n - T_RAS_MIN_CYCLES - 1, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TRcd, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (n - 1, Command::Read, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TRp, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RAS_MIN_CYCLES - 1, Command::Precharge, IoBank::Bank0, 0),
+                    (n - 1, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            // From the last data in
+            (ViolationKind::TWr, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Write, IoBank::Bank0, 0),
+                    (BURST_LEN - 1 + n - 1, Command::Precharge, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TCcd, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, 0),
+                    (n - 1, Command::Read, IoBank::Bank0, BURST_LEN),
+                ])
+            }),
+            (ViolationKind::TWtr, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Write, IoBank::Bank0, 0),
+                    (BURST_LEN - 1 + n - 1, Command::Read, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TDal, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Write, IoBank::Bank0, A_10_MASK),
+                    (BURST_LEN - 1 + n - 1, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::TMrd, |n| {
+                command_violations(&[
+                    (0, Command::LoadModeRegister, IoBank::Bank0, 0x0033),
+                    (n - 1, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            // From the cycle CKE goes high
+            (ViolationKind::TXsr, |n| {
+                cke_command_violations(&[
+                    (0, false, Command::AutoRefresh, IoBank::Bank0, 0),
+                    (T_RFC_CYCLES, false, Command::Nop, IoBank::Bank0, 0),
+                    (0, true, Command::Nop, IoBank::Bank0, 0),
+                    (n - 1, true, Command::Active, IoBank::Bank0, 0),
+                ])
+            }),
+            // The start is the last column read, so the PRECHARGE can share its cycle
+            (ViolationKind::ReadToPrecharge, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, 0),
+                    (BURST_LEN - 1 + n - 1, Command::Precharge, IoBank::Bank0, 0),
+                ])
+            }),
+            (ViolationKind::ReadToWrite, |n| {
+                command_violations(&[
+                    (0, Command::Active, IoBank::Bank0, 0),
+                    (T_RCD_CYCLES - 1, Command::Read, IoBank::Bank0, 0),
+                    (BURST_LEN - 1 + n - 1, Command::Write, IoBank::Bank0, 0),
+                ])
+            }),
+        ];
+
+        let sdram = Sdram::new(None)?;
+        for checker in sdram.min_delays() {
+            let kind = checker.kind();
+            let (_, scenario) = scenarios
+                .iter()
+                .find(|(scenario_kind, _)| *scenario_kind == kind)
+                .unwrap_or_else(|| panic!("no boundary scenario for {}", kind.name()));
+
+            let num_cycles = checker.num_cycles();
+            assert!(
+                !scenario(num_cycles)?.contains(&kind),
+                "{} violated at its minimum of {} cycles",
+                kind.name(),
+                num_cycles
+            );
+            // A READ or WRITE a cycle early would interrupt the previous command's burst, which
+            //  is legal, so tCCD and tWTR can only be broken with a longer minimum
+            if matches!(kind, ViolationKind::TCcd | ViolationKind::TWtr) && num_cycles == 1 {
+                continue;
+            }
+            assert!(
+                scenario(num_cycles - 1)?.contains(&kind),
+                "{} not violated at {} cycles",
+                kind.name(),
+                num_cycles - 1
+            );
+        }
+
+        // Deadlines are reported on the cycle they're reached, so the last legal cycle is the one
+        //  before
+        let num_cycles = sdram.t_ras_max.num_cycles();
+        let t_ras_max = |n: u32| {
+            command_violations(&[
+                (0, Command::Active, IoBank::Bank0, 0),
+                (n - 1, Command::Precharge, IoBank::Bank0, 0),
+            ])
+        };
+        assert_eq!(t_ras_max(num_cycles - 1)?, []);
+        assert_eq!(t_ras_max(num_cycles)?, [ViolationKind::TRasMax]);
+
+        // tREF spans millions of cycles, so its tester is driven directly
+        let mut t_ref_tester = TRefTester::new(T_REF_CYCLES);
+        t_ref_tester.precharge(0, 0);
+        assert_eq!(t_ref_tester.clk(T_REF_CYCLES as u64 - 1), Ok(()));
+        assert_eq!(
+            t_ref_tester.clk(T_REF_CYCLES as u64),
+            Err(ViolationKind::TRef)
+        );
+
+        Ok(())
+    }
+
+    // Activates and precharges `row_addr` in bank 0 `num_times` times, as quickly as possible
+    fn hammer(sdram: &mut Sdram, row_addr: u32, num_times: u32) -> io::Result<()> {
+        let mut io = Io::new();
+        for _ in 0..num_times {
+            io.command = Command::Active;
+            io.a = row_addr as _;
+            for _ in 0..T_RAS_MIN_CYCLES {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+            io.command = Command::Precharge;
+            io.a = 0;
+            for _ in 0..T_RP_CYCLES {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn row_hammer() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+
+        // TODO: Initialization
+
+        // Row 6 is left uninitialized, so only row 4 can flip
+        let pattern = OptionalBytePair::some(0x5555);
+        for row_addr in [4, 5] {
+            sdram.banks[0].rows[row_addr].cols.fill(pattern);
+        }
+
+        // Off by default
+        hammer(&mut sdram, 5, 20)?;
+        assert_eq!(sdram.take_bit_flips(), []);
+        assert_eq!(sdram.row_activations(IoBank::Bank0, 5), 20);
+        assert_eq!(sdram.stats().banks[0].max_row_activations, 20);
+        assert_eq!(sdram.stats().banks[0].max_neighbor_activations, 20);
+
+        sdram = Sdram::new(None)?;
+        for row_addr in [4, 5] {
+            sdram.banks[0].rows[row_addr].cols.fill(pattern);
+        }
+        sdram.set_row_hammer(Some(RowHammerParams {
+            threshold: 10,
+            flip_probability: 1.0,
+            seed: 1,
+        }));
+        hammer(&mut sdram, 5, 20)?;
+        let bit_flips = sdram.take_bit_flips();
+        assert_eq!(bit_flips.len(), 10);
+        let mut expected_row = vec![pattern; NUM_COLS as usize];
+        for bit_flip in &bit_flips {
+            assert_eq!(bit_flip.bank, IoBank::Bank0);
+            assert_eq!(bit_flip.row_addr, 4);
+            let col = &mut expected_row[bit_flip.col_addr as usize];
+            let value = col.expect("initialized") ^ (1 << bit_flip.bit);
+            *col = OptionalBytePair::some(value);
+        }
+        assert!(sdram.banks[0].rows[4].cols.iter().eq(expected_row.iter()));
+        assert!(sdram.banks[0].rows[5]
+            .cols
+            .iter()
+            .all(|&col| col == pattern));
+        assert!(sdram.banks[0].rows[6]
+            .cols
+            .iter()
+            .all(|col| col.are_both_none()));
+
+        // Refreshing rows 0-4 starts the victim over, while the aggressor keeps its count
+        let mut io = Io::new();
+        for _ in 0..=4 {
+            io.command = Command::AutoRefresh;
+            for _ in 0..T_RFC_CYCLES {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+        }
+        assert_eq!(sdram.row_activations(IoBank::Bank0, 4), 0);
+        assert_eq!(sdram.row_activations(IoBank::Bank0, 5), 20);
+        hammer(&mut sdram, 5, 10)?;
+        assert_eq!(sdram.take_bit_flips(), []);
+        hammer(&mut sdram, 5, 1)?;
+        assert_eq!(sdram.take_bit_flips().len(), 1);
+        assert_eq!(sdram.stats().banks[0].max_row_activations, 31);
+
+        Ok(())
+    }
+
+    // ACT, a full WRITE burst and PRE
+    fn write_burst(
+        sdram: &mut Sdram,
+        bank: IoBank,
+        row_addr: u32,
+        col_addr: u32,
+        data: &[u16],
+    ) -> io::Result<()> {
+        let mut io = Io::new();
+        io.bank = bank;
+        io.command = Command::Active;
+        io.a = row_addr as _;
+        for _ in 0..T_RCD_CYCLES {
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+        }
+        io.command = Command::Write;
+        io.a = col_addr as _;
+        for &value in data {
+            io.dq_in = OptionalBytePair::some(value);
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+        }
+        io.dq_in = OptionalBytePair::none();
+        for _ in 0..T_WR_CYCLES {
+            sdram.clk(&mut io)?;
+        }
+        io.command = Command::Precharge;
+        for _ in 0..T_RP_CYCLES {
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+        }
+
+        Ok(())
+    }
+
+    // ACT, a full READ burst and PRE, returning the data the controller sees
+    fn read_burst(
+        sdram: &mut Sdram,
+        bank: IoBank,
+        row_addr: u32,
+        col_addr: u32,
+    ) -> io::Result<Vec<OptionalBytePair>> {
+        let mut io = Io::new();
+        io.bank = bank;
+        io.command = Command::Active;
+        io.a = row_addr as _;
+        for _ in 0..T_RCD_CYCLES {
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+        }
+        io.command = Command::Read;
+        io.a = col_addr as _;
+        let mut data = Vec::new();
+        for cycle in 0..BURST_LEN + CAS_LATENCY - 1 {
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+            if cycle >= CAS_LATENCY - 1 {
+                data.push(io.dq_out());
+            }
+        }
+        io.command = Command::Precharge;
+        for _ in 0..T_RP_CYCLES {
+            sdram.clk(&mut io)?;
+            io.command = Command::Nop;
+        }
+
+        Ok(data)
+    }
+
+    fn fault_cols(faulty_reads: &[FaultyRead], id: FaultId) -> Vec<u32> {
+        faulty_reads
+            .iter()
+            .filter(|faulty_read| faulty_read.fault_ids.contains(&id))
+            .map(|faulty_read| faulty_read.col_addr)
+            .collect()
+    }
+
+    #[test]
+    fn faults() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+
+        // TODO: Initialization
+
+        // Faults can be there from the start
+        let dead_bank = sdram.add_fault(Fault::DeadBank(IoBank::Bank2))?;
+
+        let data = (0..2 * BURST_LEN as u16)
+            .map(|index| 0xa5a5 ^ index)
+            .collect::<Vec<_>>();
+        let expected = |range: std::ops::Range<usize>| {
+            data[range]
+                .iter()
+                .map(|&value| OptionalBytePair::some(value))
+                .collect::<Vec<_>>()
+        };
+        for bank in [IoBank::Bank0, IoBank::Bank2] {
+            for (index, chunk) in data.chunks(BURST_LEN as _).enumerate() {
+                write_burst(&mut sdram, bank, 1, index as u32 * BURST_LEN, chunk)?;
+            }
+        }
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, expected(0..8));
+        assert_eq!(sdram.take_faulty_reads(), []);
+
+        // Dead banks drop writes, and reads don't drive DQ (but aren't violations either)
+        assert!(sdram.banks[2].rows[1]
+            .cols
+            .iter()
+            .all(|col| col.are_both_none()));
+        assert!(read_burst(&mut sdram, IoBank::Bank2, 1, 0)?
+            .iter()
+            .all(|data| data.are_both_none()));
+        let faulty_reads = sdram.take_faulty_reads();
+        assert_eq!(
+            fault_cols(&faulty_reads, dead_bank),
+            (0..8).collect::<Vec<_>>()
+        );
+        assert!(faulty_reads
+            .iter()
+            .all(|faulty_read| faulty_read.bank == IoBank::Bank2 && faulty_read.row_addr == 1));
+        sdram.remove_fault(dead_bank);
+        assert_eq!(sdram.fault(dead_bank), None);
+
+        // Stuck-at cells change what's read, but not what's stored
+        let cell = Cell {
+            bank: IoBank::Bank0,
+            row_addr: 1,
+            col_addr: 2,
+            bit: 1,
+        };
+        let stuck_at = sdram.add_fault(Fault::StuckAt { cell, value: false })?;
+        let mut stuck_expected = expected(0..8);
+        stuck_expected[2] = OptionalBytePair::some(data[2] & !2);
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, stuck_expected);
+        assert_eq!(
+            sdram.banks[0].rows[1].cols[2],
+            OptionalBytePair::some(data[2])
+        );
+        let faulty_reads = sdram.take_faulty_reads();
+        assert_eq!(faulty_reads.len(), 1);
+        assert_eq!(
+            faulty_reads[0].to_string(),
+            format!(
+                "cycle {}: bank 0: read of row 0x0001 col 0x002 affected by fault #1.",
+                faulty_reads[0].cycle
+            )
+        );
+        sdram.remove_fault(stuck_at);
+
+        // Transient flips change the stored bit once, which lasts until it's written again, and
+        //  can't flip uninitialized cells
+        let flip = sdram.add_fault(Fault::TransientFlip {
+            cell: Cell {
+                col_addr: 3,
+                ..cell
+            },
+            cycle: sdram.cycle(),
+        })?;
+        let uninitialized_flip = sdram.add_fault(Fault::TransientFlip {
+            cell: Cell {
+                row_addr: 2,
+                ..cell
+            },
+            cycle: sdram.cycle(),
+        })?;
+        let mut flip_expected = expected(0..8);
+        flip_expected[3] = OptionalBytePair::some(data[3] ^ 2);
+        for _ in 0..2 {
+            assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, flip_expected);
+            assert_eq!(fault_cols(&sdram.take_faulty_reads(), flip), [3]);
+        }
+        assert!(sdram.banks[0].rows[2].cols[2].are_both_none());
+        write_burst(&mut sdram, IoBank::Bank0, 1, 0, &data[0..8])?;
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, expected(0..8));
+        assert_eq!(sdram.take_faulty_reads(), []);
+        sdram.remove_fault(flip);
+        sdram.remove_fault(uninitialized_flip);
+
+        // Stuck DQ pins affect every read which drives them, whether or not they change the data
+        let stuck_dq = sdram.add_fault(Fault::StuckPin {
+            pin: Pin::Dq(15),
+            value: true,
+        })?;
+        assert!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?
+            .iter()
+            .zip(&data)
+            .all(|(read, &value)| read.expect("initialized") == value | 0x8000));
+        assert_eq!(
+            fault_cols(&sdram.take_faulty_reads(), stuck_dq),
+            (0..8).collect::<Vec<_>>()
+        );
+        sdram.remove_fault(stuck_dq);
+
+        // Stuck address pins move the burst, and only count when they changed the address
+        let stuck_a = sdram.add_fault(Fault::StuckPin {
+            pin: Pin::A(0),
+            value: true,
+        })?;
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, expected(1..9));
+        assert_eq!(
+            fault_cols(&sdram.take_faulty_reads(), stuck_a),
+            (1..9).collect::<Vec<_>>()
+        );
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 1)?, expected(1..9));
+        assert_eq!(sdram.take_faulty_reads(), []);
+        sdram.remove_fault(stuck_a);
+
+        // Shorted bank pins send bank 1 to bank 0, and the controller's IO is left alone
+        let shorted_ba = sdram.add_fault(Fault::ShortedPins(Pin::Ba(0), Pin::Ba(1)))?;
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank1, 1, 0)?, expected(0..8));
+        let faulty_reads = sdram.take_faulty_reads();
+        assert_eq!(
+            fault_cols(&faulty_reads, shorted_ba),
+            (0..8).collect::<Vec<_>>()
+        );
+        assert!(faulty_reads
+            .iter()
+            .all(|faulty_read| faulty_read.bank == IoBank::Bank0));
+        let mut io = Io::new();
+        io.bank = IoBank::Bank1;
+        io.a = 1;
+        sdram.clk(&mut io)?;
+        assert_eq!((io.bank, io.a), (IoBank::Bank1, 1));
+        sdram.remove_fault(shorted_ba);
+
+        // Faults on pins or cells which don't exist are rejected, as are shorts between DQ and
+        //  address pins
+        for fault in [
+            Fault::StuckPin {
+                pin: Pin::A(NUM_ROW_ADDR_BITS),
+                value: true,
+            },
+            Fault::StuckPin {
+                pin: Pin::Ba(NUM_BANK_ADDR_BITS),
+                value: true,
+            },
+            Fault::StuckAt {
+                cell: Cell {
+                    bit: NUM_ELEMENT_BITS,
+                    ..cell
+                },
+                value: true,
+            },
+            Fault::StuckAt {
+                cell: Cell {
+                    row_addr: NUM_ROWS,
+                    ..cell
+                },
+                value: true,
+            },
+            Fault::ShortedPins(Pin::A(0), Pin::Dq(0)),
+            Fault::ShortedPins(Pin::Dq(1), Pin::Ba(0)),
+        ] {
+            assert_eq!(
+                sdram.add_fault(fault).map_err(|error| error.kind()),
+                Err(io::ErrorKind::InvalidInput)
+            );
+        }
+        assert_eq!(read_burst(&mut sdram, IoBank::Bank0, 1, 0)?, expected(0..8));
+        assert_eq!(sdram.take_faulty_reads(), []);
+
+        assert_eq!(sdram.take_violations(), []);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,317 @@
+// Golden reference model for controllers. `ShadowChecker` wraps any `Controller` and mirrors
+//  every write (honoring byte masks) into a plain map of words, then checks the data each read
+//  actually brings back through the SDRAM against it. Only bytes which have been written are
+//  checked.
+
+use crate::controller::Controller;
+use crate::naive_controller::{byte_mask_bits, Command, NUM_WORD_BYTES};
+use crate::sdram;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub addr: u32,
+    pub expected: u128,
+    pub actual: u128,
+    // Bit `i` is set if byte `i` differs
+    pub byte_mask: u16,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Read of word 0x{:06x} returned 0x{:032x}, expected 0x{:032x} (differing bytes 0x{:04x}).",
+            self.addr, self.actual, self.expected, self.byte_mask
+        )
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ShadowWord {
+    data: u128,
+    // Bit `i` is set once byte `i` has been written
+    written_mask: u16,
+}
+
+pub struct ShadowChecker<C: Controller> {
+    controller: C,
+    words: HashMap<u32, ShadowWord>,
+
+    num_checked_reads: u64,
+    panic_on_mismatch: bool,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<C: Controller> ShadowChecker<C> {
+    pub fn new(controller: C) -> ShadowChecker<C> {
+        ShadowChecker {
+            controller,
+            words: HashMap::new(),
+
+            num_checked_reads: 0,
+            panic_on_mismatch: true,
+            mismatches: Vec::new(),
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut C {
+        &mut self.controller
+    }
+
+    pub fn into_inner(self) -> C {
+        self.controller
+    }
+
+    // Reads which had at least one written byte to check
+    pub fn num_checked_reads(&self) -> u64 {
+        self.num_checked_reads
+    }
+
+    // By default, any mismatch panics. Otherwise, mismatches are recorded and can be
+    //  retrieved with `take_mismatches`.
+    pub fn set_panic_on_mismatch(&mut self, panic_on_mismatch: bool) {
+        self.panic_on_mismatch = panic_on_mismatch;
+    }
+
+    pub fn take_mismatches(&mut self) -> Vec<Mismatch> {
+        std::mem::take(&mut self.mismatches)
+    }
+
+    fn write(&mut self, addr: u32, data: u128, mask: u16) {
+        // Writes with every byte masked don't leave anything to check
+        if !mask == 0 {
+            return;
+        }
+
+        let word = self.words.entry(addr).or_default();
+        let byte_mask = byte_mask_bits(!mask);
+        word.data = (word.data & !byte_mask) | (data & byte_mask);
+        word.written_mask |= !mask;
+    }
+
+    fn check_read(&mut self, addr: u32, actual: u128) {
+        let word = match self.words.get(&addr) {
+            Some(word) => *word,
+            None => return,
+        };
+        self.num_checked_reads += 1;
+
+        let differing_bits = (word.data ^ actual) & byte_mask_bits(word.written_mask);
+        if differing_bits == 0 {
+            return;
+        }
+
+        let mismatch = Mismatch {
+            addr,
+            expected: word.data,
+            actual,
+            byte_mask: (0..NUM_WORD_BYTES)
+                .filter(|i| (differing_bits >> (i * 8)) as u8 != 0)
+                .fold(0, |byte_mask, i| byte_mask | 1 << i),
+        };
+        if self.panic_on_mismatch {
+            panic!("{}", mismatch);
+        }
+        self.mismatches.push(mismatch);
+    }
+}
+
+impl<C: Controller> Controller for ShadowChecker<C> {
+    fn sdram(&mut self) -> &mut sdram::Sdram {
+        self.controller.sdram()
+    }
+
+    fn idle(&mut self, num_cycles: u64) -> io::Result<()> {
+        self.controller.idle(num_cycles)
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<(Option<u128>, u64)> {
+        let (data, num_cycles) = self.controller.execute(command)?;
+        match command {
+            Command::Write { addr, data, mask } => self.write(addr, data, mask),
+            Command::Read { addr } => {
+                if let Some(data) = data {
+                    self.check_read(addr, data);
+                }
+            }
+        }
+
+        Ok((data, num_cycles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::controller::Hooked;
+    use crate::naive_controller::{NaiveController, NUM_WORD_ADDR_BITS};
+    use crate::rng::Rng;
+    use crate::traffic::{Pattern, Traffic};
+    use crate::workload;
+
+    // Flips one bit of every read from word 3
+    fn faulty() -> io::Result<ShadowChecker<Hooked>> {
+        let mut c = ShadowChecker::new(Hooked::new(sdram::Sdram::new(None)?, |c, command| {
+            let (data, num_cycles) = c.execute(command)?;
+            let data = match command {
+                Command::Read { addr: 3 } => data.map(|data| data ^ 1 << 77),
+                _ => data,
+            };
+            Ok((data, num_cycles))
+        }));
+        for addr in 0..4 {
+            c.execute(Command::Write {
+                addr,
+                data: 0,
+                mask: 0,
+            })?;
+        }
+        Ok(c)
+    }
+
+    #[test]
+    fn detects_mismatch() -> io::Result<()> {
+        let mut c = faulty()?;
+        c.set_panic_on_mismatch(false);
+
+        for addr in 0..4 {
+            c.execute(Command::Read { addr })?;
+        }
+
+        assert_eq!(c.num_checked_reads(), 4);
+        assert_eq!(
+            c.take_mismatches(),
+            [Mismatch {
+                addr: 3,
+                expected: 0,
+                actual: 1 << 77,
+                byte_mask: 1 << 9,
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "Read of word 0x000003 returned")]
+    fn mismatch_panics() {
+        let mut c = faulty().unwrap();
+        c.execute(Command::Read { addr: 3 }).unwrap();
+    }
+
+    #[test]
+    fn masked_bytes_are_unchecked_until_written() -> io::Result<()> {
+        let mut c = ShadowChecker::new(faulty()?.into_inner());
+        c.set_panic_on_mismatch(false);
+
+        // Byte 9 (the faulty one) is masked off, so there's nothing to compare it against yet
+        c.execute(Command::Write {
+            addr: 3,
+            data: 0x0123456789abcdef0123456789abcdef,
+            mask: 1 << 9,
+        })?;
+        c.execute(Command::Read { addr: 3 })?;
+        assert!(c.take_mismatches().is_empty());
+
+        c.execute(Command::Write {
+            addr: 3,
+            data: 0,
+            mask: !(1 << 9),
+        })?;
+        c.execute(Command::Read { addr: 3 })?;
+        assert_eq!(c.take_mismatches().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fully_masked_writes_are_unchecked() -> io::Result<()> {
+        let mut sdram = sdram::Sdram::new(None)?;
+        // The masked write leaves the columns uninitialized
+        sdram.set_panic_on_violation(false);
+        let mut c = ShadowChecker::new(NaiveController::new(sdram));
+
+        c.execute(Command::Write {
+            addr: 3,
+            data: 0x0123456789abcdef0123456789abcdef,
+            mask: !0,
+        })?;
+        c.execute(Command::Read { addr: 3 })?;
+        assert_eq!(c.num_checked_reads(), 0);
+        assert!(c.take_mismatches().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn banks_dont_alias() -> io::Result<()> {
+        let mut c = ShadowChecker::new(NaiveController::new(sdram::Sdram::new(Some(
+            "ShadowChecker__banks_dont_alias",
+        ))?));
+
+        // Same row and column in every bank
+        let bank_addr = |bank: u32| bank << (NUM_WORD_ADDR_BITS - sdram::NUM_BANK_ADDR_BITS) | 5;
+        for bank in 0..sdram::NUM_BANKS {
+            c.execute(Command::Write {
+                addr: bank_addr(bank),
+                data: 0x1111 * (bank as u128 + 1),
+                mask: 0,
+            })?;
+        }
+        for bank in 0..sdram::NUM_BANKS {
+            c.execute(Command::Read {
+                addr: bank_addr(bank),
+            })?;
+        }
+        assert_eq!(c.num_checked_reads(), sdram::NUM_BANKS as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_masked_workload() -> io::Result<()> {
+        let mut c = ShadowChecker::new(NaiveController::new(sdram::Sdram::new(None)?));
+        let mut rng = Rng::new(39);
+
+        // A handful of words in each bank, so they're revisited often
+        let addrs = (0..32)
+            .map(|_| rng.below(1 << NUM_WORD_ADDR_BITS) as u32)
+            .collect::<Vec<_>>();
+        for &addr in &addrs {
+            c.execute(Command::Write {
+                addr,
+                data: rng.next_u128(),
+                mask: 0,
+            })?;
+        }
+        for _ in 0..2000 {
+            let addr = addrs[rng.below(addrs.len() as _) as usize];
+            let command = if rng.chance(0.5) {
+                Command::Read { addr }
+            } else {
+                Command::Write {
+                    addr,
+                    data: rng.next_u128(),
+                    mask: rng.next_u64() as u16,
+                }
+            };
+            c.execute(command)?;
+        }
+        assert!(c.num_checked_reads() > 900);
+
+        // Also works with traffic replay
+        let mut traffic = Traffic::new(Pattern::RandomUniform, 39);
+        traffic.set_region(0..256);
+        let mut fill = Traffic::new(Pattern::Sequential, 0);
+        fill.set_region(0..256);
+        fill.set_read_probability(0.0);
+        workload::replay(&mut c, fill.take(256).chain(traffic.take(500)))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,472 @@
+// Timing rules, as checked by `Sdram`. Each cycle is broken down into `Event`s, which every
+//  `TimingChecker` gets to `check` before the cycle starts, and then `record` once it has. The
+//  built-in rules are all `MinDelay`s (plus a `MaxDelay` for tRAS max), and more rules can be
+//  registered with `Sdram::add_timing_checker`.
+
+use crate::sdram::{Command, IoBank, ViolationKind, Violations, NUM_BANKS};
+
+// Something the SDRAM does during a cycle. A single command can cause several events: eg.
+//  PRECHARGE with A10 set precharges every bank with an active row, and READ both issues the
+//  command and performs the first access of its burst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    // A READ or WRITE with auto precharge closing its row once its burst is done, before any
+    //  other events in the cycle
+    AutoPrecharge { bank: IoBank },
+    // Any command except NOP, including ones issued while leaving self refresh
+    Command(Command),
+    Active { bank: IoBank, row_addr: u32 },
+    // Each cycle of a burst. `drives_dq` is false if DQM masks both bytes of the read data.
+    Read { bank: IoBank, drives_dq: bool },
+    Write { bank: IoBank },
+    // Only for banks which had an active row. Comes after the cycle's burst access, which still
+    //  sees the row.
+    Precharge { bank: IoBank },
+    // AUTO REFRESH with CKE low, after its `Command` event
+    SelfRefreshEntry,
+    // CKE going high again
+    SelfRefreshExit,
+}
+
+impl Event {
+    pub fn bank(&self) -> Option<IoBank> {
+        match *self {
+            Event::Command(_) | Event::SelfRefreshEntry | Event::SelfRefreshExit => None,
+            Event::AutoPrecharge { bank }
+            | Event::Active { bank, .. }
+            | Event::Read { bank, .. }
+            | Event::Write { bank }
+            | Event::Precharge { bank } => Some(bank),
+        }
+    }
+}
+
+pub trait TimingChecker {
+    // Tests whether `event` would break this rule if it happened `num_nops` cycles after the
+    //  upcoming one (after only NOPs in between). `earlier_events` are the events which haven't
+    //  been recorded yet but happen first (eg. earlier in the same cycle, or the rest of a burst
+    //  during the NOPs), each with how many cycles before `event` it happens. Violations are
+    //  reported against the event's bank.
+    fn check(
+        &self,
+        event: &Event,
+        earlier_events: &[(u32, Event)],
+        num_nops: u32,
+    ) -> Result<(), ViolationKind>;
+
+    // Advances to the next cycle, after its events have been checked. Deadlines which pass no
+    //  matter what's issued (eg. tRAS max) are reported here.
+    fn clk(&mut self, violations: &mut Violations);
+
+    // Called for each of the cycle's events, in order, after `clk`
+    fn record(&mut self, event: &Event);
+
+    // Longest any `check` can keep failing before waiting makes it pass, so
+    //  `Sdram::earliest_cycle` knows when to give up
+    fn max_num_wait_cycles(&self) -> u32;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    // Tracked separately for each bank, so only events in the same bank interact
+    Bank,
+    Device,
+}
+
+// Events which `guards` selects must come at least `num_cycles` cycles after the last event
+//  which `starts` selects
+#[derive(Clone)]
+pub struct MinDelay {
+    kind: ViolationKind,
+    num_cycles: u32,
+    scope: Scope,
+    starts: fn(&Event) -> bool,
+    guards: fn(&Event) -> bool,
+
+    cycle: u64,
+    start_cycles: [Option<u64>; NUM_BANKS as usize],
+}
+
+impl MinDelay {
+    pub fn new(
+        kind: ViolationKind,
+        num_cycles: u32,
+        scope: Scope,
+        starts: fn(&Event) -> bool,
+        guards: fn(&Event) -> bool,
+    ) -> MinDelay {
+        MinDelay {
+            kind,
+            num_cycles,
+            scope,
+            starts,
+            guards,
+
+            cycle: 0,
+            start_cycles: [None; NUM_BANKS as usize],
+        }
+    }
+
+    pub fn kind(&self) -> ViolationKind {
+        self.kind
+    }
+
+    pub fn num_cycles(&self) -> u32 {
+        self.num_cycles
+    }
+
+    fn index(&self, event: &Event) -> Option<usize> {
+        match self.scope {
+            Scope::Bank => event.bank().map(|bank| bank.index()),
+            Scope::Device => Some(0),
+        }
+    }
+
+    fn cycles_since_start(&self, index: usize) -> Option<u64> {
+        self.start_cycles[index].map(|start_cycle| self.cycle - start_cycle)
+    }
+
+    // Cycles left before a guarded event would be legal on the upcoming cycle
+    pub fn remaining_cycles(&self, bank: IoBank) -> u32 {
+        let index = match self.scope {
+            Scope::Bank => bank.index(),
+            Scope::Device => 0,
+        };
+        match self.cycles_since_start(index) {
+            Some(cycles_since_start) => {
+                (self.num_cycles as u64).saturating_sub(cycles_since_start + 1) as u32
+            }
+            None => 0,
+        }
+    }
+
+    // Whether `event` on the upcoming cycle would come exactly `num_cycles` after the start of
+    //  this rule, ie. as early as it's allowed to
+    pub fn is_at_minimum(&self, event: &Event) -> bool {
+        if !(self.guards)(event) {
+            return false;
+        }
+        self.index(event)
+            .and_then(|index| self.cycles_since_start(index))
+            .is_some_and(|cycles_since_start| cycles_since_start + 1 == self.num_cycles as u64)
+    }
+}
+
+impl TimingChecker for MinDelay {
+    fn check(
+        &self,
+        event: &Event,
+        earlier_events: &[(u32, Event)],
+        num_nops: u32,
+    ) -> Result<(), ViolationKind> {
+        if !(self.guards)(event) {
+            return Ok(());
+        }
+        let Some(index) = self.index(event) else {
+            return Ok(());
+        };
+
+        let is_pending = match self.cycles_since_start(index) {
+            Some(cycles_since_start) => {
+                cycles_since_start + 1 + (num_nops as u64) < self.num_cycles as u64
+            }
+            None => false,
+        };
+        let is_started_since = earlier_events
+            .iter()
+            .any(|(num_cycles_before, earlier_event)| {
+                *num_cycles_before < self.num_cycles
+                    && (self.starts)(earlier_event)
+                    && self.index(earlier_event) == Some(index)
+            });
+        if is_pending || is_started_since {
+            return Err(self.kind);
+        }
+
+        Ok(())
+    }
+
+    fn clk(&mut self, _violations: &mut Violations) {
+        self.cycle += 1;
+    }
+
+    fn record(&mut self, event: &Event) {
+        if (self.starts)(event) {
+            if let Some(index) = self.index(event) {
+                self.start_cycles[index] = Some(self.cycle);
+            }
+        }
+    }
+
+    fn max_num_wait_cycles(&self) -> u32 {
+        self.num_cycles
+    }
+}
+
+// Once an event which `starts` selects has happened, one which `stops` selects must follow
+//  within `num_cycles` cycles. Each missed deadline is only reported once.
+#[derive(Clone)]
+pub struct MaxDelay {
+    kind: ViolationKind,
+    num_cycles: u32,
+    scope: Scope,
+    starts: fn(&Event) -> bool,
+    stops: fn(&Event) -> bool,
+
+    cycle: u64,
+    deadlines: [Option<u64>; NUM_BANKS as usize],
+    // Earliest of `deadlines`, so most cycles only need one comparison
+    next_deadline: Option<u64>,
+}
+
+impl MaxDelay {
+    pub fn new(
+        kind: ViolationKind,
+        num_cycles: u32,
+        scope: Scope,
+        starts: fn(&Event) -> bool,
+        stops: fn(&Event) -> bool,
+    ) -> MaxDelay {
+        MaxDelay {
+            kind,
+            num_cycles,
+            scope,
+            starts,
+            stops,
+
+            cycle: 0,
+            deadlines: [None; NUM_BANKS as usize],
+            next_deadline: None,
+        }
+    }
+
+    pub fn kind(&self) -> ViolationKind {
+        self.kind
+    }
+
+    pub fn num_cycles(&self) -> u32 {
+        self.num_cycles
+    }
+
+    fn index(&self, event: &Event) -> Option<usize> {
+        match self.scope {
+            Scope::Bank => event.bank().map(|bank| bank.index()),
+            Scope::Device => Some(0),
+        }
+    }
+}
+
+impl TimingChecker for MaxDelay {
+    fn check(
+        &self,
+        _event: &Event,
+        _earlier_events: &[(u32, Event)],
+        _num_nops: u32,
+    ) -> Result<(), ViolationKind> {
+        Ok(())
+    }
+
+    fn clk(&mut self, violations: &mut Violations) {
+        self.cycle += 1;
+        if self
+            .next_deadline
+            .is_none_or(|deadline| self.cycle < deadline)
+        {
+            return;
+        }
+
+        for (index, deadline) in self.deadlines.iter_mut().enumerate() {
+            if deadline.is_some_and(|deadline| self.cycle >= deadline) {
+                *deadline = None;
+                let bank = match self.scope {
+                    Scope::Bank => IoBank::from_index(index),
+                    Scope::Device => None,
+                };
+                violations.push(self.kind, bank);
+            }
+        }
+        self.next_deadline = self.deadlines.iter().flatten().copied().min();
+    }
+
+    fn record(&mut self, event: &Event) {
+        if let Some(index) = self.index(event) {
+            if (self.stops)(event) {
+                self.deadlines[index] = None;
+            }
+            if (self.starts)(event) {
+                self.deadlines[index] = Some(self.cycle + self.num_cycles as u64);
+            }
+            self.next_deadline = self.deadlines.iter().flatten().copied().min();
+        }
+    }
+
+    fn max_num_wait_cycles(&self) -> u32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sdram::{Io, Sdram, Violation, T_RRD_CYCLES};
+
+    use std::collections::VecDeque;
+    use std::io;
+
+    // At most `max_num_actives` ACTIVEs in any `num_cycles` cycle window, across all banks
+    struct ActiveWindow {
+        num_cycles: u64,
+        max_num_actives: usize,
+
+        cycle: u64,
+        active_cycles: VecDeque<u64>,
+    }
+
+    impl TimingChecker for ActiveWindow {
+        fn check(
+            &self,
+            event: &Event,
+            earlier_events: &[(u32, Event)],
+            num_nops: u32,
+        ) -> Result<(), ViolationKind> {
+            if !matches!(event, Event::Active { .. }) {
+                return Ok(());
+            }
+
+            let cycle = self.cycle + num_nops as u64;
+            let num_actives = self
+                .active_cycles
+                .iter()
+                .filter(|&&active_cycle| cycle - active_cycle < self.num_cycles)
+                .count()
+                + earlier_events
+                    .iter()
+                    .filter(|(num_cycles_before, earlier_event)| {
+                        *num_cycles_before < self.num_cycles as u32
+                            && matches!(earlier_event, Event::Active { .. })
+                    })
+                    .count();
+            if num_actives >= self.max_num_actives {
+                return Err(ViolationKind::Custom("tFAW"));
+            }
+
+            Ok(())
+        }
+
+        fn clk(&mut self, _violations: &mut Violations) {
+            self.cycle += 1;
+            while self
+                .active_cycles
+                .front()
+                .is_some_and(|&active_cycle| self.cycle - active_cycle >= self.num_cycles)
+            {
+                self.active_cycles.pop_front();
+            }
+        }
+
+        fn record(&mut self, event: &Event) {
+            if matches!(event, Event::Active { .. }) {
+                self.active_cycles.push_back(self.cycle - 1);
+            }
+        }
+
+        fn max_num_wait_cycles(&self) -> u32 {
+            self.num_cycles as _
+        }
+    }
+
+    fn io_for(command: Command, bank: IoBank) -> Io {
+        let mut io = Io::new();
+        io.command = command;
+        io.bank = bank;
+        io
+    }
+
+    fn idle(sdram: &mut Sdram, num_cycles: u64) -> io::Result<()> {
+        for _ in 0..num_cycles {
+            sdram.clk(&mut Io::new())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn custom_checker() -> io::Result<()> {
+        let mut sdram = Sdram::new(Some("TimingChecker__custom_checker"))?;
+        sdram.set_panic_on_violation(false);
+        sdram.add_timing_checker(Box::new(ActiveWindow {
+            num_cycles: 20,
+            max_num_actives: 2,
+
+            cycle: 0,
+            active_cycles: VecDeque::new(),
+        }));
+
+        // TODO: Initialization
+
+        for bank in [IoBank::Bank0, IoBank::Bank1] {
+            sdram.clk(&mut io_for(Command::Active, bank))?;
+            idle(&mut sdram, T_RRD_CYCLES as u64 - 1)?;
+        }
+
+        // tRRD is met, but the window isn't
+        let mut active = io_for(Command::Active, IoBank::Bank2);
+        let violation = Violation {
+            cycle: sdram.cycle(),
+            kind: ViolationKind::Custom("tFAW"),
+            bank: Some(IoBank::Bank2),
+        };
+        assert_eq!(sdram.can_issue(&active), Err(violation));
+        assert_eq!(sdram.earliest_cycle(&active), Some(20));
+        sdram.clk(&mut active)?;
+        assert_eq!(sdram.take_violations(), [violation]);
+        assert!(violation.to_string().ends_with("bank 2: tFAW violated."));
+
+        // Bank 1's ACTIVE is still in the window, along with bank 2's
+        let num_cycles = 20 - sdram.cycle();
+        idle(&mut sdram, num_cycles)?;
+        let mut active = io_for(Command::Active, IoBank::Bank3);
+        assert!(sdram.can_issue(&active).is_err());
+        idle(&mut sdram, T_RRD_CYCLES as u64)?;
+        assert_eq!(sdram.can_issue(&active), Ok(()));
+        sdram.clk(&mut active)?;
+        assert!(sdram.take_violations().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_min_delay() -> io::Result<()> {
+        let mut sdram = Sdram::new(None)?;
+        sdram.set_panic_on_violation(false);
+        // A board which can't take activates on any bank for a while after a precharge
+        sdram.add_timing_checker(Box::new(MinDelay::new(
+            ViolationKind::Custom("Board tRP"),
+            10,
+            Scope::Device,
+            |event| matches!(event, Event::Precharge { .. }),
+            |event| matches!(event, Event::Active { .. }),
+        )));
+
+        // TODO: Initialization
+
+        sdram.clk(&mut io_for(Command::Active, IoBank::Bank0))?;
+        idle(&mut sdram, 10)?;
+        sdram.clk(&mut io_for(Command::Precharge, IoBank::Bank0))?;
+
+        let active = io_for(Command::Active, IoBank::Bank1);
+        assert_eq!(
+            sdram.can_issue(&active).map_err(|violation| violation.kind),
+            Err(ViolationKind::Custom("Board tRP"))
+        );
+        assert_eq!(sdram.earliest_cycle(&active), Some(11 + 10));
+
+        // Precharging a bank without an active row does nothing, so doesn't restart the delay
+        idle(&mut sdram, 9)?;
+        sdram.clk(&mut io_for(Command::Precharge, IoBank::Bank1))?;
+        assert_eq!(sdram.can_issue(&active), Ok(()));
+        assert!(sdram.take_violations().is_empty());
+
+        Ok(())
+    }
+}
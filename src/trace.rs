@@ -0,0 +1,1474 @@
+// Trace output for `Sdram`. The model hands each cycle to a `TraceSink`, which decides what to
+//  keep and how to store it:
+//  - `VcdSink` and `FstSink` write waveforms with the same signals, for viewing in GTKWave or
+//    replaying with `vcd_replay`. FST is much smaller for long runs.
+//  - `MemorySink` keeps every cycle around, for tests that want to look at what happened.
+//  - `TextLogSink` writes a line per command, with its data, for reading through.
+//  - `DramPowerSink` writes a command trace for DRAMPower.
+//  - `CommandLogSink` writes a compact binary log of just the commands, which
+//    `read_command_log` reads back.
+// A `Vec` of sinks passes each cycle on to all of them.
+
+use crate::coverage;
+use crate::fst;
+use crate::sdram::{
+    self, Command, IoBank, OptionalBytePair, Violation, A_10_MASK, BURST_LEN, COL_ADDR_MASK,
+    NUM_BANKS, NUM_ROW_ADDR_BITS, ROW_ADDR_MASK,
+};
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::{fs, io};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Burst {
+    Idle,
+    Read,
+    Write,
+}
+
+// Remaining cycle counts are 0 when the corresponding command is legal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BankState {
+    pub active_row: Option<u32>,
+    pub burst: Burst,
+    pub burst_remaining: u32,
+    pub t_ras: u32,
+    pub t_rc: u32,
+    pub t_rcd: u32,
+    pub t_rp: u32,
+    pub t_wr: u32,
+}
+
+// What was on the bus during a cycle, and the model's state as of the start of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceCycle {
+    pub cycle: u64,
+    pub command: Command,
+    pub cke: bool,
+    pub bank: IoBank,
+    pub a: u16,
+    pub ldqm: bool,
+    pub udqm: bool,
+    // Driven by either the controller or the model
+    pub dq: OptionalBytePair,
+    pub violations: Vec<Violation>,
+
+    pub t_rrd: u32,
+    pub t_rfc: u32,
+    pub banks: [BankState; NUM_BANKS as usize],
+}
+
+pub trait TraceSink {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()>;
+
+    // Called whenever a violation occurs, before the model (possibly) panics, so the trace can be
+    //  inspected up to that point
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+// Extra waveform signals, which are off by default to keep traces small
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceOptions {
+    // CS#, RAS#, CAS#, WE# alongside the command string, for comparing against logic analyzer
+    //  captures or RTL waveforms
+    pub pins: bool,
+    // Each bank's active row, burst state and remaining cycles on its timing parameters (in a
+    //  scope per bank), plus tRRD and tRFC
+    pub bank_state: bool,
+}
+
+// What VCD and FST have in common, so both can be written from the same set of signals
+trait Waveform {
+    type Id: Copy;
+
+    fn add_module(&mut self, name: &str) -> io::Result<()>;
+    fn upscope(&mut self) -> io::Result<()>;
+    fn add_wire(&mut self, width: u32, reference: &str) -> io::Result<Self::Id>;
+    // `width` is only a hint, for formats where strings need one
+    fn add_string(&mut self, width: u32, reference: &str) -> io::Result<Self::Id>;
+    fn enddefinitions(&mut self) -> io::Result<()>;
+
+    // In half clock periods, so there's a timestamp for each clock edge
+    fn timestamp(&mut self, time: u64) -> io::Result<()>;
+    fn change_scalar(&mut self, id: Self::Id, value: bool) -> io::Result<()>;
+    fn change_vector(&mut self, id: Self::Id, value: &[vcd::Value]) -> io::Result<()>;
+    fn change_string(&mut self, id: Self::Id, value: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+// `vcd::Writer` doesn't give access to the writer it wraps, so this keeps a second handle to it,
+//  so it can still be flushed
+struct SharedWriter<W>(Arc<Mutex<W>>);
+
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter(self.0.clone())
+    }
+}
+
+impl<W: io::Write> io::Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+struct VcdWaveform<W: io::Write> {
+    w: vcd::Writer<SharedWriter<W>>,
+    inner: SharedWriter<W>,
+}
+
+impl<W: io::Write> Waveform for VcdWaveform<W> {
+    type Id = vcd::IdCode;
+
+    fn add_module(&mut self, name: &str) -> io::Result<()> {
+        self.w.add_module(name)
+    }
+
+    fn upscope(&mut self) -> io::Result<()> {
+        self.w.upscope()
+    }
+
+    fn add_wire(&mut self, width: u32, reference: &str) -> io::Result<vcd::IdCode> {
+        self.w.add_wire(width, reference)
+    }
+
+    fn add_string(&mut self, width: u32, reference: &str) -> io::Result<vcd::IdCode> {
+        self.w.add_var(vcd::VarType::String, width, reference, None)
+    }
+
+    fn enddefinitions(&mut self) -> io::Result<()> {
+        self.w.enddefinitions()
+    }
+
+    fn timestamp(&mut self, time: u64) -> io::Result<()> {
+        self.w.timestamp(time)
+    }
+
+    fn change_scalar(&mut self, id: vcd::IdCode, value: bool) -> io::Result<()> {
+        self.w.change_scalar(id, value)
+    }
+
+    fn change_vector(&mut self, id: vcd::IdCode, value: &[vcd::Value]) -> io::Result<()> {
+        self.w.change_vector(id, value)
+    }
+
+    fn change_string(&mut self, id: vcd::IdCode, value: &str) -> io::Result<()> {
+        self.w.change_string(id, value)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.inner)
+    }
+}
+
+// FST timescales can only be powers of ten, so times are in ps to keep half periods exact
+const FST_TIMESCALE_EXPONENT: i8 = -12;
+const FST_HALF_PERIOD_PS: u64 = sdram::CLOCK_PERIOD_NS as u64 * 1000 / 2;
+
+impl<W: io::Write + io::Seek> Waveform for fst::Writer<W> {
+    type Id = fst::Handle;
+
+    fn add_module(&mut self, name: &str) -> io::Result<()> {
+        fst::Writer::add_module(self, name);
+        Ok(())
+    }
+
+    fn upscope(&mut self) -> io::Result<()> {
+        fst::Writer::upscope(self);
+        Ok(())
+    }
+
+    fn add_wire(&mut self, width: u32, reference: &str) -> io::Result<fst::Handle> {
+        Ok(fst::Writer::add_wire(self, width, reference))
+    }
+
+    fn add_string(&mut self, _width: u32, reference: &str) -> io::Result<fst::Handle> {
+        Ok(fst::Writer::add_string(self, reference))
+    }
+
+    fn enddefinitions(&mut self) -> io::Result<()> {
+        fst::Writer::enddefinitions(self)
+    }
+
+    fn timestamp(&mut self, time: u64) -> io::Result<()> {
+        fst::Writer::timestamp(self, time * FST_HALF_PERIOD_PS)
+    }
+
+    fn change_scalar(&mut self, id: fst::Handle, value: bool) -> io::Result<()> {
+        self.change_vector(id, &[value.into()])
+    }
+
+    fn change_vector(&mut self, id: fst::Handle, value: &[vcd::Value]) -> io::Result<()> {
+        fst::Writer::change_vector(self, id, value)
+    }
+
+    fn change_string(&mut self, id: fst::Handle, value: &str) -> io::Result<()> {
+        fst::Writer::change_string(self, id, value)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        fst::Writer::flush(self)
+    }
+}
+
+struct ScalarSignal<F: Waveform> {
+    value: Option<bool>,
+    id: F::Id,
+}
+
+impl<F: Waveform> ScalarSignal<F> {
+    fn new(reference: &str, w: &mut F) -> io::Result<ScalarSignal<F>> {
+        let id = w.add_wire(1, reference)?;
+        Ok(ScalarSignal { value: None, id })
+    }
+
+    fn update(&mut self, value: bool, w: &mut F) -> io::Result<()> {
+        if self.value == Some(value) {
+            return Ok(());
+        }
+
+        w.change_scalar(self.id, value)?;
+        self.value = Some(value);
+
+        Ok(())
+    }
+}
+
+struct VectorSignal<F: Waveform> {
+    width: u32,
+    value: Option<Box<[vcd::Value]>>,
+    id: F::Id,
+}
+
+impl<F: Waveform> VectorSignal<F> {
+    fn new(width: u32, reference: &str, w: &mut F) -> io::Result<VectorSignal<F>> {
+        let id = w.add_wire(width, reference)?;
+        Ok(VectorSignal {
+            width,
+            value: None,
+            id,
+        })
+    }
+
+    fn update(&mut self, value: Box<[vcd::Value]>, w: &mut F) -> io::Result<()> {
+        assert_eq!(self.width, value.len() as _);
+        if self.value.as_ref() == Some(&value) {
+            return Ok(());
+        }
+
+        w.change_vector(self.id, &value)?;
+        self.value = Some(value);
+
+        Ok(())
+    }
+
+    fn update_counter(&mut self, value: u32, w: &mut F) -> io::Result<()> {
+        self.update(counter_bits(value, self.width), w)
+    }
+}
+
+struct StringSignal<F: Waveform> {
+    value: Option<String>,
+    id: F::Id,
+}
+
+impl<F: Waveform> StringSignal<F> {
+    fn new(width: u32, reference: &str, w: &mut F) -> io::Result<StringSignal<F>> {
+        let id = w.add_string(width, reference)?;
+        Ok(StringSignal { value: None, id })
+    }
+
+    fn update(&mut self, value: String, w: &mut F) -> io::Result<()> {
+        if self.value.as_ref() == Some(&value) {
+            return Ok(());
+        }
+
+        w.change_string(self.id, &value)?;
+        self.value = Some(value);
+
+        Ok(())
+    }
+}
+
+struct PinSignals<F: Waveform> {
+    cke: ScalarSignal<F>,
+    cs_n: ScalarSignal<F>,
+    ras_n: ScalarSignal<F>,
+    cas_n: ScalarSignal<F>,
+    we_n: ScalarSignal<F>,
+}
+
+struct BankSignals<F: Waveform> {
+    active_row: VectorSignal<F>,
+    burst: StringSignal<F>,
+    burst_remaining: VectorSignal<F>,
+    t_ras: VectorSignal<F>,
+    t_rc: VectorSignal<F>,
+    t_rcd: VectorSignal<F>,
+    t_rp: VectorSignal<F>,
+    t_wr: VectorSignal<F>,
+}
+
+struct BankStateSignals<F: Waveform> {
+    t_rrd: VectorSignal<F>,
+    t_rfc: VectorSignal<F>,
+    banks: Vec<BankSignals<F>>,
+}
+
+// Counter signals only need to be wide enough for their largest value
+fn counter_signal<F: Waveform>(
+    max: u32,
+    reference: &str,
+    w: &mut F,
+) -> io::Result<VectorSignal<F>> {
+    VectorSignal::new(u32::BITS - max.leading_zeros(), reference, w)
+}
+
+fn counter_bits(value: u32, width: u32) -> Box<[vcd::Value]> {
+    (0..width)
+        .rev()
+        .map(|i| match (value >> i) & 1 {
+            0 => vcd::Value::V0,
+            _ => vcd::Value::V1,
+        })
+        .collect()
+}
+
+fn byte_bits(byte: Option<u8>) -> Box<[vcd::Value]> {
+    byte.map_or_else(
+        || vec![vcd::Value::Z; 8].into(),
+        |byte| counter_bits(byte as _, 8),
+    )
+}
+
+// Eg. "tRCD:bank1,tRFC"
+fn violation_names(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|violation| match violation.bank {
+            Some(bank) => format!("{}:bank{}", violation.kind.name(), bank.index()),
+            None => violation.kind.name().into(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Each cycle's signals are written at even timestamps, with the rising edge in between
+struct Signals<F: Waveform> {
+    clk: ScalarSignal<F>,
+    command: StringSignal<F>,
+    ldqm: ScalarSignal<F>,
+    udqm: ScalarSignal<F>,
+    bank: VectorSignal<F>,
+    a: VectorSignal<F>,
+    dq: VectorSignal<F>,
+    // Names and banks of any violations on each cycle, eg. "tRCD:bank1", or "none"
+    violation: StringSignal<F>,
+
+    pins: Option<PinSignals<F>>,
+    bank_state: Option<BankStateSignals<F>>,
+
+    time_stamp: u64,
+}
+
+impl<F: Waveform> Signals<F> {
+    fn new(options: TraceOptions, w: &mut F) -> io::Result<Signals<F>> {
+        w.add_module("sdram")?;
+
+        let clk = ScalarSignal::new("clk", w)?;
+        // Strings have no defined width in VCD, so size it for the longest command name
+        let command_width = Command::ALL
+            .iter()
+            .map(|command| format!("{:?}", command).len())
+            .max()
+            .unwrap() as u32
+            * 8;
+        let command = StringSignal::new(command_width, "command", w)?;
+        let ldqm = ScalarSignal::new("ldqm", w)?;
+        let udqm = ScalarSignal::new("udqm", w)?;
+        let bank = VectorSignal::new(2, "bank", w)?;
+        let a = VectorSignal::new(NUM_ROW_ADDR_BITS, "a", w)?;
+        let dq = VectorSignal::new(16, "dq", w)?;
+        // As with commands, strings have no defined width; this fits a few violations
+        let violation = StringSignal::new(64 * 8, "violation", w)?;
+
+        let pins = if options.pins {
+            Some(PinSignals {
+                cke: ScalarSignal::new("cke", w)?,
+                cs_n: ScalarSignal::new("cs_n", w)?,
+                ras_n: ScalarSignal::new("ras_n", w)?,
+                cas_n: ScalarSignal::new("cas_n", w)?,
+                we_n: ScalarSignal::new("we_n", w)?,
+            })
+        } else {
+            None
+        };
+
+        let bank_state = if options.bank_state {
+            let t_rrd = counter_signal(sdram::T_RRD_CYCLES, "t_rrd", w)?;
+            let t_rfc = counter_signal(sdram::T_RFC_CYCLES, "t_rfc", w)?;
+            let mut banks = Vec::new();
+            for index in 0..NUM_BANKS {
+                w.add_module(&format!("bank{}", index))?;
+                banks.push(BankSignals {
+                    active_row: VectorSignal::new(NUM_ROW_ADDR_BITS, "active_row", w)?,
+                    // Longest value is "Write"
+                    burst: StringSignal::new(5 * 8, "burst", w)?,
+                    burst_remaining: counter_signal(BURST_LEN, "burst_remaining", w)?,
+                    t_ras: counter_signal(sdram::T_RAS_MIN_CYCLES, "t_ras", w)?,
+                    t_rc: counter_signal(sdram::T_RC_CYCLES, "t_rc", w)?,
+                    t_rcd: counter_signal(sdram::T_RCD_CYCLES, "t_rcd", w)?,
+                    t_rp: counter_signal(sdram::T_RP_CYCLES, "t_rp", w)?,
+                    t_wr: counter_signal(sdram::T_WR_CYCLES, "t_wr", w)?,
+                });
+                w.upscope()?;
+            }
+            Some(BankStateSignals {
+                t_rrd,
+                t_rfc,
+                banks,
+            })
+        } else {
+            None
+        };
+
+        w.upscope()?;
+        w.enddefinitions()?;
+
+        let time_stamp = 0;
+        w.timestamp(time_stamp)?;
+
+        Ok(Signals {
+            clk,
+            command,
+            ldqm,
+            udqm,
+            bank,
+            a,
+            dq,
+            violation,
+
+            pins,
+            bank_state,
+
+            time_stamp,
+        })
+    }
+
+    fn cycle(&mut self, cycle: &TraceCycle, w: &mut F) -> io::Result<()> {
+        self.clk.update(false, w)?;
+
+        self.command.update(format!("{:?}", cycle.command), w)?;
+        self.ldqm.update(cycle.ldqm, w)?;
+        self.udqm.update(cycle.udqm, w)?;
+        self.bank.update_counter(cycle.bank.index() as _, w)?;
+        self.a.update_counter(cycle.a as _, w)?;
+        self.dq.update(
+            byte_bits(cycle.dq.high)
+                .iter()
+                .chain(byte_bits(cycle.dq.low).iter())
+                .cloned()
+                .collect(),
+            w,
+        )?;
+
+        if let Some(pins) = &mut self.pins {
+            let levels = cycle.command.pins();
+            pins.cke.update(cycle.cke, w)?;
+            pins.cs_n.update(levels.cs_n, w)?;
+            pins.ras_n.update(levels.ras_n, w)?;
+            pins.cas_n.update(levels.cas_n, w)?;
+            pins.we_n.update(levels.we_n, w)?;
+        }
+
+        if let Some(bank_state) = &mut self.bank_state {
+            bank_state.t_rrd.update_counter(cycle.t_rrd, w)?;
+            bank_state.t_rfc.update_counter(cycle.t_rfc, w)?;
+            for (bank, signals) in cycle.banks.iter().zip(&mut bank_state.banks) {
+                signals.active_row.update(
+                    bank.active_row.map_or_else(
+                        || vec![vcd::Value::Z; NUM_ROW_ADDR_BITS as usize].into(),
+                        |row_addr| counter_bits(row_addr, NUM_ROW_ADDR_BITS),
+                    ),
+                    w,
+                )?;
+                signals.burst.update(format!("{:?}", bank.burst), w)?;
+                signals
+                    .burst_remaining
+                    .update_counter(bank.burst_remaining, w)?;
+                signals.t_ras.update_counter(bank.t_ras, w)?;
+                signals.t_rc.update_counter(bank.t_rc, w)?;
+                signals.t_rcd.update_counter(bank.t_rcd, w)?;
+                signals.t_rp.update_counter(bank.t_rp, w)?;
+                signals.t_wr.update_counter(bank.t_wr, w)?;
+            }
+        }
+
+        // The rising edge carries the cycle's violations
+        self.time_stamp += 1;
+        w.timestamp(self.time_stamp)?;
+        self.clk.update(true, w)?;
+        let violation = if cycle.violations.is_empty() {
+            "none".into()
+        } else {
+            violation_names(&cycle.violations)
+        };
+        self.violation.update(violation, w)?;
+        self.time_stamp += 1;
+        w.timestamp(self.time_stamp)
+    }
+}
+
+pub struct VcdSink<W: io::Write> {
+    w: VcdWaveform<W>,
+    signals: Signals<VcdWaveform<W>>,
+}
+
+impl<W: io::Write> VcdSink<W> {
+    pub fn new(w: W, options: TraceOptions) -> io::Result<VcdSink<W>> {
+        let inner = SharedWriter(Arc::new(Mutex::new(w)));
+        let mut w = VcdWaveform {
+            w: vcd::Writer::new(inner.clone()),
+            inner,
+        };
+        w.w.timescale(sdram::CLOCK_PERIOD_NS / 2, vcd::TimescaleUnit::NS)?;
+        let signals = Signals::new(options, &mut w)?;
+        Ok(VcdSink { w, signals })
+    }
+}
+
+impl VcdSink<io::BufWriter<fs::File>> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        options: TraceOptions,
+    ) -> io::Result<VcdSink<io::BufWriter<fs::File>>> {
+        VcdSink::new(io::BufWriter::new(fs::File::create(path)?), options)
+    }
+}
+
+impl<W: io::Write> TraceSink for VcdSink<W> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        self.signals.cycle(cycle, &mut self.w)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+// FST needs to go back and patch its header, hence `Seek`
+pub struct FstSink<W: io::Write + io::Seek> {
+    w: fst::Writer<W>,
+    signals: Signals<fst::Writer<W>>,
+}
+
+impl<W: io::Write + io::Seek> FstSink<W> {
+    pub fn new(w: W, options: TraceOptions) -> io::Result<FstSink<W>> {
+        let mut w = fst::Writer::new(w, FST_TIMESCALE_EXPONENT)?;
+        let signals = Signals::new(options, &mut w)?;
+        Ok(FstSink { w, signals })
+    }
+}
+
+impl FstSink<io::BufWriter<fs::File>> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        options: TraceOptions,
+    ) -> io::Result<FstSink<io::BufWriter<fs::File>>> {
+        FstSink::new(io::BufWriter::new(fs::File::create(path)?), options)
+    }
+}
+
+impl<W: io::Write + io::Seek> TraceSink for FstSink<W> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        self.signals.cycle(cycle, &mut self.w)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Waveform::flush(&mut self.w)
+    }
+}
+
+// Clones share the same cycles, so keep one to look at them after handing the other to `Sdram`
+#[derive(Clone, Default)]
+pub struct MemorySink(Arc<Mutex<Vec<TraceCycle>>>);
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        Default::default()
+    }
+
+    pub fn cycles(&self) -> Vec<TraceCycle> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl TraceSink for MemorySink {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        self.0.lock().unwrap().push(cycle.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Any number of sinks, each getting every cycle
+impl TraceSink for Vec<Box<dyn TraceSink>> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        for sink in self.iter_mut() {
+            sink.cycle(cycle)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for sink in self.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+struct TextLogLine {
+    command: Command,
+    text: String,
+    // Cycles the command's data is on the bus, which is CAS latency later for reads
+    data_cycles: Range<u64>,
+    data: Vec<String>,
+}
+
+// One line per command (and per cycle with violations), eg.
+//         3  Write        bank1  col 0x008  beef --ad 1234 ...  ! tRCD:bank1
+//  Read and write lines include the burst's data, high byte first, with "--" for bytes that
+//  were masked off. Lines are written once their data is complete.
+pub struct TextLogSink<W: io::Write> {
+    w: W,
+    lines: VecDeque<TextLogLine>,
+}
+
+impl<W: io::Write> TextLogSink<W> {
+    pub fn new(w: W) -> TextLogSink<W> {
+        TextLogSink {
+            w,
+            lines: VecDeque::new(),
+        }
+    }
+
+    fn write_line(&mut self, line: TextLogLine) -> io::Result<()> {
+        let mut text = line.text;
+        if !line.data.is_empty() {
+            text = format!("{:<43}{}", text, line.data.join(" "));
+        }
+        writeln!(self.w, "{}", text.trim_end())
+    }
+}
+
+impl TextLogSink<io::BufWriter<fs::File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<TextLogSink<io::BufWriter<fs::File>>> {
+        Ok(TextLogSink::new(io::BufWriter::new(fs::File::create(
+            path,
+        )?)))
+    }
+}
+
+impl<W: io::Write> TraceSink for TextLogSink<W> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        let data_start = match cycle.command {
+            Command::Read => Some(cycle.cycle + sdram::CAS_LATENCY as u64),
+            Command::Write => Some(cycle.cycle),
+            _ => None,
+        };
+        if let Some(data_start) = data_start {
+            // A new burst cuts off any burst still in progress
+            for line in &mut self.lines {
+                line.data_cycles.end = line.data_cycles.end.min(data_start);
+            }
+        }
+
+        if cycle.command != Command::Nop || !cycle.violations.is_empty() {
+            let bank = format!("bank{}", cycle.bank.index());
+            let (bank, address) = match cycle.command {
+                Command::Active => (bank, format!("row {:#06x}", cycle.a as u32 & ROW_ADDR_MASK)),
+                Command::Read | Command::Write if cycle.a as u32 & A_10_MASK != 0 => (
+                    bank,
+                    format!("col {:#05x} AP", cycle.a as u32 & COL_ADDR_MASK),
+                ),
+                Command::Read | Command::Write => {
+                    (bank, format!("col {:#05x}", cycle.a as u32 & COL_ADDR_MASK))
+                }
+                Command::Precharge if cycle.a as u32 & A_10_MASK != 0 => ("all".into(), "".into()),
+                Command::Precharge => (bank, "".into()),
+                Command::LoadModeRegister => ("".into(), format!("mode {:#06x}", cycle.a)),
+                Command::AutoRefresh | Command::Nop => ("".into(), "".into()),
+            };
+            let command = match cycle.command {
+                Command::AutoRefresh if !cycle.cke => "SelfRefresh".into(),
+                command => format!("{:?}", command),
+            };
+            let mut text = format!(
+                "{:>10}  {:<11}  {:<5}  {:<10}",
+                cycle.cycle, command, bank, address
+            );
+            if !cycle.violations.is_empty() {
+                text = format!("{}  ! {}", text, violation_names(&cycle.violations));
+            }
+            let data_start = data_start.unwrap_or(cycle.cycle);
+            self.lines.push_back(TextLogLine {
+                command: cycle.command,
+                text,
+                data_cycles: data_start..match cycle.command {
+                    Command::Read | Command::Write => data_start + BURST_LEN as u64,
+                    _ => data_start,
+                },
+                data: Vec::new(),
+            });
+        }
+
+        for line in &mut self.lines {
+            if line.data_cycles.contains(&cycle.cycle) {
+                // Write data is masked on the same cycle, whereas the model has already masked
+                //  read data
+                let (ldqm, udqm) = match line.command {
+                    Command::Write => (cycle.ldqm, cycle.udqm),
+                    _ => (false, false),
+                };
+                let byte = |byte: Option<u8>, masked: bool| match byte {
+                    Some(byte) if !masked => format!("{:02x}", byte),
+                    _ => "--".into(),
+                };
+                line.data.push(format!(
+                    "{}{}",
+                    byte(cycle.dq.high, udqm),
+                    byte(cycle.dq.low, ldqm)
+                ));
+            }
+        }
+
+        while self
+            .lines
+            .front()
+            .is_some_and(|line| line.data_cycles.end <= cycle.cycle + 1)
+        {
+            let line = self.lines.pop_front().unwrap();
+            self.write_line(line)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W: io::Write> Drop for TextLogSink<W> {
+    // Whatever data there is for bursts that didn't finish
+    fn drop(&mut self) {
+        while let Some(line) = self.lines.pop_front() {
+            if self.write_line(line).is_err() {
+                return;
+            }
+        }
+        let _ = self.w.flush();
+    }
+}
+
+// Command trace as read by DRAMPower (the `-t` option of its legacy command line tool): one
+//  `cycle,command,bank` line per command, ending with END at the cycle after the last. Pair it
+//  with a memspec describing this part (see `power::PowerParams`) to cross-check energy numbers.
+pub struct DramPowerSink<W: io::Write> {
+    w: W,
+    num_cycles: u64,
+    is_self_refreshing: bool,
+}
+
+impl<W: io::Write> DramPowerSink<W> {
+    pub fn new(w: W) -> DramPowerSink<W> {
+        DramPowerSink {
+            w,
+            num_cycles: 0,
+            is_self_refreshing: false,
+        }
+    }
+}
+
+impl DramPowerSink<io::BufWriter<fs::File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<DramPowerSink<io::BufWriter<fs::File>>> {
+        Ok(DramPowerSink::new(io::BufWriter::new(fs::File::create(
+            path,
+        )?)))
+    }
+}
+
+impl<W: io::Write> TraceSink for DramPowerSink<W> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        self.num_cycles = cycle.cycle + 1;
+        // Commands are ignored until CKE goes high again
+        if self.is_self_refreshing {
+            if !cycle.cke {
+                return Ok(());
+            }
+            self.is_self_refreshing = false;
+            writeln!(self.w, "{},SREX,0", cycle.cycle)?;
+        }
+
+        let command = match coverage::Command::decode(cycle.command, cycle.a, cycle.cke) {
+            // DRAMPower has no mode register command; it takes the timings from the memspec
+            Some(coverage::Command::LoadModeRegister) | None => return Ok(()),
+            Some(command) => command,
+        };
+        if command == coverage::Command::SelfRefresh {
+            self.is_self_refreshing = true;
+        }
+        let bank = if command.is_all_banks() {
+            0
+        } else {
+            cycle.bank.index()
+        };
+        writeln!(self.w, "{},{},{}", cycle.cycle, command.name(), bank)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W: io::Write> Drop for DramPowerSink<W> {
+    fn drop(&mut self) {
+        let _ = writeln!(self.w, "{},END,0", self.num_cycles);
+        let _ = self.w.flush();
+    }
+}
+
+// Command log format: COMMAND_LOG_MAGIC, followed by a record for every cycle with a command
+//  other than NOP, or where CKE changed:
+//  - cycles since the previous record (or since cycle 0), as a LEB128 varint
+//  - command (its index in `Command::ALL`) in bits 0-2, bank in bits 3-4, bit 5 set if CKE is
+//    low
+//  - A as a LEB128 varint
+// So most commands take 3-4 bytes, and idle stretches take none.
+const COMMAND_LOG_MAGIC: &[u8; 8] = b"DRAMCMD1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoggedCommand {
+    pub cycle: u64,
+    pub command: Command,
+    pub cke: bool,
+    pub bank: IoBank,
+    pub a: u16,
+}
+
+pub struct CommandLogSink<W: io::Write> {
+    w: W,
+    last_cycle: u64,
+    cke: bool,
+}
+
+impl<W: io::Write> CommandLogSink<W> {
+    pub fn new(mut w: W) -> io::Result<CommandLogSink<W>> {
+        w.write_all(COMMAND_LOG_MAGIC)?;
+        Ok(CommandLogSink {
+            w,
+            last_cycle: 0,
+            cke: true,
+        })
+    }
+}
+
+impl CommandLogSink<io::BufWriter<fs::File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<CommandLogSink<io::BufWriter<fs::File>>> {
+        CommandLogSink::new(io::BufWriter::new(fs::File::create(path)?))
+    }
+}
+
+fn write_varint(w: &mut impl io::Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+// `None` at the end of the input
+fn read_varint(r: &mut impl io::Read) -> io::Result<Option<u64>> {
+    let mut value = 0;
+    for i in 0..10 {
+        let mut byte = [0];
+        if r.read(&mut byte)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Varint too long",
+    ))
+}
+
+impl<W: io::Write> TraceSink for CommandLogSink<W> {
+    fn cycle(&mut self, cycle: &TraceCycle) -> io::Result<()> {
+        if cycle.command == Command::Nop && cycle.cke == self.cke {
+            return Ok(());
+        }
+
+        let command_index = Command::ALL
+            .iter()
+            .position(|command| *command == cycle.command)
+            .unwrap();
+        write_varint(&mut self.w, cycle.cycle - self.last_cycle)?;
+        self.w.write_all(&[command_index as u8
+            | (cycle.bank.index() as u8) << 3
+            | (!cycle.cke as u8) << 5])?;
+        write_varint(&mut self.w, cycle.a as _)?;
+        self.last_cycle = cycle.cycle;
+        self.cke = cycle.cke;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+pub fn read_command_log(mut r: impl io::Read) -> io::Result<Vec<LoggedCommand>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut magic = [0; COMMAND_LOG_MAGIC.len()];
+    r.read_exact(&mut magic)?;
+    if &magic != COMMAND_LOG_MAGIC {
+        return Err(invalid("Not a command log"));
+    }
+
+    let mut ret = Vec::new();
+    let mut cycle = 0;
+    while let Some(delta) = read_varint(&mut r)? {
+        cycle += delta;
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+        let command = *Command::ALL
+            .get((byte[0] & 0x07) as usize)
+            .ok_or_else(|| invalid("Invalid command"))?;
+        let bank = IoBank::from_index((byte[0] >> 3) as usize & 0x03).unwrap();
+        let a =
+            read_varint(&mut r)?.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        ret.push(LoggedCommand {
+            cycle,
+            command,
+            cke: byte[0] & 0x20 == 0,
+            bank,
+            a: a as _,
+        });
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::{self, NaiveController};
+    use crate::sdram::{Io, Sdram, ViolationKind};
+
+    use fst_reader::{FstFilter, FstHierarchyEntry, FstReader, FstSignalValue};
+
+    // Values of a trace signal, as of each timestamp it changed
+    fn trace_changes(file_name: &str, path: &[&str]) -> io::Result<Vec<(u64, Vec<vcd::Value>)>> {
+        let file = fs::File::open(file_name)?;
+        let mut parser = vcd::Parser::new(io::BufReader::new(file));
+        let header = parser.parse_header()?;
+        let code = header.find_var(path).unwrap().code;
+
+        let mut time_stamp = 0;
+        let mut ret = Vec::new();
+        for command in parser {
+            match command? {
+                vcd::Command::Timestamp(t) => time_stamp = t,
+                vcd::Command::ChangeScalar(id, value) if id == code => {
+                    ret.push((time_stamp, vec![value]))
+                }
+                vcd::Command::ChangeVector(id, value) if id == code => {
+                    ret.push((time_stamp, value))
+                }
+                _ => (),
+            }
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn vcd_options() -> io::Result<()> {
+        {
+            let mut sdram = Sdram::with_trace_options(
+                Some("Trace__vcd_options"),
+                TraceOptions {
+                    pins: true,
+                    bank_state: true,
+                },
+            )?;
+
+            // TODO: Initialization
+
+            let mut io = Io::new();
+            io.command = Command::Active;
+            io.bank = IoBank::Bank1;
+            io.a = 42;
+            for _ in 0..sdram::T_RCD_CYCLES + 1 {
+                sdram.clk(&mut io)?;
+                io.command = Command::Nop;
+            }
+        }
+
+        let file_name = "vcd/Trace__vcd_options.vcd";
+        let (v0, v1, z) = (vcd::Value::V0, vcd::Value::V1, vcd::Value::Z);
+
+        // Each cycle's signals are written at even timestamps, with the rising edge in between
+        assert_eq!(
+            trace_changes(file_name, &["sdram", "ras_n"])?,
+            [(0, vec![v0]), (2, vec![v1])]
+        );
+        assert_eq!(
+            trace_changes(file_name, &["sdram", "cs_n"])?,
+            [(0, vec![v0])]
+        );
+
+        let active_row = trace_changes(file_name, &["sdram", "bank1", "active_row"])?;
+        assert_eq!(active_row[0], (0, vec![z; NUM_ROW_ADDR_BITS as usize]));
+        assert_eq!(
+            active_row[1],
+            (2, counter_bits(42, NUM_ROW_ADDR_BITS).into())
+        );
+        assert_eq!(
+            trace_changes(file_name, &["sdram", "bank0", "active_row"])?.len(),
+            1
+        );
+
+        // Counts down to 0 on the first cycle a READ or WRITE would be legal
+        let t_rcd = trace_changes(file_name, &["sdram", "bank1", "t_rcd"])?;
+        let width = u32::BITS - sdram::T_RCD_CYCLES.leading_zeros();
+        let expected = (0..=sdram::T_RCD_CYCLES)
+            .map(|cycle| {
+                let remaining = if cycle == 0 {
+                    0
+                } else {
+                    sdram::T_RCD_CYCLES - cycle
+                };
+                (cycle as u64 * 2, counter_bits(remaining, width).into())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(t_rcd, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vcd_violations() -> io::Result<()> {
+        let file_name = "vcd/Trace__vcd_violations.vcd";
+
+        let mut sdram = Sdram::new(Some("Trace__vcd_violations"))?;
+        sdram.set_panic_on_violation(false);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        io.command = Command::Active;
+        sdram.clk(&mut io)?;
+        io.bank = IoBank::Bank1;
+        sdram.clk(&mut io)?;
+
+        // The trace is flushed as soon as there's a violation, while the model is still alive
+        let strings = |file_name| -> io::Result<Vec<String>> {
+            Ok(fs::read_to_string(file_name)?
+                .lines()
+                .filter(|line| line.starts_with('s'))
+                .map(|line| line.to_string())
+                .collect())
+        };
+        let id = vcd::Parser::new(io::BufReader::new(fs::File::open(file_name)?))
+            .parse_header()?
+            .find_var(&["sdram", "violation"])
+            .unwrap()
+            .code;
+        assert_eq!(
+            strings(file_name)?
+                .iter()
+                .filter(|line| line.ends_with(&format!(" {}", id)))
+                .collect::<Vec<_>>(),
+            [&format!("snone {}", id), &format!("stRRD:bank1 {}", id)]
+        );
+
+        io.command = Command::Nop;
+        sdram.clk(&mut io)?;
+        drop(sdram);
+
+        let file = fs::File::open(file_name)?;
+        let mut parser = vcd::Parser::new(io::BufReader::new(file));
+        let header = parser.parse_header()?;
+        let code = header.find_var(&["sdram", "violation"]).unwrap().code;
+        let mut time_stamp = 0;
+        let mut changes = Vec::new();
+        for command in parser {
+            match command? {
+                vcd::Command::Timestamp(t) => time_stamp = t,
+                vcd::Command::ChangeString(id, value) if id == code => {
+                    changes.push((time_stamp, value))
+                }
+                _ => (),
+            }
+        }
+        // Violations are marked on the rising edge of the cycle they occurred on
+        assert_eq!(
+            changes,
+            [
+                (1, "none".to_string()),
+                (3, "tRRD:bank1".to_string()),
+                (5, "none".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    // A few commands, with a tRRD violation on cycle 1
+    fn run(sdram: &mut Sdram) -> io::Result<()> {
+        sdram.set_panic_on_violation(false);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        io.command = Command::Active;
+        io.a = 0x1234;
+        sdram.clk(&mut io)?;
+        io.bank = IoBank::Bank1;
+        sdram.clk(&mut io)?;
+        io.command = Command::Nop;
+        for _ in 0..sdram::T_RCD_CYCLES {
+            sdram.clk(&mut io)?;
+        }
+        io.command = Command::Write;
+        io.a = 8;
+        io.dq_in = OptionalBytePair::some(0xbeef);
+        sdram.clk(&mut io)?;
+        io.command = Command::Nop;
+        sdram.clk(&mut io)
+    }
+
+    #[test]
+    fn memory_sink() -> io::Result<()> {
+        let sink = MemorySink::new();
+        let other_sink = MemorySink::new();
+        let sinks: Vec<Box<dyn TraceSink>> =
+            vec![Box::new(sink.clone()), Box::new(other_sink.clone())];
+        let mut sdram = Sdram::with_trace_sink(Some(Box::new(sinks)));
+        run(&mut sdram)?;
+
+        let cycles = sink.cycles();
+        assert_eq!(other_sink.cycles(), cycles);
+        assert_eq!(cycles.len(), 4 + sdram::T_RCD_CYCLES as usize);
+        assert_eq!(cycles[0].command, Command::Active);
+        assert!(cycles[0].violations.is_empty());
+        assert_eq!(cycles[0].banks[0].active_row, None);
+        assert_eq!(cycles[1].bank, IoBank::Bank1);
+        assert_eq!(cycles[1].banks[0].active_row, Some(0x1234));
+        assert_eq!(
+            cycles[1]
+                .violations
+                .iter()
+                .map(|violation| violation.kind)
+                .collect::<Vec<_>>(),
+            [ViolationKind::TRrd]
+        );
+
+        let write = &cycles[2 + sdram::T_RCD_CYCLES as usize];
+        assert_eq!(write.cycle, 2 + sdram::T_RCD_CYCLES as u64);
+        assert_eq!(write.command, Command::Write);
+        assert_eq!(write.dq, OptionalBytePair::some(0xbeef));
+        assert_eq!(write.banks[1].t_rcd, 0);
+        assert_eq!(write.banks[1].burst, Burst::Idle);
+        assert_eq!(cycles.last().unwrap().banks[1].burst, Burst::Write);
+        assert_eq!(
+            cycles.last().unwrap().banks[1].burst_remaining,
+            BURST_LEN - 1
+        );
+
+        Ok(())
+    }
+
+    // A write, a partially masked write over it and a read back, with `NaiveController`
+    fn naive_controller_cycles() -> io::Result<Vec<TraceCycle>> {
+        let sink = MemorySink::new();
+        let mut c = NaiveController::new(Sdram::with_trace_sink(Some(Box::new(sink.clone()))));
+        c.execute(naive_controller::Command::Write {
+            addr: 0x12345,
+            data: 0xfadebabedeadbeefabad1deacafef00d,
+            mask: 0,
+        })?;
+        c.execute(naive_controller::Command::Write {
+            addr: 0x12345,
+            data: 0x0123456789abcdef0123456789abcdef,
+            mask: 0x0006,
+        })?;
+        c.execute(naive_controller::Command::Read { addr: 0x12345 })?;
+        Ok(sink.cycles())
+    }
+
+    #[test]
+    fn text_log() -> io::Result<()> {
+        let mut log = Vec::new();
+        {
+            let mut sink = TextLogSink::new(&mut log);
+            for cycle in naive_controller_cycles()? {
+                sink.cycle(&cycle)?;
+            }
+        }
+        assert_eq!(
+            String::from_utf8(log).unwrap().lines().collect::<Vec<_>>(),
+            [
+                "         0  Active       bank0  row 0x0246",
+                "         3  Write        bank0  col 0x228  f00d cafe 1dea abad beef dead babe fade",
+                "        13  Precharge    bank0",
+                "        16  Active       bank0  row 0x0246",
+                "        19  Write        bank0  col 0x228  --ef 89-- 4567 0123 cdef 89ab 4567 0123",
+                "        29  Precharge    bank0",
+                "        32  Active       bank0  row 0x0246",
+                "        35  Read         bank0  col 0x228  f0ef 89fe 4567 0123 cdef 89ab 4567 0123",
+                "        46  Precharge    bank0",
+            ]
+        );
+
+        // Violations get a line even on NOPs, and bursts cut short show what data there was
+        let memory = MemorySink::new();
+        let mut sdram = Sdram::with_trace_sink(Some(Box::new(memory.clone())));
+        run(&mut sdram)?;
+        sdram.clk(&mut Io::new())?;
+        let mut log = Vec::new();
+        {
+            let mut sink = TextLogSink::new(&mut log);
+            for cycle in memory.cycles() {
+                sink.cycle(&cycle)?;
+            }
+        }
+        assert_eq!(
+            String::from_utf8(log).unwrap().lines().collect::<Vec<_>>(),
+            [
+                "         0  Active       bank0  row 0x1234",
+                "         1  Active       bank1  row 0x1234  ! tRRD:bank1",
+                "         5  Write        bank1  col 0x008  beef beef ----",
+                "         7  Nop                             ! missing_write_data:bank1",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn dram_power() -> io::Result<()> {
+        let mut trace = Vec::new();
+        {
+            let mut sink = DramPowerSink::new(&mut trace);
+            for cycle in naive_controller_cycles()? {
+                sink.cycle(&cycle)?;
+            }
+        }
+        assert_eq!(
+            String::from_utf8(trace)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "0,ACT,0", "3,WR,0", "13,PRE,0", "16,ACT,0", "19,WR,0", "29,PRE,0", "32,ACT,0",
+                "35,RD,0", "46,PRE,0", "49,END,0",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn dram_power_all_bank_commands() -> io::Result<()> {
+        let memory = MemorySink::new();
+        let mut sdram = Sdram::with_trace_sink(Some(Box::new(memory.clone())));
+        sdram.set_panic_on_violation(false);
+
+        // TODO: Initialization
+
+        let mut io = Io::new();
+        for (command, cke, bank, a) in [
+            (Command::LoadModeRegister, true, IoBank::Bank0, 0x0033),
+            (Command::Precharge, true, IoBank::Bank1, A_10_MASK as u16),
+            (Command::AutoRefresh, true, IoBank::Bank2, 0),
+            (Command::AutoRefresh, false, IoBank::Bank3, 0),
+            (Command::Nop, false, IoBank::Bank0, 0),
+            (Command::Nop, true, IoBank::Bank0, 0),
+            (Command::Active, true, IoBank::Bank3, 0),
+        ] {
+            io.command = command;
+            io.cke = cke;
+            io.bank = bank;
+            io.a = a;
+            sdram.clk(&mut io)?;
+        }
+
+        let mut trace = Vec::new();
+        {
+            let mut sink = DramPowerSink::new(&mut trace);
+            for cycle in memory.cycles() {
+                sink.cycle(&cycle)?;
+            }
+        }
+        // No MRS, and the all-bank commands go to bank 0
+        assert_eq!(
+            String::from_utf8(trace)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            ["1,PREA,0", "2,REF,0", "3,SREN,0", "5,SREX,0", "6,ACT,3", "7,END,0"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn command_log() -> io::Result<()> {
+        let file_name = "vcd/Trace__command_log.bin";
+        {
+            let mut sdram =
+                Sdram::with_trace_sink(Some(Box::new(CommandLogSink::create(file_name)?)));
+            run(&mut sdram)?;
+        }
+
+        let write_cycle = 2 + sdram::T_RCD_CYCLES as u64;
+        assert_eq!(
+            read_command_log(io::BufReader::new(fs::File::open(file_name)?))?,
+            [
+                LoggedCommand {
+                    cycle: 0,
+                    command: Command::Active,
+                    cke: true,
+                    bank: IoBank::Bank0,
+                    a: 0x1234,
+                },
+                LoggedCommand {
+                    cycle: 1,
+                    command: Command::Active,
+                    cke: true,
+                    bank: IoBank::Bank1,
+                    a: 0x1234,
+                },
+                LoggedCommand {
+                    cycle: write_cycle,
+                    command: Command::Write,
+                    cke: true,
+                    bank: IoBank::Bank1,
+                    a: 8,
+                },
+            ]
+        );
+        // Magic, then 4 bytes for each ACT and 3 for the WRITE
+        assert_eq!(fs::metadata(file_name)?.len(), 8 + 4 + 4 + 3);
+
+        assert_eq!(
+            read_command_log(&b"DRAMCMD2"[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        Ok(())
+    }
+
+    // FST traces have the same signals and changes as VCD ones, just with times in ps
+    #[test]
+    fn fst_matches_vcd() -> io::Result<()> {
+        let options = TraceOptions {
+            pins: true,
+            bank_state: true,
+        };
+        let vcd_file_name = "vcd/Trace__fst_matches_vcd.vcd";
+        let fst_file_name = "vcd/Trace__fst_matches_vcd.fst";
+        {
+            let mut sdram =
+                Sdram::with_trace_sink(Some(Box::new(VcdSink::create(vcd_file_name, options)?)));
+            run(&mut sdram)?;
+            let mut sdram =
+                Sdram::with_trace_sink(Some(Box::new(FstSink::create(fst_file_name, options)?)));
+            run(&mut sdram)?;
+        }
+
+        let mut reader =
+            FstReader::open(io::BufReader::new(fs::File::open(fst_file_name)?)).unwrap();
+        let mut paths = Vec::new();
+        let mut scopes = Vec::new();
+        reader
+            .read_hierarchy(|entry| match entry {
+                FstHierarchyEntry::Scope { name, .. } => scopes.push(name),
+                FstHierarchyEntry::UpScope => {
+                    scopes.pop();
+                }
+                FstHierarchyEntry::Var { name, .. } => {
+                    let mut path = scopes.clone();
+                    path.push(name);
+                    paths.push(path);
+                }
+                _ => (),
+            })
+            .unwrap();
+        let mut fst_changes = vec![Vec::new(); paths.len()];
+        reader
+            .read_signals(&FstFilter::all(), |time, handle, value| {
+                if let FstSignalValue::String(value) = value {
+                    fst_changes[handle.get_index()]
+                        .push((time, String::from_utf8(value.to_vec()).unwrap()));
+                }
+            })
+            .unwrap();
+
+        let file = fs::File::open(vcd_file_name)?;
+        let mut parser = vcd::Parser::new(io::BufReader::new(file));
+        let header = parser.parse_header()?;
+        let codes = paths
+            .iter()
+            .map(|path| header.find_var(path).unwrap().code)
+            .collect::<Vec<_>>();
+        let mut vcd_changes = vec![Vec::new(); paths.len()];
+        let mut time_stamp = 0;
+        for command in parser {
+            let (id, value) = match command? {
+                vcd::Command::Timestamp(t) => {
+                    time_stamp = t * FST_HALF_PERIOD_PS;
+                    continue;
+                }
+                vcd::Command::ChangeScalar(id, value) => (id, value.to_string()),
+                vcd::Command::ChangeVector(id, value) => {
+                    (id, value.iter().map(|bit| bit.to_string()).collect())
+                }
+                vcd::Command::ChangeString(id, value) => (id, value),
+                _ => continue,
+            };
+            let index = codes.iter().position(|code| *code == id).unwrap();
+            vcd_changes[index].push((time_stamp, value));
+        }
+
+        assert_eq!(paths.len(), 8 + 5 + 2 + 4 * 8);
+        assert_eq!(fst_changes, vcd_changes);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+// Synthetic request streams for evaluating controllers. A `Traffic` is an endless iterator of
+//  `workload::Access`es (use `take` to bound it), so it can be fed straight to
+//  `workload::replay`. Streams are fully determined by their pattern, settings and seed.
+//
+// All addresses are offsets into the traffic's region (the whole device by default), wrapping
+//  around at its end. Narrowing the region makes it cheap to initialize beforehand with a write
+//  only `Sequential` stream, since reading uninitialized columns is a violation.
+
+use crate::naive_controller::{Command, NUM_ROW_WORDS, NUM_WORD_ADDR_BITS};
+use crate::rng::Rng;
+use crate::workload::Access;
+
+use std::ops::Range;
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Sequential,
+    Strided {
+        stride: u32,
+    },
+    RandomUniform,
+    // `hot_probability` of accesses go to the first `num_hot_words` words; the rest are spread
+    //  uniformly over the whole region
+    HotSpot {
+        num_hot_words: u32,
+        hot_probability: f64,
+    },
+    // Each access is to a random column in the row after the previous access's, ie. in the same
+    //  bank but always in a different row
+    RowConflict,
+    // Reads `src..src + num_words` and writes `dst..dst + num_words`, alternating word by word,
+    //  over and over
+    Memcpy {
+        src: u32,
+        dst: u32,
+        num_words: u32,
+    },
+    // Display scanout: reads `line_words` words starting every `stride_words`, for `num_lines`
+    //  lines from `base`, frame after frame
+    Framebuffer {
+        base: u32,
+        line_words: u32,
+        stride_words: u32,
+        num_lines: u32,
+    },
+}
+
+pub struct Traffic {
+    pattern: Pattern,
+    region: Range<u32>,
+    read_probability: f64,
+    interval: Option<u64>,
+
+    rng: Rng,
+    index: u64,
+}
+
+impl Traffic {
+    pub fn new(pattern: Pattern, seed: u64) -> Traffic {
+        match pattern {
+            Pattern::HotSpot { num_hot_words, .. } => {
+                assert!(num_hot_words > 0, "HotSpot needs at least one hot word.");
+            }
+            Pattern::Memcpy { num_words, .. } => {
+                assert!(num_words > 0, "Memcpy needs at least one word.");
+            }
+            Pattern::Framebuffer {
+                line_words,
+                num_lines,
+                ..
+            } => {
+                assert!(
+                    line_words > 0 && num_lines > 0,
+                    "Framebuffer needs at least one line of at least one word."
+                );
+            }
+            _ => (),
+        }
+
+        Traffic {
+            pattern,
+            region: 0..1 << NUM_WORD_ADDR_BITS,
+            read_probability: 0.5,
+            interval: None,
+
+            rng: Rng::new(seed),
+            index: 0,
+        }
+    }
+
+    // Word addresses the traffic is confined to
+    pub fn set_region(&mut self, region: Range<u32>) {
+        assert!(!region.is_empty(), "Region must not be empty.");
+        self.region = region;
+    }
+
+    // Fraction of reads, for patterns which don't imply a direction (ie. not `Memcpy` or
+    //  `Framebuffer`); the rest are writes of random data. Defaults to 0.5.
+    pub fn set_read_probability(&mut self, read_probability: f64) {
+        self.read_probability = read_probability;
+    }
+
+    // Cycles between successive arrivals, or `None` (the default) to issue back to back
+    pub fn set_interval(&mut self, interval: Option<u64>) {
+        self.interval = interval;
+    }
+
+    fn region_len(&self) -> u64 {
+        (self.region.end - self.region.start) as u64
+    }
+
+    fn random_offset(&mut self) -> u64 {
+        self.rng.below(self.region_len())
+    }
+
+    // Offset into the region and whether the access is a write, or `None` if that's up to
+    //  `read_probability`
+    fn next_offset(&mut self) -> (u64, Option<bool>) {
+        let index = self.index;
+        match self.pattern {
+            Pattern::Sequential => (index, None),
+            Pattern::Strided { stride } => (index * stride as u64, None),
+            Pattern::RandomUniform => (self.random_offset(), None),
+            Pattern::HotSpot {
+                num_hot_words,
+                hot_probability,
+            } => {
+                let offset = if self.rng.chance(hot_probability) {
+                    self.rng.below(num_hot_words as _)
+                } else {
+                    self.random_offset()
+                };
+                (offset, None)
+            }
+            Pattern::RowConflict => (
+                index * NUM_ROW_WORDS as u64 + self.rng.below(NUM_ROW_WORDS as _),
+                None,
+            ),
+            Pattern::Memcpy {
+                src,
+                dst,
+                num_words,
+            } => {
+                let word = index / 2 % num_words as u64;
+                let (base, is_write) = if index.is_multiple_of(2) {
+                    (src, false)
+                } else {
+                    (dst, true)
+                };
+                (base as u64 + word, Some(is_write))
+            }
+            Pattern::Framebuffer {
+                base,
+                line_words,
+                stride_words,
+                num_lines,
+            } => {
+                let word = index % (line_words as u64 * num_lines as u64);
+                let line = word / line_words as u64;
+                let offset = base as u64 + line * stride_words as u64 + word % line_words as u64;
+                (offset, Some(false))
+            }
+        }
+    }
+}
+
+impl Iterator for Traffic {
+    type Item = Access;
+
+    fn next(&mut self) -> Option<Access> {
+        let (offset, is_write) = self.next_offset();
+        let addr = self.region.start + (offset % self.region_len()) as u32;
+        let is_write = is_write.unwrap_or_else(|| !self.rng.chance(self.read_probability));
+        let cycle = self.interval.map(|interval| self.index * interval);
+        self.index += 1;
+
+        Some(Access {
+            cycle,
+            command: if is_write {
+                Command::Write {
+                    addr,
+                    data: self.rng.next_u128(),
+                    mask: 0,
+                }
+            } else {
+                Command::Read { addr }
+            },
+            expected_data: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::NaiveController;
+    use crate::sdram;
+    use crate::workload;
+
+    use std::io;
+
+    fn addrs(traffic: Traffic, num_accesses: usize) -> Vec<u32> {
+        traffic
+            .take(num_accesses)
+            .map(|access| match access.command {
+                Command::Write { addr, .. } | Command::Read { addr } => addr,
+            })
+            .collect()
+    }
+
+    fn bank_and_row(addr: u32) -> (u32, u32) {
+        let row_addr = addr / NUM_ROW_WORDS;
+        (
+            row_addr >> sdram::NUM_ROW_ADDR_BITS,
+            row_addr & sdram::ROW_ADDR_MASK,
+        )
+    }
+
+    #[test]
+    fn seeded() {
+        let a = Traffic::new(Pattern::RandomUniform, 1)
+            .take(100)
+            .collect::<Vec<_>>();
+        let b = Traffic::new(Pattern::RandomUniform, 1)
+            .take(100)
+            .collect::<Vec<_>>();
+        let c = Traffic::new(Pattern::RandomUniform, 2)
+            .take(100)
+            .collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn patterns() {
+        let mut traffic = Traffic::new(Pattern::Sequential, 0);
+        traffic.set_region(10..13);
+        assert_eq!(addrs(traffic, 5), [10, 11, 12, 10, 11]);
+
+        let mut traffic = Traffic::new(Pattern::Strided { stride: 3 }, 0);
+        traffic.set_region(100..108);
+        assert_eq!(addrs(traffic, 5), [100, 103, 106, 101, 104]);
+
+        let mut traffic = Traffic::new(Pattern::RandomUniform, 0);
+        traffic.set_region(50..60);
+        assert!(addrs(traffic, 1000)
+            .iter()
+            .all(|addr| (50..60).contains(addr)));
+
+        let traffic = Traffic::new(
+            Pattern::HotSpot {
+                num_hot_words: 16,
+                hot_probability: 0.9,
+            },
+            0,
+        );
+        let num_hot = addrs(traffic, 1000)
+            .iter()
+            .filter(|&&addr| addr < 16)
+            .count();
+        assert!((850..950).contains(&num_hot), "{} hot accesses", num_hot);
+
+        let mut prev = None;
+        for addr in addrs(Traffic::new(Pattern::RowConflict, 0), 100) {
+            let (bank, row) = bank_and_row(addr);
+            if let Some((prev_bank, prev_row)) = prev {
+                assert_eq!(bank, prev_bank);
+                assert_ne!(row, prev_row);
+            }
+            prev = Some((bank, row));
+        }
+
+        let traffic = Traffic::new(
+            Pattern::Memcpy {
+                src: 0,
+                dst: 1000,
+                num_words: 2,
+            },
+            0,
+        );
+        let accesses = traffic.take(6).collect::<Vec<_>>();
+        assert_eq!(
+            accesses
+                .iter()
+                .map(|access| match access.command {
+                    Command::Write { addr, .. } => (addr, true),
+                    Command::Read { addr } => (addr, false),
+                })
+                .collect::<Vec<_>>(),
+            [
+                (0, false),
+                (1000, true),
+                (1, false),
+                (1001, true),
+                (0, false),
+                (1000, true)
+            ]
+        );
+
+        let traffic = Traffic::new(
+            Pattern::Framebuffer {
+                base: 8,
+                line_words: 2,
+                stride_words: 10,
+                num_lines: 2,
+            },
+            0,
+        );
+        assert_eq!(addrs(traffic, 6), [8, 9, 18, 19, 8, 9]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Memcpy needs at least one word.")]
+    fn empty_memcpy() {
+        Traffic::new(
+            Pattern::Memcpy {
+                src: 0,
+                dst: 16,
+                num_words: 0,
+            },
+            38,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Framebuffer needs at least one line of at least one word.")]
+    fn empty_framebuffer() {
+        Traffic::new(
+            Pattern::Framebuffer {
+                base: 0,
+                line_words: 4,
+                stride_words: 8,
+                num_lines: 0,
+            },
+            38,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "HotSpot needs at least one hot word.")]
+    fn empty_hot_spot() {
+        Traffic::new(
+            Pattern::HotSpot {
+                num_hot_words: 0,
+                hot_probability: 0.9,
+            },
+            38,
+        );
+    }
+
+    #[test]
+    fn read_probability_and_interval() {
+        let mut traffic = Traffic::new(Pattern::RandomUniform, 3);
+        traffic.set_read_probability(0.75);
+        traffic.set_interval(Some(20));
+        let accesses = traffic.take(1000).collect::<Vec<_>>();
+
+        let num_reads = accesses
+            .iter()
+            .filter(|access| matches!(access.command, Command::Read { .. }))
+            .count();
+        assert!((700..800).contains(&num_reads), "{} reads", num_reads);
+        assert_eq!(accesses[0].cycle, Some(0));
+        assert_eq!(accesses[999].cycle, Some(999 * 20));
+    }
+
+    #[test]
+    fn naive_controller() -> io::Result<()> {
+        const REGION: Range<u32> = 0x1000..0x1400;
+
+        let mut c = NaiveController::new(sdram::Sdram::new(None)?);
+
+        let mut fill = Traffic::new(Pattern::Sequential, 0);
+        fill.set_region(REGION);
+        fill.set_read_probability(0.0);
+        workload::replay(&mut c, fill.take(REGION.len()))?;
+
+        // Without any row buffer management, every access costs the same no matter the pattern
+        for pattern in [
+            Pattern::Sequential,
+            Pattern::RandomUniform,
+            Pattern::RowConflict,
+        ] {
+            let mut traffic = Traffic::new(pattern, 4);
+            traffic.set_region(REGION);
+            traffic.set_interval(Some(40));
+            let report = workload::replay(&mut c, traffic.take(200))?;
+            assert!(report
+                .latencies
+                .iter()
+                .all(|&latency| latency == 16 || latency == 17));
+            assert!(report.violations.is_empty());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1535 @@
+// Replays a VCD recorded from an SDRAM controller (eg. an HDL simulation) against `Sdram`. The
+//  pins are sampled on every clock edge and fed to `Sdram::clk`, and anything the model objects
+//  to is reported along with the VCD timestamp of the offending edge.
+//
+// Which VCD variables drive which pins is described by a `SignalMap`, usually loaded from TOML:
+//
+//  scope = "tb.dut"
+//  clock_edge = "rising"
+//  time_offset = 0
+//
+//  [signals]
+//  clk = "sdram_clk"
+//  cs_n = { path = "sdram_cs", invert = true }
+//  ba = { bits = ["DRAM_BA[1]", "DRAM_BA[0]"] }
+//  a = { path = "DRAM_ADDR", width = 13 }
+//  dq = "dq_out"
+//  dq_oe = "dq_oe"
+//  dq_in = "dq_in"
+//  dqm = { path = "dq_en", invert = true }
+//
+//  [decode]
+//  "0011" = "active"
+//
+// Pins are named as on the datasheet, so eg. `cs_n` is low when the part is selected; `invert`
+//  is for VCD signals with the opposite polarity. Paths are matched against the end of each
+//  variable's dotted path within `scope`, including any bit select (eg. `DRAM_BA[0]`). `lsb` and
+//  `width` pick a slice out of a wider variable, and `bits` assembles a pin from bit-blasted
+//  scalars, MSB first. Bit 1 of `dqm` is UDQM and bit 0 is LDQM; `ldqm` and `udqm` can be
+//  mapped separately instead.
+//
+// Commands are decoded by looking up CS#, RAS#, CAS#, WE# (or `command_bus`, MSB first) in
+//  `decode`, where `x` matches either level and the most specific match wins. Without a
+//  `decode` table, the datasheet's truth table is used, and an unmapped CS# is taken to be low.
+//  Alternatively, `command` can name a string variable holding `sdram::Command` names, as in
+//  `Sdram`'s own traces.
+//
+// DQ is bidirectional. If `dq_oe` is mapped, `dq` is only driven by the controller while it's
+//  asserted, and read data is checked against `dq_in` if that's mapped too. Otherwise, `dq` is
+//  a shared bus, and on lanes the model is driving, its value is checked against the model's
+//  read data instead of being fed back in as write data.
+//
+// Each edge is sampled using the values that stood just before `time_offset` VCD time units
+//  after it, so with the default offset of 0, signals may change at the same timestamp as the
+//  edge that registered them.
+
+use crate::sdram;
+
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FindingKind {
+    Violation(sdram::Violation),
+    // Control pins were X/Z while CS# was asserted, or didn't match any `decode` entry
+    UnknownCommand,
+    // Decoded, but not supported by the model; treated as a NOP
+    UnsupportedCommand(&'static str),
+    // The model was driving read data while the controller was driving DQ (or DQ was X)
+    BusConflict,
+    ReadDataMismatch { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindingKind::Violation(violation) => {
+                if let Some(bank) = violation.bank {
+                    write!(f, "bank {}: ", bank.index())?;
+                }
+                write!(f, "{}", violation.kind)
+            }
+            FindingKind::UnknownCommand => write!(f, "Unknown command."),
+            FindingKind::UnsupportedCommand(name) => {
+                write!(f, "Unsupported command {} treated as NOP.", name)
+            }
+            FindingKind::BusConflict => write!(f, "DQ bus conflict occurred."),
+            FindingKind::ReadDataMismatch { expected, actual } => write!(
+                f,
+                "Read data mismatch: expected {:#04x}, got {:#04x}.",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    // VCD timestamp of the clock edge
+    pub time: u64,
+    pub cycle: u64,
+    pub kind: FindingKind,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} (cycle {}): {}", self.time, self.cycle, self.kind)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub num_cycles: u64,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockEdge {
+    #[default]
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodedCommand {
+    Nop,
+    Active,
+    Read,
+    Write,
+    Precharge,
+    AutoRefresh,
+    LoadModeRegister,
+    BurstTerminate,
+}
+
+impl DecodedCommand {
+    fn command(&self) -> Result<sdram::Command, FindingKind> {
+        match self {
+            DecodedCommand::Nop => Ok(sdram::Command::Nop),
+            DecodedCommand::Active => Ok(sdram::Command::Active),
+            DecodedCommand::Read => Ok(sdram::Command::Read),
+            DecodedCommand::Write => Ok(sdram::Command::Write),
+            DecodedCommand::Precharge => Ok(sdram::Command::Precharge),
+            DecodedCommand::AutoRefresh => Ok(sdram::Command::AutoRefresh),
+            DecodedCommand::LoadModeRegister => Ok(sdram::Command::LoadModeRegister),
+            DecodedCommand::BurstTerminate => {
+                Err(FindingKind::UnsupportedCommand("BURST TERMINATE"))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "SignalSpecDef")]
+pub struct SignalSpec {
+    pub path: Option<String>,
+    pub bits: Vec<String>,
+    pub invert: bool,
+    pub lsb: u32,
+    pub width: Option<u32>,
+}
+
+impl SignalSpec {
+    pub fn new(path: &str) -> SignalSpec {
+        SignalSpec {
+            path: Some(path.into()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignalSpecTable {
+    path: Option<String>,
+    #[serde(default)]
+    bits: Vec<String>,
+    #[serde(default)]
+    invert: bool,
+    #[serde(default)]
+    lsb: u32,
+    width: Option<u32>,
+}
+
+// Signals can also be given as just a path
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SignalSpecDef {
+    Path(String),
+    Table(SignalSpecTable),
+}
+
+impl From<SignalSpecDef> for SignalSpec {
+    fn from(def: SignalSpecDef) -> SignalSpec {
+        match def {
+            SignalSpecDef::Path(path) => SignalSpec::new(&path),
+            SignalSpecDef::Table(table) => SignalSpec {
+                path: table.path,
+                bits: table.bits,
+                invert: table.invert,
+                lsb: table.lsb,
+                width: table.width,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Signals {
+    pub clk: Option<SignalSpec>,
+    pub cs_n: Option<SignalSpec>,
+    pub ras_n: Option<SignalSpec>,
+    pub cas_n: Option<SignalSpec>,
+    pub we_n: Option<SignalSpec>,
+    pub command_bus: Option<SignalSpec>,
+    pub command: Option<SignalSpec>,
+    pub ba: Option<SignalSpec>,
+    pub a: Option<SignalSpec>,
+    pub dq: Option<SignalSpec>,
+    pub dq_oe: Option<SignalSpec>,
+    pub dq_in: Option<SignalSpec>,
+    pub dqm: Option<SignalSpec>,
+    pub ldqm: Option<SignalSpec>,
+    pub udqm: Option<SignalSpec>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignalMap {
+    pub scope: Option<String>,
+    pub clock_edge: ClockEdge,
+    pub time_offset: i64,
+    pub signals: Signals,
+    // Empty means the datasheet's truth table
+    pub decode: BTreeMap<String, DecodedCommand>,
+}
+
+impl SignalMap {
+    // Pin-level names: clk, cs_n, ras_n, cas_n, we_n, ba, a, dq, dqm
+    pub fn pins() -> SignalMap {
+        SignalMap {
+            signals: Signals {
+                clk: Some(SignalSpec::new("clk")),
+                cs_n: Some(SignalSpec::new("cs_n")),
+                ras_n: Some(SignalSpec::new("ras_n")),
+                cas_n: Some(SignalSpec::new("cas_n")),
+                we_n: Some(SignalSpec::new("we_n")),
+                ba: Some(SignalSpec::new("ba")),
+                a: Some(SignalSpec::new("a")),
+                dq: Some(SignalSpec::new("dq")),
+                dqm: Some(SignalSpec::new("dqm")),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    // Matches the traces written by `Sdram` itself
+    pub fn sdram_trace() -> SignalMap {
+        SignalMap {
+            signals: Signals {
+                clk: Some(SignalSpec::new("clk")),
+                command: Some(SignalSpec::new("command")),
+                ba: Some(SignalSpec::new("bank")),
+                a: Some(SignalSpec::new("a")),
+                dq: Some(SignalSpec::new("dq")),
+                ldqm: Some(SignalSpec::new("ldqm")),
+                udqm: Some(SignalSpec::new("udqm")),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn from_toml(s: &str) -> io::Result<SignalMap> {
+        toml::from_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<SignalMap> {
+        SignalMap::from_toml(&fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pin {
+    Clk,
+    CsN,
+    RasN,
+    CasN,
+    WeN,
+    CommandBus,
+    Command,
+    Ba,
+    A,
+    Dq,
+    DqOe,
+    DqIn,
+    Dqm,
+    Ldqm,
+    Udqm,
+}
+
+const NUM_PINS: usize = 15;
+
+impl Pin {
+    fn name(&self) -> &'static str {
+        match self {
+            Pin::Clk => "clk",
+            Pin::CsN => "cs_n",
+            Pin::RasN => "ras_n",
+            Pin::CasN => "cas_n",
+            Pin::WeN => "we_n",
+            Pin::CommandBus => "command_bus",
+            Pin::Command => "command",
+            Pin::Ba => "ba",
+            Pin::A => "a",
+            Pin::Dq => "dq",
+            Pin::DqOe => "dq_oe",
+            Pin::DqIn => "dq_in",
+            Pin::Dqm => "dqm",
+            Pin::Ldqm => "ldqm",
+            Pin::Udqm => "udqm",
+        }
+    }
+
+    fn max_width(&self) -> u32 {
+        match self {
+            Pin::CommandBus => 8,
+            Pin::Command => u32::MAX,
+            Pin::Ba => sdram::NUM_BANK_ADDR_BITS,
+            Pin::A => 16,
+            Pin::Dq | Pin::DqIn => 16,
+            Pin::DqOe | Pin::Dqm => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Signals {
+    fn specs(&self) -> [(Pin, &Option<SignalSpec>); NUM_PINS] {
+        [
+            (Pin::Clk, &self.clk),
+            (Pin::CsN, &self.cs_n),
+            (Pin::RasN, &self.ras_n),
+            (Pin::CasN, &self.cas_n),
+            (Pin::WeN, &self.we_n),
+            (Pin::CommandBus, &self.command_bus),
+            (Pin::Command, &self.command),
+            (Pin::Ba, &self.ba),
+            (Pin::A, &self.a),
+            (Pin::Dq, &self.dq),
+            (Pin::DqOe, &self.dq_oe),
+            (Pin::DqIn, &self.dq_in),
+            (Pin::Dqm, &self.dqm),
+            (Pin::Ldqm, &self.ldqm),
+            (Pin::Udqm, &self.udqm),
+        ]
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn missing_signal(name: &str) -> io::Error {
+    invalid_data(format!("Missing signal: {}", name))
+}
+
+// Dotted path of each variable, along with any bit select or range
+struct NamedVar<'a> {
+    names: Vec<String>,
+    var: &'a vcd::Var,
+}
+
+fn collect_vars<'a>(items: &'a [vcd::ScopeItem], scope: &str, vars: &mut Vec<NamedVar<'a>>) {
+    for item in items {
+        match item {
+            vcd::ScopeItem::Scope(child) => {
+                let path = format!("{}{}.", scope, child.identifier);
+                collect_vars(&child.children, &path, vars);
+            }
+            vcd::ScopeItem::Var(var) => {
+                let name = format!("{}{}", scope, var.reference);
+                let names = match var.index {
+                    None => vec![name],
+                    Some(index @ vcd::ReferenceIndex::BitSelect(_)) => {
+                        vec![format!("{}{}", name, index)]
+                    }
+                    Some(index @ vcd::ReferenceIndex::Range(..)) => {
+                        vec![format!("{}{}", name, index), name]
+                    }
+                };
+                vars.push(NamedVar { names, var });
+            }
+        }
+    }
+}
+
+fn find_var<'a>(vars: &[NamedVar<'a>], pattern: &str) -> io::Result<&'a vcd::Var> {
+    let suffix = format!(".{}", pattern);
+    let mut found: Option<&NamedVar> = None;
+    for named_var in vars {
+        let is_match = named_var
+            .names
+            .iter()
+            .any(|name| name == pattern || name.ends_with(&suffix));
+        if !is_match {
+            continue;
+        }
+        // Hierarchical dumps often alias the same net in several scopes, which is fine
+        match found {
+            Some(other) if other.var.code != named_var.var.code => {
+                return Err(invalid_data(format!(
+                    "Ambiguous signal {}: {}, {}",
+                    pattern, other.names[0], named_var.names[0]
+                )));
+            }
+            Some(_) => (),
+            None => found = Some(named_var),
+        }
+    }
+    found
+        .map(|named_var| named_var.var)
+        .ok_or_else(|| missing_signal(pattern))
+}
+
+// Bits `lsb..lsb + width` of a variable
+struct Slice {
+    code: vcd::IdCode,
+    size: u32,
+    lsb: u32,
+    width: u32,
+}
+
+struct ResolvedPin {
+    slices: Vec<Slice>,
+    invert: bool,
+    width: u32,
+}
+
+impl ResolvedPin {
+    fn resolve(
+        pin: Pin,
+        spec: &SignalSpec,
+        scope: Option<&str>,
+        vars: &[NamedVar],
+    ) -> io::Result<ResolvedPin> {
+        let pattern = |path: &str| match scope {
+            Some(scope) => format!("{}.{}", scope, path),
+            None => path.to_string(),
+        };
+
+        let slices = match (&spec.path, spec.bits.is_empty()) {
+            (Some(path), true) => {
+                let var = find_var(vars, &pattern(path))?;
+                let width = spec.width.unwrap_or(var.size.saturating_sub(spec.lsb));
+                if spec.lsb + width > var.size {
+                    return Err(invalid_data(format!(
+                        "Signal {} is {} bits wide, but bits {}..{} were selected",
+                        path,
+                        var.size,
+                        spec.lsb,
+                        spec.lsb + width
+                    )));
+                }
+                vec![Slice {
+                    code: var.code,
+                    size: var.size,
+                    lsb: spec.lsb,
+                    width,
+                }]
+            }
+            (None, false) => spec
+                .bits
+                .iter()
+                .map(|path| {
+                    let var = find_var(vars, &pattern(path))?;
+                    Ok(Slice {
+                        code: var.code,
+                        size: var.size,
+                        lsb: 0,
+                        width: 1,
+                    })
+                })
+                .collect::<io::Result<_>>()?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "Signal {} needs exactly one of path or bits",
+                    pin.name()
+                )));
+            }
+        };
+
+        let width = slices.iter().map(|slice| slice.width).sum::<u32>();
+        if pin != Pin::Command && (width == 0 || width > pin.max_width()) {
+            return Err(invalid_data(format!(
+                "Signal {} is {} bits wide, expected at most {}",
+                pin.name(),
+                width,
+                pin.max_width()
+            )));
+        }
+
+        Ok(ResolvedPin {
+            slices,
+            invert: spec.invert,
+            width,
+        })
+    }
+
+    // MSB first
+    fn bits(&self, values: &Values) -> Vec<vcd::Value> {
+        let mut ret = Vec::with_capacity(self.width as usize);
+        for slice in &self.slices {
+            let bits = values.bits(slice.code, slice.size);
+            let end = (slice.size - slice.lsb) as usize;
+            ret.extend_from_slice(&bits[end - slice.width as usize..end]);
+        }
+        if self.invert {
+            for bit in &mut ret {
+                *bit = match *bit {
+                    vcd::Value::V0 => vcd::Value::V1,
+                    vcd::Value::V1 => vcd::Value::V0,
+                    x => x,
+                };
+            }
+        }
+        ret
+    }
+}
+
+fn integer(bits: &[vcd::Value]) -> Option<u32> {
+    bits.iter().try_fold(0, |acc, bit| match bit {
+        vcd::Value::V0 => Some(acc << 1),
+        vcd::Value::V1 => Some((acc << 1) | 1),
+        _ => None,
+    })
+}
+
+fn scalar(bits: &[vcd::Value]) -> Option<bool> {
+    integer(bits).map(|value| value != 0)
+}
+
+// Per lane flags (eg. DQM) are either one bit per lane, LSB first, or a single bit for both
+fn lane_flags(bits: &[vcd::Value]) -> [Option<bool>; 2] {
+    let lsb = scalar(&bits[bits.len() - 1..]);
+    if bits.len() == 1 {
+        [lsb, lsb]
+    } else {
+        [lsb, scalar(&bits[bits.len() - 2..bits.len() - 1])]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lane {
+    Known(u8),
+    Z,
+    X,
+}
+
+// Lanes are LSB first; a bus narrower than 16 bits leaves the high lane floating
+fn lanes(bits: &[vcd::Value]) -> [Lane; 2] {
+    let lane = |bits: &[vcd::Value]| {
+        if bits.iter().all(|&bit| bit == vcd::Value::Z) {
+            return Lane::Z;
+        }
+        integer(bits).map_or(Lane::X, |value| Lane::Known(value as _))
+    };
+    let len = bits.len();
+    let low = &bits[len.saturating_sub(8)..];
+    let high = &bits[len.saturating_sub(16)..len.saturating_sub(8)];
+    [lane(low), lane(high)]
+}
+
+#[derive(Clone, Default)]
+struct Values {
+    vectors: HashMap<vcd::IdCode, Vec<vcd::Value>>,
+    strings: HashMap<vcd::IdCode, String>,
+}
+
+impl Values {
+    // Vectors narrower than their variable are extended as per the VCD spec: with X/Z if the
+    //  leftmost value is X/Z, and with 0 otherwise. Variables which haven't changed yet are X.
+    fn bits(&self, code: vcd::IdCode, size: u32) -> Vec<vcd::Value> {
+        let value = match self.vectors.get(&code) {
+            Some(value) if !value.is_empty() => value,
+            _ => return vec![vcd::Value::X; size as usize],
+        };
+        let extension = match value[0] {
+            vcd::Value::V0 | vcd::Value::V1 => vcd::Value::V0,
+            x => x,
+        };
+        let mut bits = vec![extension; (size as usize).saturating_sub(value.len())];
+        bits.extend_from_slice(&value[value.len().saturating_sub(size as usize)..]);
+        bits
+    }
+}
+
+// Decode table entry; `None` matches either level
+type DecodePattern = Vec<Option<bool>>;
+
+fn parse_decode_pattern(s: &str, width: u32) -> io::Result<DecodePattern> {
+    let pattern = s
+        .chars()
+        .map(|c| match c {
+            '0' => Ok(Some(false)),
+            '1' => Ok(Some(true)),
+            'x' | 'X' => Ok(None),
+            _ => Err(invalid_data(format!("Invalid decode pattern: {}", s))),
+        })
+        .collect::<io::Result<DecodePattern>>()?;
+    if pattern.len() != width as usize {
+        return Err(invalid_data(format!(
+            "Decode pattern {} should be {} bits wide",
+            s, width
+        )));
+    }
+    Ok(pattern)
+}
+
+fn default_decode_table() -> BTreeMap<String, DecodedCommand> {
+    [
+        ("1xxx", DecodedCommand::Nop),
+        ("0111", DecodedCommand::Nop),
+        ("0011", DecodedCommand::Active),
+        ("0101", DecodedCommand::Read),
+        ("0100", DecodedCommand::Write),
+        ("0010", DecodedCommand::Precharge),
+        ("0001", DecodedCommand::AutoRefresh),
+        ("0000", DecodedCommand::LoadModeRegister),
+        ("0110", DecodedCommand::BurstTerminate),
+    ]
+    .into_iter()
+    .map(|(pattern, command)| (pattern.to_string(), command))
+    .collect()
+}
+
+struct Pins {
+    pins: Vec<Option<ResolvedPin>>,
+    decode: Vec<(DecodePattern, DecodedCommand)>,
+}
+
+impl Pins {
+    fn resolve(map: &SignalMap, header: &vcd::Header) -> io::Result<Pins> {
+        let mut vars = Vec::new();
+        collect_vars(&header.items, "", &mut vars);
+
+        let pins = map
+            .signals
+            .specs()
+            .iter()
+            .map(|(pin, spec)| {
+                spec.as_ref()
+                    .map(|spec| ResolvedPin::resolve(*pin, spec, map.scope.as_deref(), &vars))
+                    .transpose()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut ret = Pins {
+            pins,
+            decode: Vec::new(),
+        };
+
+        for pin in [Pin::Clk, Pin::Ba, Pin::A, Pin::Dq] {
+            if !ret.has(pin) {
+                return Err(missing_signal(pin.name()));
+            }
+        }
+
+        let decode_width = if ret.has(Pin::Command) {
+            None
+        } else if let Some(bus) = ret.get(Pin::CommandBus) {
+            Some(bus.width)
+        } else if [Pin::RasN, Pin::CasN, Pin::WeN]
+            .iter()
+            .all(|&pin| ret.has(pin))
+        {
+            Some(4)
+        } else {
+            return Err(missing_signal("ras_n/cas_n/we_n, command_bus or command"));
+        };
+        if let Some(width) = decode_width {
+            let decode = if map.decode.is_empty() {
+                default_decode_table()
+            } else {
+                map.decode.clone()
+            };
+            ret.decode = decode
+                .iter()
+                .map(|(pattern, command)| Ok((parse_decode_pattern(pattern, width)?, *command)))
+                .collect::<io::Result<_>>()?;
+        }
+
+        Ok(ret)
+    }
+
+    fn get(&self, pin: Pin) -> Option<&ResolvedPin> {
+        self.pins[pin as usize].as_ref()
+    }
+
+    fn has(&self, pin: Pin) -> bool {
+        self.get(pin).is_some()
+    }
+
+    fn bits(&self, pin: Pin, values: &Values) -> Option<Vec<vcd::Value>> {
+        self.get(pin).map(|resolved| resolved.bits(values))
+    }
+
+    fn is_tracked(&self, code: vcd::IdCode) -> bool {
+        self.pins
+            .iter()
+            .flatten()
+            .flat_map(|pin| &pin.slices)
+            .any(|slice| slice.code == code)
+    }
+
+    fn decode_command(&self, values: &Values) -> Result<sdram::Command, FindingKind> {
+        if let Some(command) = self.get(Pin::Command) {
+            return match values
+                .strings
+                .get(&command.slices[0].code)
+                .map(|s| s.as_str())
+            {
+                Some("Active") => Ok(sdram::Command::Active),
+                Some("AutoRefresh") => Ok(sdram::Command::AutoRefresh),
+                Some("LoadModeRegister") => Ok(sdram::Command::LoadModeRegister),
+                Some("Precharge") => Ok(sdram::Command::Precharge),
+                Some("Read") => Ok(sdram::Command::Read),
+                Some("Write") => Ok(sdram::Command::Write),
+                Some("Nop") => Ok(sdram::Command::Nop),
+                _ => Err(FindingKind::UnknownCommand),
+            };
+        }
+
+        let bits = if let Some(bits) = self.bits(Pin::CommandBus, values) {
+            bits
+        } else {
+            [Pin::CsN, Pin::RasN, Pin::CasN, Pin::WeN]
+                .iter()
+                .map(|&pin| {
+                    self.bits(pin, values)
+                        .map_or(vcd::Value::V0, |bits| bits[0])
+                })
+                .collect()
+        };
+        let levels = bits
+            .iter()
+            .map(|bit| match bit {
+                vcd::Value::V0 => Some(false),
+                vcd::Value::V1 => Some(true),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // X/Z is fine on pins the matching entry doesn't care about (eg. with CS# high)
+        self.decode
+            .iter()
+            .filter(|(pattern, _)| {
+                pattern
+                    .iter()
+                    .zip(&levels)
+                    .all(|(expected, level)| expected.is_none() || expected == level)
+            })
+            .min_by_key(|(pattern, _)| pattern.iter().filter(|level| level.is_none()).count())
+            .map_or(Err(FindingKind::UnknownCommand), |(_, command)| {
+                command.command()
+            })
+    }
+}
+
+struct Replayer {
+    pins: Pins,
+    clock_edge: ClockEdge,
+    time_offset: i64,
+
+    // Values as they stood from each timestamp on, going back far enough to cover any
+    //  pending samples
+    history: VecDeque<(u64, Values)>,
+    // Edge times along with the times they're sampled at
+    pending_samples: VecDeque<(u64, u64)>,
+
+    sdram: sdram::Sdram,
+    io: sdram::Io,
+    findings: Vec<Finding>,
+}
+
+impl Replayer {
+    fn clk(&self, values: &Values) -> Option<bool> {
+        scalar(&self.pins.bits(Pin::Clk, values).unwrap())
+    }
+
+    // Called with the values at `time` once all of that timestamp's changes are in
+    fn finish_timestamp(&mut self, time: u64, values: &Values) {
+        let previous_clk = self.history.back().and_then(|(_, values)| self.clk(values));
+        let is_edge = matches!(
+            (previous_clk, self.clk(values), self.clock_edge),
+            (Some(false), Some(true), ClockEdge::Rising)
+                | (Some(true), Some(false), ClockEdge::Falling)
+        );
+        if is_edge {
+            let sample_time = time.saturating_add_signed(self.time_offset);
+            self.pending_samples.push_back((time, sample_time));
+        }
+        self.history.push_back((time, values.clone()));
+    }
+
+    // Takes all samples before `next_time`, the next timestamp with any changes
+    fn take_samples(&mut self, next_time: u64) -> io::Result<()> {
+        while let Some(&(time, sample_time)) = self.pending_samples.front() {
+            if sample_time > next_time {
+                break;
+            }
+            self.pending_samples.pop_front();
+
+            let values = self
+                .history
+                .iter()
+                .rev()
+                .find(|(time, _)| *time < sample_time)
+                .map(|(_, values)| values.clone())
+                .unwrap_or_default();
+            self.edge(time, &values)?;
+        }
+
+        // Any later edge is sampled after this bound, so older entries aren't needed anymore
+        let latest_time = self.history.back().map_or(0, |(time, _)| *time);
+        let bound = self
+            .pending_samples
+            .front()
+            .map_or(u64::MAX, |(_, sample_time)| *sample_time)
+            .min(latest_time.saturating_add_signed(self.time_offset));
+        while self.history.len() >= 2 && self.history[1].0 < bound {
+            self.history.pop_front();
+        }
+
+        Ok(())
+    }
+
+    fn edge(&mut self, time: u64, values: &Values) -> io::Result<()> {
+        let cycle = self.sdram.cycle();
+        let push = |findings: &mut Vec<Finding>, kind| findings.push(Finding { time, cycle, kind });
+
+        self.io.command = match self.pins.decode_command(values) {
+            Ok(command) => command,
+            Err(kind) => {
+                push(&mut self.findings, kind);
+                sdram::Command::Nop
+            }
+        };
+        self.io.bank = self
+            .pins
+            .bits(Pin::Ba, values)
+            .and_then(|bits| integer(&bits))
+            .and_then(|bank| sdram::IoBank::from_index(bank as _))
+            .unwrap_or(sdram::IoBank::Bank0);
+        self.io.a = self
+            .pins
+            .bits(Pin::A, values)
+            .and_then(|bits| integer(&bits))
+            .unwrap_or(0) as _;
+        let [ldqm, udqm] = if let Some(bits) = self.pins.bits(Pin::Dqm, values) {
+            lane_flags(&bits)
+        } else {
+            [Pin::Ldqm, Pin::Udqm]
+                .map(|pin| self.pins.bits(pin, values).and_then(|bits| scalar(&bits)))
+        };
+        self.io.ldqm = ldqm.unwrap_or(false);
+        self.io.udqm = udqm.unwrap_or(false);
+
+        let dq = lanes(&self.pins.bits(Pin::Dq, values).unwrap());
+        let dq_in = self.pins.bits(Pin::DqIn, values).map(|bits| lanes(&bits));
+        let dq_oe = self
+            .pins
+            .bits(Pin::DqOe, values)
+            .map(|bits| lane_flags(&bits));
+        let dq_out = self.io.dq_out();
+        let mut lane_in = |lane: usize, out: Option<u8>| {
+            // What the controller drives, and what it sees
+            let (driven, observed) = match dq_oe {
+                Some(dq_oe) => match dq_oe[lane] {
+                    Some(true) => (dq[lane], None),
+                    Some(false) => (
+                        Lane::Z,
+                        dq_in.map_or(Some(dq[lane]), |dq_in| Some(dq_in[lane])),
+                    ),
+                    None => (Lane::X, None),
+                },
+                None => (dq[lane], Some(dq[lane])),
+            };
+            match (out, driven, observed) {
+                (Some(expected), _, Some(Lane::Known(actual))) => {
+                    if actual != expected {
+                        push(
+                            &mut self.findings,
+                            FindingKind::ReadDataMismatch { expected, actual },
+                        );
+                    }
+                    None
+                }
+                (Some(_), Lane::Z, _) => None,
+                (Some(_), _, _) => {
+                    push(&mut self.findings, FindingKind::BusConflict);
+                    None
+                }
+                (None, Lane::Known(value), _) => Some(value),
+                (None, _, _) => None,
+            }
+        };
+        self.io.dq_in = sdram::OptionalBytePair {
+            low: lane_in(0, dq_out.low),
+            high: lane_in(1, dq_out.high),
+        };
+
+        self.sdram.clk(&mut self.io)?;
+
+        for violation in self.sdram.take_violations() {
+            push(&mut self.findings, FindingKind::Violation(violation));
+        }
+
+        Ok(())
+    }
+}
+
+// Without a `map`, `SignalMap::sdram_trace` is used if the VCD has a `command` variable, and
+//  `SignalMap::pins` otherwise. `trace_file_name_prefix` works the same as for `Sdram::new`, so
+//  the model's view of the run can be traced as well.
+pub fn replay<R: io::Read>(
+    r: R,
+    map: Option<&SignalMap>,
+    trace_file_name_prefix: Option<&str>,
+) -> io::Result<Report> {
+    let mut parser = vcd::Parser::new(r);
+    let header = parser.parse_header()?;
+
+    let default_map;
+    let map = match map {
+        Some(map) => map,
+        None => {
+            let mut vars = Vec::new();
+            collect_vars(&header.items, "", &mut vars);
+            let has_command = vars
+                .iter()
+                .any(|named_var| named_var.var.reference == "command");
+            default_map = if has_command {
+                SignalMap::sdram_trace()
+            } else {
+                SignalMap::pins()
+            };
+            &default_map
+        }
+    };
+
+    let mut sdram = sdram::Sdram::new(trace_file_name_prefix)?;
+    sdram.set_panic_on_violation(false);
+    let mut replayer = Replayer {
+        pins: Pins::resolve(map, &header)?,
+        clock_edge: map.clock_edge,
+        time_offset: map.time_offset,
+
+        history: VecDeque::new(),
+        pending_samples: VecDeque::new(),
+
+        sdram,
+        io: sdram::Io::new(),
+        findings: Vec::new(),
+    };
+
+    let mut values = Values::default();
+    let mut time = None;
+    for command in parser {
+        match command? {
+            vcd::Command::Timestamp(next_time) => {
+                if let Some(time) = time {
+                    replayer.finish_timestamp(time, &values);
+                }
+                replayer.take_samples(next_time)?;
+                time = Some(next_time);
+            }
+            vcd::Command::ChangeScalar(id, value) if replayer.pins.is_tracked(id) => {
+                values.vectors.insert(id, vec![value]);
+            }
+            vcd::Command::ChangeVector(id, value) if replayer.pins.is_tracked(id) => {
+                values.vectors.insert(id, value);
+            }
+            vcd::Command::ChangeString(id, value) if replayer.pins.is_tracked(id) => {
+                values.strings.insert(id, value);
+            }
+            _ => (),
+        }
+    }
+    replayer.finish_timestamp(time.unwrap_or(0), &values);
+    replayer.take_samples(u64::MAX)?;
+
+    Ok(Report {
+        num_cycles: replayer.sdram.cycle(),
+        findings: replayer.findings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::{Command, NaiveController};
+    use crate::trace;
+
+    use std::fs;
+
+    const HEADER: &str = "
+$timescale 1ns $end
+$scope module tb $end
+$scope module sdram $end
+$var wire 1 c clk $end
+$var wire 1 s cs_n $end
+$var wire 1 r ras_n $end
+$var wire 1 k cas_n $end
+$var wire 1 w we_n $end
+$var wire 2 b ba $end
+$var wire 13 a a $end
+$var wire 16 d dq $end
+$var wire 2 m dqm $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+";
+
+    struct Cycle {
+        // CS#, RAS#, CAS#, WE#
+        pins: &'static str,
+        a: u16,
+        dq: String,
+        // Only used with `CUSTOM_HEADER`, where DQ has a separate output enable and input
+        dq_in: String,
+    }
+
+    impl Cycle {
+        fn new(pins: &'static str) -> Cycle {
+            Cycle {
+                pins,
+                a: 0,
+                dq: "z".into(),
+                dq_in: "z".into(),
+            }
+        }
+
+        fn dq(self, dq: u16) -> Cycle {
+            Cycle {
+                dq: format!("{:016b}", dq),
+                ..self
+            }
+        }
+
+        fn dq_in(self, dq_in: u16) -> Cycle {
+            Cycle {
+                dq_in: format!("{:016b}", dq_in),
+                ..self
+            }
+        }
+    }
+
+    const ACT: &str = "0011";
+    const NOP: &str = "0111";
+    const READ: &str = "0101";
+    const WRITE: &str = "0100";
+    const BST: &str = "0110";
+
+    // Cycle `i`'s signals change at time 10i (or at its rising edge at 10i + 5 if `registered`),
+    //  to be sampled by the rising edge at 10i + 15
+    fn vcd(cycles: &[Cycle], registered: bool) -> String {
+        let mut ret = HEADER.to_string();
+        let mut time = 0;
+        ret += "#0\n0c\nb00 b\nb00 m\n";
+        for cycle in cycles {
+            if !registered {
+                time += 5;
+                ret += &format!("#{}\n0c\n", time);
+            }
+            let pins = cycle.pins.as_bytes();
+            for (value, id) in pins.iter().zip(["s", "r", "k", "w"]) {
+                ret += &format!("{}{}\n", *value as char, id);
+            }
+            ret += &format!("b{:b} a\nb{} d\n", cycle.a, cycle.dq);
+            if registered {
+                time += 5;
+                ret += &format!("#{}\n0c\n", time);
+            }
+            time += 5;
+            ret += &format!("#{}\n1c\n", time);
+        }
+        ret + &format!("#{}\n0c\n", time + 5)
+    }
+
+    fn replay_cycles(cycles: &[Cycle], registered: bool) -> io::Result<Vec<Finding>> {
+        Ok(replay(vcd(cycles, registered).as_bytes(), None, None)?.findings)
+    }
+
+    fn violation(finding: &Finding) -> sdram::ViolationKind {
+        match &finding.kind {
+            FindingKind::Violation(violation) => violation.kind,
+            kind => panic!("Expected a violation, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn naive_controller_trace() -> io::Result<()> {
+        {
+            let mut c = NaiveController::new(sdram::Sdram::new(Some(
+                "VcdReplay__naive_controller_trace",
+            ))?);
+
+            for addr in 0..2 {
+                c.execute(Command::Write {
+                    addr,
+                    data: 0xfadebabedeadbeefabad1deacafef00d,
+                    mask: 0,
+                })?;
+                c.execute(Command::Read { addr })?;
+            }
+        }
+
+        let file = fs::File::open("vcd/VcdReplay__naive_controller_trace.vcd")?;
+        let report = replay(io::BufReader::new(file), None, None)?;
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+        assert_eq!(report.num_cycles, 2 * (16 + 17));
+
+        Ok(())
+    }
+
+    #[test]
+    fn naive_controller_pin_trace() -> io::Result<()> {
+        {
+            let mut c = NaiveController::new(sdram::Sdram::with_trace_options(
+                Some("VcdReplay__naive_controller_pin_trace"),
+                trace::TraceOptions {
+                    pins: true,
+                    bank_state: true,
+                },
+            )?);
+
+            c.execute(Command::Write {
+                addr: 0,
+                data: 0xfadebabedeadbeefabad1deacafef00d,
+                mask: 0,
+            })?;
+            c.execute(Command::Read { addr: 0 })?;
+        }
+
+        // Decode the raw pins instead of the command string
+        let map = SignalMap::from_toml(
+            r#"
+[signals]
+clk = "clk"
+cs_n = "cs_n"
+ras_n = "ras_n"
+cas_n = "cas_n"
+we_n = "we_n"
+ba = "sdram.bank"
+a = "a"
+dq = "dq"
+ldqm = "ldqm"
+udqm = "udqm"
+"#,
+        )?;
+        let file = fs::File::open("vcd/VcdReplay__naive_controller_pin_trace.vcd")?;
+        let report = replay(io::BufReader::new(file), Some(&map), None)?;
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+        assert_eq!(report.num_cycles, 16 + 17);
+
+        Ok(())
+    }
+
+    #[test]
+    fn violation_timestamps() -> io::Result<()> {
+        let cycles = [Cycle::new(NOP), Cycle::new(ACT), Cycle::new(READ)];
+        for registered in [false, true] {
+            let findings = replay_cycles(&cycles, registered)?;
+            assert_eq!(findings.len(), 2, "{:?}", findings);
+            assert_eq!(findings[0].time, 30);
+            assert_eq!(findings[0].cycle, 2);
+            assert_eq!(violation(&findings[0]), sdram::ViolationKind::TRcd);
+            assert_eq!(
+                violation(&findings[1]),
+                sdram::ViolationKind::UninitializedRead
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_command() -> io::Result<()> {
+        let findings = replay_cycles(&[Cycle::new(BST), Cycle::new("1000")], false)?;
+        assert_eq!(
+            findings,
+            [Finding {
+                time: 10,
+                cycle: 0,
+                kind: FindingKind::UnsupportedCommand("BURST TERMINATE"),
+            }]
+        );
+
+        let findings = replay_cycles(&[Cycle::new("0x11")], false)?;
+        assert_eq!(findings[0].kind, FindingKind::UnknownCommand);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_data() -> io::Result<()> {
+        let mut cycles = vec![Cycle::new(ACT), Cycle::new(NOP), Cycle::new(NOP)];
+        for i in 0..sdram::BURST_LEN {
+            let pins = if i == 0 { WRITE } else { NOP };
+            cycles.push(Cycle::new(pins).dq(0xbab0 + i as u16));
+        }
+        // Data shows up CAS latency cycles after the READ, on cycle 14
+        cycles.push(Cycle::new(READ));
+        for _ in 0..sdram::CAS_LATENCY - 1 {
+            cycles.push(Cycle::new(NOP));
+        }
+        let mut bus_conflict = Cycle::new(NOP);
+        bus_conflict.dq = "x".into();
+        cycles.push(bus_conflict);
+        cycles.push(Cycle::new(NOP).dq(0xbab0));
+        for i in 2..sdram::BURST_LEN {
+            cycles.push(Cycle::new(NOP).dq(0xbab0 + i as u16));
+        }
+
+        let findings = replay_cycles(&cycles, false)?;
+        assert_eq!(
+            findings,
+            [
+                Finding {
+                    time: 150,
+                    cycle: 14,
+                    kind: FindingKind::BusConflict,
+                },
+                Finding {
+                    time: 150,
+                    cycle: 14,
+                    kind: FindingKind::BusConflict,
+                },
+                Finding {
+                    time: 160,
+                    cycle: 15,
+                    kind: FindingKind::ReadDataMismatch {
+                        expected: 0xb1,
+                        actual: 0xb0,
+                    },
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_signal() {
+        let header = HEADER.replace("$var wire 1 c clk $end\n", "");
+        match replay(header.as_bytes(), None, None) {
+            Err(e) => assert_eq!(e.to_string(), "Missing signal: clk"),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    const CUSTOM_HEADER: &str = "
+$timescale 1ns $end
+$scope module tb $end
+$var wire 1 c clk $end
+$scope module dut $end
+$var wire 1 C clk $end
+$var wire 1 s sdram_cs $end
+$var wire 1 r sdram_ras_n $end
+$var wire 1 k sdram_cas_n $end
+$var wire 1 w sdram_we_n $end
+$var wire 1 0 DRAM_BA [0] $end
+$var wire 1 1 DRAM_BA [1] $end
+$var wire 16 a DRAM_ADDR [15:0] $end
+$var wire 16 d dq_out [15:0] $end
+$var wire 1 e dq_oe $end
+$var wire 16 i dq_in [15:0] $end
+$var wire 2 m dq_en [1:0] $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+";
+
+    const CUSTOM_MAP: &str = r#"
+scope = "tb.dut"
+clock_edge = "falling"
+time_offset = 2
+
+[signals]
+clk = "clk"
+cs_n = { path = "sdram_cs", invert = true }
+ras_n = "sdram_ras_n"
+cas_n = "sdram_cas_n"
+we_n = "sdram_we_n"
+ba = { bits = ["DRAM_BA[1]", "DRAM_BA[0]"] }
+a = { path = "DRAM_ADDR", width = 13 }
+dq = "dq_out"
+dq_oe = "dq_oe"
+dq_in = "dq_in"
+dqm = { path = "dq_en", invert = true }
+"#;
+
+    // Cycle `i` is sampled by the falling edge at 10i + 10, but its signals only change 1ns
+    //  after that edge, so they're only seen thanks to the time offset. The high address bits
+    //  are garbage, and DQ is only driven by the controller when it has data to write.
+    fn custom_vcd(cycles: &[Cycle]) -> String {
+        let mut ret = CUSTOM_HEADER.to_string();
+        ret += "#0\n0c\n0C\n0s\n1r\n1k\n1w\nb00 0\nb00 1\nb11 m\n";
+        for (i, cycle) in cycles.iter().enumerate() {
+            let time = 10 * i as u64;
+            ret += &format!("#{}\n1C\n#{}\n0C\n#{}\n", time + 5, time + 10, time + 11);
+            let cs = if cycle.pins.starts_with('0') {
+                '1'
+            } else {
+                '0'
+            };
+            ret += &format!("{}s\n", cs);
+            for (value, id) in cycle.pins[1..].chars().zip(["r", "k", "w"]) {
+                ret += &format!("{}{}\n", value, id);
+            }
+            let oe = if cycle.dq == "z" { '0' } else { '1' };
+            ret += &format!(
+                "b{:b} a\nb{} d\n{}e\nb{} i\n",
+                0xe000 | cycle.a,
+                cycle.dq,
+                oe,
+                cycle.dq_in
+            );
+        }
+        let time = 10 * cycles.len() as u64;
+        ret + &format!("#{}\n1C\n#{}\n0C\n", time + 5, time + 10)
+    }
+
+    fn replay_custom(cycles: &[Cycle]) -> io::Result<Vec<Finding>> {
+        let map = SignalMap::from_toml(CUSTOM_MAP)?;
+        Ok(replay(custom_vcd(cycles).as_bytes(), Some(&map), None)?.findings)
+    }
+
+    #[test]
+    fn signal_map() -> io::Result<()> {
+        let map = SignalMap::from_toml(CUSTOM_MAP)?;
+        assert_eq!(map.scope.as_deref(), Some("tb.dut"));
+        assert_eq!(map.clock_edge, ClockEdge::Falling);
+        assert_eq!(map.time_offset, 2);
+        assert_eq!(map.signals.clk, Some(SignalSpec::new("clk")));
+        assert_eq!(
+            map.signals.cs_n,
+            Some(SignalSpec {
+                invert: true,
+                ..SignalSpec::new("sdram_cs")
+            })
+        );
+        assert_eq!(map.signals.ba.unwrap().bits, ["DRAM_BA[1]", "DRAM_BA[0]"]);
+        assert_eq!(map.signals.a.unwrap().width, Some(13));
+        assert_eq!(map.signals.command, None);
+        assert!(map.decode.is_empty());
+
+        assert!(SignalMap::from_toml("[signals]\nclk = { name = \"clk\" }").is_err());
+        assert!(SignalMap::from_toml("clock_edge = \"both\"").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_violation_timestamps() -> io::Result<()> {
+        let mut act = Cycle::new(ACT);
+        act.a = 42;
+        // The READ's burst carries on past the end, so only its first cycle is checked
+        let findings = replay_custom(&[Cycle::new("1000"), act, Cycle::new(READ)])?;
+        assert_eq!(findings[0].time, 30);
+        assert_eq!(findings[0].cycle, 2);
+        assert_eq!(violation(&findings[0]), sdram::ViolationKind::TRcd);
+        assert_eq!(
+            violation(&findings[1]),
+            sdram::ViolationKind::UninitializedRead
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_read_data() -> io::Result<()> {
+        let mut cycles = vec![Cycle::new(ACT), Cycle::new(NOP), Cycle::new(NOP)];
+        for i in 0..sdram::BURST_LEN {
+            let pins = if i == 0 { WRITE } else { NOP };
+            cycles.push(Cycle::new(pins).dq(0xbab0 + i as u16));
+        }
+        cycles.push(Cycle::new(READ));
+        for _ in 0..sdram::CAS_LATENCY - 1 {
+            cycles.push(Cycle::new(NOP));
+        }
+        // Driving DQ while the model does is a conflict; a mismatch on `dq_in` isn't
+        cycles.push(Cycle::new(NOP).dq(0xbab0));
+        cycles.push(Cycle::new(NOP).dq_in(0xbab0));
+        for i in 2..sdram::BURST_LEN {
+            cycles.push(Cycle::new(NOP).dq_in(0xbab0 + i as u16));
+        }
+
+        let findings = replay_custom(&cycles)?;
+        assert_eq!(
+            findings,
+            [
+                Finding {
+                    time: 150,
+                    cycle: 14,
+                    kind: FindingKind::BusConflict,
+                },
+                Finding {
+                    time: 150,
+                    cycle: 14,
+                    kind: FindingKind::BusConflict,
+                },
+                Finding {
+                    time: 160,
+                    cycle: 15,
+                    kind: FindingKind::ReadDataMismatch {
+                        expected: 0xb1,
+                        actual: 0xb0,
+                    },
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_time_offset() -> io::Result<()> {
+        // Sampling right at the edge sees each cycle's signals one cycle late, so the READ
+        //  lands on the edge after the one it was meant for
+        let mut map = SignalMap::from_toml(CUSTOM_MAP)?;
+        map.time_offset = 0;
+        let vcd = custom_vcd(&[Cycle::new(ACT), Cycle::new(READ)]);
+        let findings = replay(vcd.as_bytes(), Some(&map), None)?.findings;
+        assert_eq!(findings[0].time, 30);
+        assert_eq!(findings[0].cycle, 2);
+
+        // Sampling before the edge works the same way, as long as it's after the last change
+        map.clock_edge = ClockEdge::Rising;
+        map.time_offset = -1;
+        let findings = replay(vcd.as_bytes(), Some(&map), None)?.findings;
+        assert_eq!(findings[0].time, 25);
+        assert_eq!(findings[0].cycle, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn command_bus() -> io::Result<()> {
+        let vcd = "
+$timescale 1ns $end
+$var wire 1 c clk $end
+$var wire 3 x cmd $end
+$var wire 2 b ba $end
+$var wire 13 a a $end
+$var wire 16 d dq $end
+$enddefinitions $end
+#0
+0c
+b000 b
+b0 a
+bz d
+b001 x
+#5
+1c
+#10
+0c
+b010 x
+#15
+1c
+#20
+0c
+";
+        let map = SignalMap::from_toml(
+            r#"
+[signals]
+clk = "clk"
+command_bus = "cmd"
+ba = "ba"
+a = "a"
+dq = "dq"
+
+[decode]
+"000" = "nop"
+"001" = "active"
+"01x" = "read"
+"#,
+        )?;
+        let findings = replay(vcd.as_bytes(), Some(&map), None)?.findings;
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        assert_eq!(findings[0].time, 15);
+        assert_eq!(violation(&findings[0]), sdram::ViolationKind::TRcd);
+
+        // Decode patterns need to be as wide as the command bus
+        let mut map = map;
+        map.decode.insert("0000".into(), DecodedCommand::Nop);
+        match replay(vcd.as_bytes(), Some(&map), None) {
+            Err(e) => assert_eq!(e.to_string(), "Decode pattern 0000 should be 3 bits wide"),
+            Ok(_) => panic!("Expected an error"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let error = |map: &SignalMap| match replay(custom_vcd(&[]).as_bytes(), Some(map), None) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("Expected an error"),
+        };
+
+        let mut map = SignalMap::from_toml(CUSTOM_MAP).unwrap();
+        map.scope = None;
+        assert_eq!(error(&map), "Ambiguous signal clk: tb.clk, tb.dut.clk");
+
+        let mut map = SignalMap::from_toml(CUSTOM_MAP).unwrap();
+        map.signals.a = Some(SignalSpec::new("DRAM_ADDR"));
+        map.signals.ba = Some(SignalSpec::new("DRAM_ADDR"));
+        assert_eq!(error(&map), "Signal ba is 16 bits wide, expected at most 2");
+
+        let mut map = SignalMap::from_toml(CUSTOM_MAP).unwrap();
+        map.signals.a = Some(SignalSpec {
+            lsb: 4,
+            width: Some(13),
+            ..SignalSpec::new("DRAM_ADDR")
+        });
+        assert_eq!(
+            error(&map),
+            "Signal DRAM_ADDR is 16 bits wide, but bits 4..17 were selected"
+        );
+
+        let mut map = SignalMap::from_toml(CUSTOM_MAP).unwrap();
+        map.signals.dq_in = Some(SignalSpec::new("dq_inn"));
+        assert_eq!(error(&map), "Missing signal: tb.dut.dq_inn");
+    }
+}
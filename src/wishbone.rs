@@ -0,0 +1,300 @@
+// Wishbone B4 pipelined slave with a 32-bit data port and 4-bit byte granularity selects.
+//  ADR is a word address (ie. it addresses 32-bit words, not bytes).
+
+use crate::naive_controller::{Command, NaiveController, NUM_WORD_BITS};
+
+use std::io;
+
+pub const NUM_DATA_BITS: u32 = 32;
+pub const NUM_SEL_BITS: u32 = NUM_DATA_BITS / 8;
+pub const SEL_MASK: u8 = (1 << NUM_SEL_BITS) - 1;
+// Number of bus words in each 128-bit controller word
+pub const NUM_LANES: u32 = NUM_WORD_BITS / NUM_DATA_BITS;
+
+// Bus signals, named from the slave's point of view. Inputs are driven by the master before
+//  each `Slave::clk` call; outputs are updated by the slave at the clock edge.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bus {
+    // Inputs
+    pub cyc: bool,
+    pub stb: bool,
+    pub we: bool,
+    pub sel: u8,
+    pub adr: u32,
+    pub dat_in: u32,
+
+    // Outputs
+    pub ack: bool,
+    pub stall: bool,
+    pub dat_out: u32,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Default::default()
+    }
+}
+
+pub struct Slave {
+    controller: NaiveController,
+
+    // Bus cycles left until the access currently in flight is acknowledged
+    num_busy_cycles: u64,
+    pending_ack: Option<u32>,
+
+    num_cycles: u64,
+}
+
+impl Slave {
+    pub fn new(controller: NaiveController) -> Slave {
+        Slave {
+            controller,
+
+            num_busy_cycles: 0,
+            pending_ack: None,
+
+            num_cycles: 0,
+        }
+    }
+
+    pub fn num_cycles(&self) -> u64 {
+        self.num_cycles
+    }
+
+    pub fn controller(&mut self) -> &mut NaiveController {
+        &mut self.controller
+    }
+
+    // The controller executes each access to completion immediately, after which the slave
+    //  stalls the bus for the same number of cycles the access took before acknowledging it.
+    //  While the bus is idle, the SDRAM is clocked along with it, so both stay in lockstep.
+    pub fn clk(&mut self, bus: &mut Bus) -> io::Result<()> {
+        self.num_cycles += 1;
+
+        bus.ack = false;
+
+        if self.num_busy_cycles > 0 {
+            self.num_busy_cycles -= 1;
+            if self.num_busy_cycles == 0 {
+                let dat_out = self.pending_ack.take().unwrap();
+                // Dropping CYC mid-access aborts the cycle, so there's nothing to acknowledge
+                if bus.cyc {
+                    bus.ack = true;
+                    bus.dat_out = dat_out;
+                }
+                bus.stall = false;
+            }
+            return Ok(());
+        }
+
+        if !(bus.cyc && bus.stb && !bus.stall) {
+            return self.controller.idle(1);
+        }
+
+        let addr = bus.adr / NUM_LANES;
+        let lane = bus.adr % NUM_LANES;
+        let (data, num_cycles) = if bus.we {
+            let sel_mask = ((bus.sel & SEL_MASK) as u16) << (lane * NUM_SEL_BITS);
+            self.controller.execute(Command::Write {
+                addr,
+                data: (bus.dat_in as u128) << (lane * NUM_DATA_BITS),
+                mask: !sel_mask,
+            })?
+        } else {
+            self.controller.execute(Command::Read { addr })?
+        };
+
+        self.num_busy_cycles = num_cycles - 1;
+        let dat_out = data.map_or(0, |data| (data >> (lane * NUM_DATA_BITS)) as u32);
+        if self.num_busy_cycles == 0 {
+            bus.ack = true;
+            bus.dat_out = dat_out;
+        } else {
+            self.pending_ack = Some(dat_out);
+            bus.stall = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sdram;
+
+    fn slave(trace_file_name_prefix: &str) -> Slave {
+        Slave::new(NaiveController::new(
+            sdram::Sdram::new(Some(trace_file_name_prefix)).unwrap(),
+        ))
+    }
+
+    struct Access {
+        we: bool,
+        sel: u8,
+        adr: u32,
+        dat: u32,
+    }
+
+    impl Access {
+        fn write(adr: u32, dat: u32) -> Access {
+            Access {
+                we: true,
+                sel: SEL_MASK,
+                adr,
+                dat,
+            }
+        }
+
+        fn read(adr: u32) -> Access {
+            Access {
+                we: false,
+                sel: SEL_MASK,
+                adr,
+                dat: 0,
+            }
+        }
+    }
+
+    // Minimal pipelined master: issues all accesses in a single bus cycle, presenting the
+    //  next one as soon as STALL is low, and returns the read data for each ACK
+    fn run(s: &mut Slave, accesses: &[Access]) -> io::Result<Vec<u32>> {
+        let mut bus = Bus::new();
+        let mut acks = Vec::new();
+        let mut next = 0;
+
+        bus.cyc = true;
+        while acks.len() < accesses.len() {
+            bus.stb = next < accesses.len();
+            if let Some(access) = accesses.get(next) {
+                bus.we = access.we;
+                bus.sel = access.sel;
+                bus.adr = access.adr;
+                bus.dat_in = access.dat;
+            }
+            let accepted = bus.stb && !bus.stall;
+
+            s.clk(&mut bus)?;
+
+            if accepted {
+                next += 1;
+            }
+            if bus.ack {
+                acks.push(bus.dat_out);
+            }
+        }
+        bus.cyc = false;
+        bus.stb = false;
+        s.clk(&mut bus)?;
+
+        Ok(acks)
+    }
+
+    #[test]
+    fn write_read() -> io::Result<()> {
+        let mut s = slave("Wishbone__write_read");
+
+        let accesses = (0..NUM_LANES * 2)
+            .map(|adr| Access::write(adr, 0xdead0000 | adr))
+            .chain((0..NUM_LANES * 2).map(Access::read))
+            .collect::<Vec<_>>();
+        let acks = run(&mut s, &accesses)?;
+        for adr in 0..NUM_LANES * 2 {
+            assert_eq!(acks[(NUM_LANES * 2 + adr) as usize], 0xdead0000 | adr);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sel() -> io::Result<()> {
+        let mut s = slave("Wishbone__sel");
+
+        let mut accesses = (0..NUM_LANES)
+            .map(|adr| Access::write(adr, 0xfadebabe))
+            .collect::<Vec<_>>();
+        accesses.push(Access {
+            we: true,
+            sel: 0b0101,
+            adr: 1,
+            dat: 0x12345678,
+        });
+        accesses.push(Access {
+            we: true,
+            sel: 0b1000,
+            adr: 2,
+            dat: 0xcafef00d,
+        });
+        accesses.extend((0..NUM_LANES).map(Access::read));
+        let acks = run(&mut s, &accesses)?;
+        assert_eq!(
+            &acks[acks.len() - NUM_LANES as usize..],
+            [0xfadebabe, 0xfa34ba78, 0xcadebabe, 0xfadebabe]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn stall_and_ack_timing() -> io::Result<()> {
+        let mut s = slave("Wishbone__stall_and_ack_timing");
+
+        let mut bus = Bus::new();
+        bus.cyc = true;
+        bus.stb = true;
+        bus.we = true;
+        bus.sel = SEL_MASK;
+        bus.dat_in = 0xabad1dea;
+        s.clk(&mut bus)?;
+        assert!(bus.stall);
+        assert!(!bus.ack);
+
+        // Stalled requests are held, and not accepted again
+        let mut num_cycles = 1;
+        while !bus.ack {
+            assert!(bus.stall);
+            s.clk(&mut bus)?;
+            num_cycles += 1;
+        }
+        assert!(!bus.stall);
+        assert_eq!(num_cycles, s.num_cycles());
+
+        let (_, num_write_cycles) = s.controller().execute(Command::Write {
+            addr: 1,
+            data: 0,
+            mask: 0,
+        })?;
+        assert_eq!(num_cycles, num_write_cycles);
+
+        bus.stb = false;
+        for _ in 0..10 {
+            s.clk(&mut bus)?;
+            assert!(!bus.ack);
+            assert!(!bus.stall);
+        }
+        assert_eq!(s.num_cycles(), num_cycles + 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn abort() -> io::Result<()> {
+        let mut s = slave("Wishbone__abort");
+
+        let mut bus = Bus::new();
+        bus.cyc = true;
+        bus.stb = true;
+        bus.we = true;
+        bus.sel = SEL_MASK;
+        s.clk(&mut bus)?;
+        bus.cyc = false;
+        bus.stb = false;
+        while bus.stall {
+            s.clk(&mut bus)?;
+            assert!(!bus.ack);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+// Memory access traces, which can be replayed through any `Controller` (see the `dramatic-run`
+//  binary). One access per line, in any of these formats:
+//
+//  native:    <cycle> R|W <address> [<data>]
+//  Ramulator: <address> R|W
+//  DRAMSim:   <address> <command> <cycle>
+//
+// Addresses are byte addresses, in hex with a `0x` prefix or decimal. Each access transfers the
+//  128-bit controller word containing its address, and address bits beyond the SDRAM's capacity
+//  are ignored, so traces recorded on machines with more memory still replay. `data` is hex,
+//  and for writes defaults to zero; for reads, it's the data the trace expects back, and any
+//  difference is reported. DRAMSim commands are READ/WRITE (DRAMSim3) or P_MEM_RD, P_MEM_WR,
+//  P_FETCH, P_LOCK_RD and P_LOCK_WR (DRAMSim2). Ramulator traces have no timestamps, so each
+//  access arrives as soon as the controller is done with the previous one.
+//
+// Blank lines and lines starting with `#` are skipped.
+
+use crate::controller::Controller;
+use crate::naive_controller::{Command, NUM_WORD_BYTES, WORD_ADDR_MASK};
+use crate::sdram;
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Native,
+    Ramulator,
+    DramSim,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "native" => Some(Format::Native),
+            "ramulator" => Some(Format::Ramulator),
+            "dramsim" => Some(Format::DramSim),
+            _ => None,
+        }
+    }
+
+    // Guesses the format from a single (non-comment) line
+    pub fn detect(line: &str) -> Format {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields.as_slice() {
+            [_, rw] if is_read_write(rw) => Format::Ramulator,
+            [_, command, _] if dramsim_is_write(command).is_some() => Format::DramSim,
+            _ => Format::Native,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    // Cycle at which the access arrives at the controller, or `None` to arrive as soon as the
+    //  controller is idle
+    pub cycle: Option<u64>,
+    pub command: Command,
+    // For reads, the data the trace expects back
+    pub expected_data: Option<u128>,
+}
+
+impl Access {
+    pub fn read(cycle: Option<u64>, addr: u32) -> Access {
+        Access {
+            cycle,
+            command: Command::Read { addr },
+            expected_data: None,
+        }
+    }
+
+    pub fn write(cycle: Option<u64>, addr: u32, data: u128) -> Access {
+        Access {
+            cycle,
+            command: Command::Write {
+                addr,
+                data,
+                mask: 0,
+            },
+            expected_data: None,
+        }
+    }
+}
+
+// Controller word address of the word containing `byte_addr`
+pub fn word_addr(byte_addr: u64) -> u32 {
+    (byte_addr / NUM_WORD_BYTES as u64) as u32 & WORD_ADDR_MASK
+}
+
+fn is_read_write(s: &str) -> bool {
+    s == "R" || s == "W"
+}
+
+fn dramsim_is_write(command: &str) -> Option<bool> {
+    match command {
+        "READ" | "P_MEM_RD" | "P_FETCH" | "P_LOCK_RD" => Some(false),
+        "WRITE" | "P_MEM_WR" | "P_LOCK_WR" => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid number `{}`", s))
+}
+
+fn parse_data(s: &str) -> Result<u128, String> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u128::from_str_radix(hex, 16).map_err(|_| format!("invalid data `{}`", s))
+}
+
+fn access(cycle: Option<u64>, is_write: bool, addr: u64, data: Option<u128>) -> Access {
+    let addr = word_addr(addr);
+    if is_write {
+        Access::write(cycle, addr, data.unwrap_or(0))
+    } else {
+        Access {
+            expected_data: data,
+            ..Access::read(cycle, addr)
+        }
+    }
+}
+
+pub fn parse_line(format: Format, line: &str) -> Result<Access, String> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    match (format, fields.as_slice()) {
+        (Format::Native, [cycle, rw, addr, data @ ..]) if is_read_write(rw) && data.len() <= 1 => {
+            let data = data.first().map(|data| parse_data(data)).transpose()?;
+            Ok(access(
+                Some(parse_number(cycle)?),
+                *rw == "W",
+                parse_number(addr)?,
+                data,
+            ))
+        }
+        (Format::Native, _) => Err("expected `<cycle> R|W <address> [<data>]`".into()),
+        (Format::Ramulator, [addr, rw]) if is_read_write(rw) => {
+            Ok(access(None, *rw == "W", parse_number(addr)?, None))
+        }
+        (Format::Ramulator, _) => Err("expected `<address> R|W`".into()),
+        (Format::DramSim, [addr, command, cycle]) => {
+            let is_write = dramsim_is_write(command)
+                .ok_or_else(|| format!("unsupported command `{}`", command))?;
+            Ok(access(
+                Some(parse_number(cycle)?),
+                is_write,
+                parse_number(addr)?,
+                None,
+            ))
+        }
+        (Format::DramSim, _) => Err("expected `<address> <command> <cycle>`".into()),
+    }
+}
+
+// Without a `format`, it's detected from the first access
+pub fn parse<R: BufRead>(r: R, format: Option<Format>) -> io::Result<Vec<Access>> {
+    let mut format = format;
+    let mut accesses = Vec::new();
+    for (index, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let format = *format.get_or_insert_with(|| Format::detect(line));
+        let access = parse_line(format, line).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, msg),
+            )
+        })?;
+        accesses.push(access);
+    }
+
+    Ok(accesses)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    // Index of the access in the workload
+    pub index: usize,
+    pub addr: u32,
+    pub expected: u128,
+    pub actual: u128,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "access {}: read of word 0x{:06x} returned 0x{:032x}, expected 0x{:032x}",
+            self.index, self.addr, self.actual, self.expected
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub num_cycles: u64,
+    pub num_reads: u64,
+    pub num_writes: u64,
+    // Cycles from arrival to completion, for each access in workload order
+    pub latencies: Vec<u64>,
+    pub mismatches: Vec<Mismatch>,
+    pub violations: Vec<sdram::Violation>,
+}
+
+impl Report {
+    pub fn num_bytes(&self) -> u64 {
+        (self.num_reads + self.num_writes) * NUM_WORD_BYTES as u64
+    }
+
+    // Bytes per cycle
+    pub fn bandwidth(&self) -> f64 {
+        if self.num_cycles == 0 {
+            return 0.0;
+        }
+
+        self.num_bytes() as f64 / self.num_cycles as f64
+    }
+
+    pub fn bandwidth_mb_per_s(&self) -> f64 {
+        self.bandwidth() / sdram::CLOCK_PERIOD_NS as f64 * 1000.0
+    }
+
+    pub fn average_latency(&self) -> Option<f64> {
+        match self.latencies.len() {
+            0 => None,
+            num_latencies => Some(self.latencies.iter().sum::<u64>() as f64 / num_latencies as f64),
+        }
+    }
+
+    // Nearest-rank percentile, eg. `latency_percentile(99.0)`
+    pub fn latency_percentile(&self, percentile: f64) -> Option<u64> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        let rank = (percentile / 100.0 * latencies.len() as f64).ceil() as usize;
+        latencies
+            .get(rank.clamp(1, latencies.len().max(1)) - 1)
+            .copied()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "cycles: {}", self.num_cycles)?;
+        writeln!(
+            f,
+            "accesses: {} reads, {} writes, {} bytes",
+            self.num_reads,
+            self.num_writes,
+            self.num_bytes()
+        )?;
+        writeln!(
+            f,
+            "bandwidth: {:.3} bytes/cycle ({:.1} MB/s)",
+            self.bandwidth(),
+            self.bandwidth_mb_per_s()
+        )?;
+        if let Some(average_latency) = self.average_latency() {
+            writeln!(
+                f,
+                "latency: average {:.1}, p50 {}, p90 {}, p99 {}, max {} cycles",
+                average_latency,
+                self.latency_percentile(50.0).unwrap(),
+                self.latency_percentile(90.0).unwrap(),
+                self.latency_percentile(99.0).unwrap(),
+                self.latency_percentile(100.0).unwrap()
+            )?;
+        }
+        write!(
+            f,
+            "violations: {}, data mismatches: {}",
+            self.violations.len(),
+            self.mismatches.len()
+        )
+    }
+}
+
+// Executes `accesses` in order, idling the controller until each one arrives. Cycles are
+//  counted from the controller's current cycle. Violations are taken from the SDRAM at the
+//  end, so unless it's set not to panic on them, there won't be any.
+pub fn replay(
+    controller: &mut dyn Controller,
+    accesses: impl IntoIterator<Item = Access>,
+) -> io::Result<Report> {
+    let start_cycle = controller.sdram().cycle();
+    let mut report = Report::default();
+    for (index, access) in accesses.into_iter().enumerate() {
+        let cycle = controller.sdram().cycle() - start_cycle;
+        let arrival_cycle = match access.cycle {
+            Some(arrival_cycle) if arrival_cycle > cycle => {
+                controller.idle(arrival_cycle - cycle)?;
+                arrival_cycle
+            }
+            Some(arrival_cycle) => arrival_cycle,
+            None => cycle,
+        };
+
+        let (data, _) = controller.execute(access.command)?;
+        report
+            .latencies
+            .push(controller.sdram().cycle() - start_cycle - arrival_cycle);
+
+        match access.command {
+            Command::Write { .. } => report.num_writes += 1,
+            Command::Read { addr } => {
+                report.num_reads += 1;
+                if let (Some(expected), Some(actual)) = (access.expected_data, data) {
+                    if expected != actual {
+                        report.mismatches.push(Mismatch {
+                            index,
+                            addr,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    report.num_cycles = controller.sdram().cycle() - start_cycle;
+    report.violations = controller.sdram().take_violations();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::naive_controller::NaiveController;
+
+    #[test]
+    fn parse_formats() -> io::Result<()> {
+        let native = "\
+            # cycle R/W address [data]\n\
+            0 W 0x1230 deadbeef\n\
+            \n\
+            40 R 4656 0xdeadbeef\n\
+            41 R 0x10\n";
+        assert_eq!(
+            parse(native.as_bytes(), None)?,
+            [
+                Access::write(Some(0), 0x123, 0xdeadbeef),
+                Access {
+                    expected_data: Some(0xdeadbeef),
+                    ..Access::read(Some(40), 0x123)
+                },
+                Access::read(Some(41), 1),
+            ]
+        );
+
+        let ramulator = "0x12345680 R\n0x4cbd56c0 W\n";
+        assert_eq!(
+            parse(ramulator.as_bytes(), None)?,
+            [
+                Access::read(None, word_addr(0x12345680)),
+                Access::write(None, word_addr(0x4cbd56c0), 0),
+            ]
+        );
+
+        let dramsim = "0x7f64768badc0 P_MEM_RD 12\n0x20 WRITE 30\n0x40 P_FETCH 31\n";
+        assert_eq!(
+            parse(dramsim.as_bytes(), None)?,
+            [
+                Access::read(Some(12), word_addr(0x7f64768badc0)),
+                Access::write(Some(30), 2, 0),
+                Access::read(Some(31), 4),
+            ]
+        );
+
+        // Explicit formats aren't detected
+        assert!(parse(ramulator.as_bytes(), Some(Format::Native)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let e = parse("0 W 0x10\n1 X 0x10\n".as_bytes(), None).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.to_string(),
+            "line 2: expected `<cycle> R|W <address> [<data>]`"
+        );
+
+        let e = parse("0x10 BOFF 3\n".as_bytes(), Some(Format::DramSim)).unwrap_err();
+        assert_eq!(e.to_string(), "line 1: unsupported command `BOFF`");
+
+        let e = parse("0 R 0xfoo\n".as_bytes(), None).unwrap_err();
+        assert_eq!(e.to_string(), "line 1: invalid number `0xfoo`");
+    }
+
+    #[test]
+    fn replay_naive() -> io::Result<()> {
+        let mut c = NaiveController::new(sdram::Sdram::new(Some("Workload__replay_naive"))?);
+
+        let trace = "\
+            0 W 0x100 fadebabedeadbeefabad1deacafef00d\n\
+            4 R 0x100 fadebabedeadbeefabad1deacafef00d\n\
+            100 R 0x100 0\n";
+        let report = replay(&mut c, parse(trace.as_bytes(), None)?)?;
+
+        // The second access waits for the first to finish, and the third idles until it arrives
+        assert_eq!(report.latencies, [16, 16 + 17 - 4, 17]);
+        assert_eq!(report.num_cycles, 117);
+        assert_eq!((report.num_reads, report.num_writes), (2, 1));
+        assert_eq!(report.num_bytes(), 48);
+        assert_eq!(
+            report.mismatches,
+            [Mismatch {
+                index: 2,
+                addr: 0x10,
+                expected: 0,
+                actual: 0xfadebabedeadbeefabad1deacafef00d,
+            }]
+        );
+        assert!(report.violations.is_empty());
+
+        assert_eq!(report.latency_percentile(50.0), Some(17));
+        assert_eq!(report.latency_percentile(100.0), Some(29));
+        assert_eq!(report.average_latency(), Some(62.0 / 3.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn replay_reports_violations() -> io::Result<()> {
+        let mut sdram = sdram::Sdram::new(None)?;
+        sdram.set_panic_on_violation(false);
+        let mut c = NaiveController::new(sdram);
+
+        let report = replay(&mut c, [Access::read(None, 0)])?;
+
+        assert_eq!(report.latencies, [17]);
+        assert_eq!(report.violations.len(), sdram::BURST_LEN as usize);
+        assert!(report
+            .violations
+            .iter()
+            .all(|violation| violation.kind == sdram::ViolationKind::UninitializedRead));
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_report() {
+        let report = Report::default();
+        assert_eq!(report.bandwidth(), 0.0);
+        assert_eq!(report.average_latency(), None);
+        assert_eq!(report.latency_percentile(99.0), None);
+    }
+}